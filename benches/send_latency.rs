@@ -0,0 +1,152 @@
+//! Send-to-wire latency of `CanSimple::send`, with the bus idle and saturated.
+//!
+//! Latency is measured from the `send` call until a second node sees the frame,
+//! so it includes that node's receive path as well.
+//!
+//! Runs on an in-process `VirtualBus` by default. Set `CAN_BENCH_IFACE` to a
+//! SocketCAN interface (e.g. `vcan0`) to measure the kernel path instead.
+//!
+//!     cargo bench --bench send_latency
+//!     CAN_BENCH_IFACE=vcan0 cargo bench --bench send_latency
+
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+#[cfg(target_os = "linux")]
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "linux")]
+use anyhow::{anyhow, Result};
+#[cfg(target_os = "linux")]
+use tokio_stream::StreamExt;
+
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::connection::CanSimple;
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::myactuator_v3_msgs::{MotorShutdownCommand, ReadMultiTurnAngleMessage};
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::socketcan_transport::SocketCanTransport;
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::transport::CanTransport;
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::virtual_bus::VirtualBus;
+
+#[cfg(target_os = "linux")]
+const SAMPLES: usize = 2000;
+#[cfg(target_os = "linux")]
+const WARMUP: usize = 100;
+#[cfg(target_os = "linux")]
+const REPLY_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[cfg(target_os = "linux")]
+enum Bus {
+    Virtual(VirtualBus),
+    SocketCan(String),
+}
+
+#[cfg(target_os = "linux")]
+impl Bus {
+    fn node(&self) -> Result<CanSimple> {
+        Ok(match self {
+            Bus::Virtual(bus) => CanSimple::with_virtual_bus(bus),
+            Bus::SocketCan(iface) => CanSimple::with_transport(SocketCanTransport::open(iface)?),
+        })
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Bus::Virtual(bus) => format!("virtual bus '{}'", bus.name()),
+            Bus::SocketCan(iface) => format!("SocketCAN interface {}", iface),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn measure(bus: &Bus) -> Result<Vec<Duration>> {
+    let sender = bus.node()?;
+    let observer = bus.node()?;
+    let mut frames = observer.subscribe_node::<ReadMultiTurnAngleMessage>(1);
+    let mut samples = Vec::with_capacity(SAMPLES);
+    for i in 0..WARMUP + SAMPLES {
+        let start = Instant::now();
+        sender.send(ReadMultiTurnAngleMessage::new(1)).await?;
+        tokio::time::timeout(REPLY_TIMEOUT, frames.next())
+            .await
+            .map_err(|_| anyhow!("frame {} never reached the observer", i))?;
+        if i >= WARMUP {
+            samples.push(start.elapsed());
+        }
+    }
+    drop(frames);
+    sender.shutdown().await;
+    observer.shutdown().await;
+    Ok(samples)
+}
+
+/// Floods the bus from a third node until the returned flag is cleared.
+#[cfg(target_os = "linux")]
+fn saturate(bus: &Bus) -> Result<(Arc<AtomicBool>, Arc<AtomicU64>, tokio::task::JoinHandle<()>)> {
+    let flooder = bus.node()?;
+    let running = Arc::new(AtomicBool::new(true));
+    let sent = Arc::new(AtomicU64::new(0));
+    let (flag, count) = (running.clone(), sent.clone());
+    let handle = tokio::spawn(async move {
+        while flag.load(Ordering::Relaxed) {
+            // Node 32 sits at 0x160, a lower priority than the measured frames on 0x141.
+            if flooder.send(MotorShutdownCommand::new(32)).await.is_err() {
+                break;
+            }
+            count.fetch_add(1, Ordering::Relaxed);
+            tokio::task::yield_now().await;
+        }
+        flooder.shutdown().await;
+    });
+    Ok((running, sent, handle))
+}
+
+#[cfg(target_os = "linux")]
+fn report(label: &str, mut samples: Vec<Duration>) {
+    samples.sort();
+    let pct = |p: f64| samples[((samples.len() - 1) as f64 * p).round() as usize];
+    let mean = samples.iter().sum::<Duration>() / samples.len() as u32;
+    println!(
+        "{:<10} n={:<5} min={:>9.1?} p50={:>9.1?} p99={:>9.1?} max={:>9.1?} mean={:>9.1?}",
+        label,
+        samples.len(),
+        samples[0],
+        pct(0.5),
+        pct(0.99),
+        samples[samples.len() - 1],
+        mean
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::main]
+async fn main() -> Result<()> {
+    let bus = match std::env::var("CAN_BENCH_IFACE") {
+        Ok(iface) => Bus::SocketCan(iface),
+        Err(_) => Bus::Virtual(VirtualBus::named("send-latency-bench")),
+    };
+    println!("send-to-wire latency on {}", bus.describe());
+
+    report("idle", measure(&bus).await?);
+
+    let (running, sent, flooder) = saturate(&bus)?;
+    let started = Instant::now();
+    let samples = measure(&bus).await?;
+    running.store(false, Ordering::Relaxed);
+    let _ = flooder.await;
+    report("saturated", samples);
+    println!(
+        "background load: {:.0} frames/s",
+        sent.load(Ordering::Relaxed) as f64 / started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    println!("This benchmark is only supported on Linux platforms with socketcan.");
+}
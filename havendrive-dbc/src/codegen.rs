@@ -0,0 +1,588 @@
+//! Rust source for the messages in a DBC file.
+//!
+//! Each message becomes a struct implementing `CanMessageTrait`, with a field per
+//! signal. Scaled signals are `f64` in physical units and clamped to their range
+//! when encoded, signals with a value table get an enum, and multiplexed
+//! signals are `Option`s that are `Some` when the multiplexor selects them.
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{ByteOrder, Dbc, Message, Multiplex, Signal, ValueType};
+
+const CAN: &str = "::havendrive::drivers::can::messages";
+const DBC: &str = "::havendrive::drivers::can::dbc";
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop",
+    "match", "mod", "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+    "use", "where", "while", "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+    "typeof", "unsized", "virtual", "yield", "try",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    /// Treat the low `n` id bits as the command and the rest as the node id, as
+    /// ODrive does with `node_id << 5 | cmd_id`. Messages then match on the
+    /// command alone and carry the node they came from. Without it, every
+    /// message has one fixed id and node id 0.
+    pub node_id_shift: Option<u32>,
+}
+
+/// Writes the code for `dbc_path` to `out_path`, and tells Cargo to rerun the
+/// build script when the DBC changes. Meant to be called from `build.rs`.
+pub fn generate_file(dbc_path: impl AsRef<Path>, out_path: impl AsRef<Path>, options: &CodegenOptions) -> Result<()> {
+    let (dbc_path, out_path) = (dbc_path.as_ref(), out_path.as_ref());
+    println!("cargo:rerun-if-changed={}", dbc_path.display());
+    let dbc = Dbc::from_file(dbc_path)?;
+    let code = generate(&dbc, options).with_context(|| format!("Failed to generate code for {}", dbc_path.display()))?;
+    fs::write(out_path, code).with_context(|| format!("Failed to write {}", out_path.display()))
+}
+
+pub fn generate(dbc: &Dbc, options: &CodegenOptions) -> Result<String> {
+    let mut out = String::from("// Generated from a DBC file by havendrive-dbc. Do not edit.\n");
+    let mut type_names = HashSet::new();
+    for message in &dbc.messages {
+        // Holds signals not attached to any message.
+        if message.name == "VECTOR__INDEPENDENT_SIG_MSG" {
+            continue;
+        }
+        let name = camel_case(&message.name);
+        if !type_names.insert(name.clone()) {
+            return Err(anyhow!("Two messages are both named {} in Rust", name));
+        }
+        MessageGen::new(message, name, options)?.write(&mut out);
+    }
+    Ok(out)
+}
+
+/// Rust type of a signal's field.
+enum FieldType {
+    Bool,
+    Unsigned(&'static str),
+    Signed(&'static str),
+    Float(&'static str),
+    Enum(String),
+}
+
+impl FieldType {
+    fn name(&self) -> &str {
+        match self {
+            FieldType::Bool => "bool",
+            FieldType::Unsigned(t) | FieldType::Signed(t) | FieldType::Float(t) => t,
+            FieldType::Enum(name) => name,
+        }
+    }
+}
+
+struct Field<'a> {
+    signal: &'a Signal,
+    ident: String,
+    ty: FieldType,
+}
+
+impl Field<'_> {
+    fn byte_order(&self) -> &'static str {
+        match self.signal.byte_order {
+            ByteOrder::LittleEndian => "LittleEndian",
+            ByteOrder::BigEndian => "BigEndian",
+        }
+    }
+
+    fn raw(&self) -> String {
+        format!("{DBC}::signal::extract(&msg.data, {}, {}, {DBC}::ByteOrder::{})", self.signal.start_bit, self.signal.size, self.byte_order())
+    }
+
+    /// The raw value as an integer, sign-extended for signed signals.
+    fn raw_integer(&self, ty: &str) -> String {
+        match self.signal.value_type {
+            ValueType::Signed => format!("{DBC}::signal::sign_extend({}, {}) as {}", self.raw(), self.signal.size, ty),
+            _ => format!("{} as {}", self.raw(), ty),
+        }
+    }
+
+    fn scaling(&self, float: &str) -> String {
+        let mut s = String::new();
+        if self.signal.factor != 1.0 {
+            write!(s, " * {}", float_literal(self.signal.factor, float)).unwrap();
+        }
+        if self.signal.offset < 0.0 {
+            write!(s, " - {}", float_literal(-self.signal.offset, float)).unwrap();
+        } else if self.signal.offset > 0.0 {
+            write!(s, " + {}", float_literal(self.signal.offset, float)).unwrap();
+        }
+        s
+    }
+
+    /// Expression for the field's value, read from `msg`.
+    fn decode(&self) -> String {
+        match &self.ty {
+            FieldType::Bool => format!("{} != 0", self.raw()),
+            FieldType::Unsigned(t) | FieldType::Signed(t) => self.raw_integer(t),
+            FieldType::Enum(name) => format!("{}::try_from({})?", name, self.raw_integer("i64")),
+            FieldType::Float(float) => {
+                let value = match self.signal.value_type {
+                    ValueType::Float32 => format!("f32::from_bits({} as u32)", self.raw()),
+                    ValueType::Float64 => format!("f64::from_bits({})", self.raw()),
+                    _ => self.raw_integer(float),
+                };
+                format!("{}{}", value, self.scaling(float))
+            }
+        }
+    }
+
+    /// Expression for the raw `u64` of `value`.
+    fn encode(&self, value: &str) -> String {
+        match &self.ty {
+            FieldType::Bool | FieldType::Unsigned(_) | FieldType::Signed(_) => format!("{} as u64", value),
+            FieldType::Enum(_) => format!("{} as i64 as u64", value),
+            FieldType::Float(float) => {
+                let mut physical = value.to_string();
+                if self.signal.has_range() {
+                    physical = format!("{}.clamp({}, {})", physical, float_literal(self.signal.min, float), float_literal(self.signal.max, float));
+                }
+                let mut raw = physical;
+                if self.signal.offset < 0.0 {
+                    raw = format!("({} + {})", raw, float_literal(-self.signal.offset, float));
+                } else if self.signal.offset > 0.0 {
+                    raw = format!("({} - {})", raw, float_literal(self.signal.offset, float));
+                }
+                if self.signal.factor != 1.0 {
+                    raw = format!("({} / {})", raw, float_literal(self.signal.factor, float));
+                }
+                match self.signal.value_type {
+                    ValueType::Float32 => format!("{}.to_bits() as u64", raw),
+                    ValueType::Float64 => format!("{}.to_bits()", raw),
+                    ValueType::Signed => format!("{}.round() as i64 as u64", raw),
+                    ValueType::Unsigned => format!("{}.round() as u64", raw),
+                }
+            }
+        }
+    }
+
+    fn insert(&self, value: &str) -> String {
+        format!(
+            "{DBC}::signal::insert(&mut data, {}, {}, {DBC}::ByteOrder::{}, {});",
+            self.signal.start_bit,
+            self.signal.size,
+            self.byte_order(),
+            self.encode(value)
+        )
+    }
+
+    fn default_value(&self) -> String {
+        match &self.ty {
+            _ if matches!(self.signal.multiplex, Multiplex::Multiplexed(_)) => "None".into(),
+            FieldType::Bool => "false".into(),
+            FieldType::Unsigned(_) | FieldType::Signed(_) => "0".into(),
+            FieldType::Float(_) => "0.0".into(),
+            FieldType::Enum(name) => format!("{}::{}", name, variant_names(&self.signal.values)[0]),
+        }
+    }
+
+    fn type_name(&self) -> String {
+        match self.signal.multiplex {
+            Multiplex::Multiplexed(_) => format!("Option<{}>", self.ty.name()),
+            _ => self.ty.name().to_string(),
+        }
+    }
+}
+
+struct MessageGen<'a> {
+    message: &'a Message,
+    name: String,
+    fields: Vec<Field<'a>>,
+    node_id_shift: Option<u32>,
+}
+
+impl<'a> MessageGen<'a> {
+    fn new(message: &'a Message, name: String, options: &CodegenOptions) -> Result<Self> {
+        let mut idents = HashSet::from(["node_id".to_string(), "timestamp".to_string()]);
+        let mut fields = Vec::new();
+        for signal in &message.signals {
+            let mut ident = snake_case(&signal.name);
+            while !idents.insert(ident.clone()) {
+                ident.push('_');
+            }
+            let ty = field_type(signal, &format!("{}{}", name, camel_case(&signal.name)));
+            fields.push(Field { signal, ident, ty });
+        }
+        let multiplexed = message.signals.iter().any(|s| matches!(s.multiplex, Multiplex::Multiplexed(_)));
+        if multiplexed && message.multiplexor().is_none() {
+            return Err(anyhow!("{} has multiplexed signals but no multiplexor", message.name));
+        }
+        Ok(Self { message, name, fields, node_id_shift: options.node_id_shift })
+    }
+
+    fn id(&self) -> u32 {
+        match self.node_id_shift {
+            Some(shift) => self.message.id & ((1 << shift) - 1),
+            None => self.message.id,
+        }
+    }
+
+    fn multiplexor(&self) -> Option<&Field<'a>> {
+        self.fields.iter().find(|f| f.signal.multiplex == Multiplex::Multiplexor)
+    }
+
+    fn write(&self, out: &mut String) {
+        for field in &self.fields {
+            if let FieldType::Enum(name) = &field.ty {
+                write_enum(out, name, &field.signal.values);
+            }
+        }
+        self.write_struct(out);
+        self.write_impl(out);
+    }
+
+    fn write_struct(&self, out: &mut String) {
+        let m = self.message;
+        writeln!(out).unwrap();
+        write_doc(out, "", m.comment.as_deref());
+        writeln!(out, "#[derive(Debug, Clone, PartialEq)]").unwrap();
+        writeln!(out, "pub struct {} {{", self.name).unwrap();
+        writeln!(out, "    pub node_id: u32,").unwrap();
+        writeln!(out, "    pub timestamp: Option<{CAN}::RxTimestamp>,").unwrap();
+        for field in &self.fields {
+            write_doc(out, "    ", field.signal.comment.as_deref());
+            let s = field.signal;
+            let mut notes = Vec::new();
+            if !s.unit.is_empty() {
+                notes.push(format!("Unit: {}", s.unit));
+            }
+            if s.has_range() {
+                notes.push(format!("Range: {} to {}", s.min, s.max));
+            }
+            if let Multiplex::Multiplexed(value) = s.multiplex {
+                notes.push(format!("Present when the multiplexor is {}", value));
+            }
+            if !notes.is_empty() {
+                if s.comment.is_some() {
+                    writeln!(out, "    ///").unwrap();
+                }
+                writeln!(out, "    /// {}.", notes.join(". ")).unwrap();
+            }
+            writeln!(out, "    pub {}: {},", field.ident, field.type_name()).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+    }
+
+    fn write_impl(&self, out: &mut String) {
+        let m = self.message;
+        let name = &self.name;
+        let (new_args, node_id) = match self.node_id_shift {
+            Some(_) => ("node_id: u32", "node_id"),
+            None => ("", "node_id: 0"),
+        };
+        let defaults: Vec<String> = self.fields.iter().map(|f| format!("{}: {}", f.ident, f.default_value())).collect();
+        writeln!(out).unwrap();
+        writeln!(out, "impl {} {{", name).unwrap();
+        writeln!(out, "    pub const DLC: usize = {};", m.dlc).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    pub fn new({}) -> Self {{", new_args).unwrap();
+        writeln!(out, "        Self {{ {}, timestamp: None{}{} }}", node_id, if defaults.is_empty() { "" } else { ", " }, defaults.join(", ")).unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+
+        let id = self.id();
+        let (matches, filters, node_of) = match self.node_id_shift {
+            Some(shift) => {
+                let mask = (1u32 << shift) - 1;
+                (
+                    format!("msg.arbitration_id & 0x{:X} == 0x{:X} && {}msg.is_extended_id", mask, id, if m.extended { "" } else { "!" }),
+                    format!(
+                        "match node_id {{\n            Some(node_id) => vec![{CAN}::CanIdFilter::exact((node_id << {shift}) | 0x{id:X})],\n            None => vec![{CAN}::CanIdFilter::new(0x{id:X}, 0x{mask:X})],\n        }}"
+                    ),
+                    format!("msg.arbitration_id >> {}", shift),
+                )
+            }
+            None => (
+                format!("msg.arbitration_id == 0x{:X} && {}msg.is_extended_id", id, if m.extended { "" } else { "!" }),
+                format!("vec![{CAN}::CanIdFilter::exact(0x{:X})]", id),
+                String::new(),
+            ),
+        };
+        // Kernel filters only cover 11-bit ids, so extended messages keep the default.
+        let filters = if m.extended { format!("vec![{CAN}::CanIdFilter::ACCEPT_ALL]") } else { filters };
+        let arbitration_id = match self.node_id_shift {
+            Some(shift) if id == 0 => format!("self.node_id << {}", shift),
+            Some(shift) => format!("(self.node_id << {}) | 0x{:X}", shift, id),
+            None => format!("0x{:X}", id),
+        };
+
+        writeln!(out).unwrap();
+        writeln!(out, "impl {CAN}::CanMessageTrait for {} {{", name).unwrap();
+        writeln!(out, "    fn cmd_id() -> u32 {{ 0x{:X} }}", id).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn node_id(&self) -> u32 {{ self.node_id }}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn timestamp(&self) -> Option<{CAN}::RxTimestamp> {{ self.timestamp }}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn set_timestamp(&mut self, timestamp: Option<{CAN}::RxTimestamp>) {{ self.timestamp = timestamp; }}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn matches(msg: &{CAN}::RawCanMessage) -> bool {{ {} }}", matches).unwrap();
+        writeln!(out).unwrap();
+        let node_id_arg = if self.node_id_shift.is_none() || m.extended { "_node_id" } else { "node_id" };
+        writeln!(out, "    fn can_filters({}: Option<u32>) -> Vec<{CAN}::CanIdFilter> {{", node_id_arg).unwrap();
+        writeln!(out, "        {}", filters).unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn try_from_can_message(msg: {CAN}::RawCanMessage) -> Result<Self, {CAN}::DecodeError> {{").unwrap();
+        writeln!(out, "        if !Self::matches(&msg) {{").unwrap();
+        writeln!(out, "            return Err({CAN}::DecodeError::WrongId {{ arbitration_id: msg.arbitration_id }});").unwrap();
+        writeln!(out, "        }}").unwrap();
+        match self.node_id_shift {
+            Some(_) => writeln!(out, "        let mut s = Self::new({});", node_of).unwrap(),
+            None => writeln!(out, "        let mut s = Self::new();").unwrap(),
+        }
+        writeln!(out, "        s.parse_can_msg_data(&msg)?;").unwrap();
+        writeln!(out, "        Ok(s)").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn as_can_message(&self) -> {CAN}::RawCanMessage {{").unwrap();
+        writeln!(out, "        {CAN}::RawCanMessage {{").unwrap();
+        writeln!(out, "            arbitration_id: {}::gen_arbitration_id(self).value(),", format_args!("{CAN}::CanMessageTrait")).unwrap();
+        writeln!(out, "            data: {}::gen_can_msg_data(self),", format_args!("{CAN}::CanMessageTrait")).unwrap();
+        writeln!(out, "            is_extended_id: {},", m.extended).unwrap();
+        writeln!(out, "            is_fd: {},", m.dlc > 8).unwrap();
+        writeln!(out, "            ..Default::default()").unwrap();
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn gen_arbitration_id(&self) -> {CAN}::ArbitrationId {{ {CAN}::ArbitrationId::Raw({}) }}", arbitration_id).unwrap();
+        writeln!(out).unwrap();
+        self.write_encode(out);
+        writeln!(out).unwrap();
+        self.write_decode(out);
+        writeln!(out, "}}").unwrap();
+    }
+
+    fn write_encode(&self, out: &mut String) {
+        let data = if self.fields.is_empty() { "data" } else { "mut data" };
+        writeln!(out, "    fn gen_can_msg_data(&self) -> Vec<u8> {{").unwrap();
+        writeln!(out, "        let {} = vec![0u8; Self::DLC];", data).unwrap();
+        let mux = self.multiplexor();
+        for field in &self.fields {
+            match field.signal.multiplex {
+                Multiplex::Multiplexed(_) => {}
+                _ => writeln!(out, "        {}", field.insert(&format!("self.{}", field.ident))).unwrap(),
+            }
+        }
+        if let Some(mux) = mux {
+            writeln!(out, "        let mux = ({}) & {};", mux.encode(&format!("self.{}", mux.ident)), mask_literal(mux.signal.size)).unwrap();
+            for field in &self.fields {
+                if let Multiplex::Multiplexed(value) = field.signal.multiplex {
+                    writeln!(out, "        if let (Some(value), {}) = (self.{}, mux) {{", value, field.ident).unwrap();
+                    writeln!(out, "            {}", field.insert("value")).unwrap();
+                    writeln!(out, "        }}").unwrap();
+                }
+            }
+        }
+        writeln!(out, "        data").unwrap();
+        writeln!(out, "    }}").unwrap();
+    }
+
+    fn write_decode(&self, out: &mut String) {
+        writeln!(out, "    fn parse_can_msg_data(&mut self, msg: &{CAN}::RawCanMessage) -> Result<(), {CAN}::DecodeError> {{").unwrap();
+        writeln!(out, "        msg.require_len(Self::DLC)?;").unwrap();
+        if let Some(mux) = self.multiplexor() {
+            writeln!(out, "        let mux = {};", mux.raw()).unwrap();
+        }
+        for field in &self.fields {
+            match field.signal.multiplex {
+                Multiplex::Multiplexed(value) => {
+                    writeln!(out, "        self.{} = if mux == {} {{ Some({}) }} else {{ None }};", field.ident, value, field.decode()).unwrap()
+                }
+                _ => writeln!(out, "        self.{} = {};", field.ident, field.decode()).unwrap(),
+            }
+        }
+        writeln!(out, "        Ok(())").unwrap();
+        writeln!(out, "    }}").unwrap();
+    }
+}
+
+fn field_type(signal: &Signal, enum_name: &str) -> FieldType {
+    match signal.value_type {
+        ValueType::Float32 => FieldType::Float("f32"),
+        ValueType::Float64 => FieldType::Float("f64"),
+        _ if !signal.values.is_empty() => FieldType::Enum(enum_name.to_string()),
+        _ if signal.is_scaled() => FieldType::Float("f64"),
+        ValueType::Unsigned if signal.size == 1 => FieldType::Bool,
+        ValueType::Unsigned => FieldType::Unsigned(match signal.size {
+            0..=8 => "u8",
+            9..=16 => "u16",
+            17..=32 => "u32",
+            _ => "u64",
+        }),
+        ValueType::Signed => FieldType::Signed(match signal.size {
+            0..=8 => "i8",
+            9..=16 => "i16",
+            17..=32 => "i32",
+            _ => "i64",
+        }),
+    }
+}
+
+fn write_enum(out: &mut String, name: &str, values: &[(i64, String)]) {
+    let variants = variant_names(values);
+    writeln!(out).unwrap();
+    writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq)]").unwrap();
+    writeln!(out, "#[repr(i64)]").unwrap();
+    writeln!(out, "pub enum {} {{", name).unwrap();
+    for ((value, description), variant) in values.iter().zip(&variants) {
+        if *variant != camel_case(description) || description.contains(char::is_whitespace) {
+            writeln!(out, "    /// {}", description).unwrap();
+        }
+        writeln!(out, "    {} = {},", variant, value).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "impl TryFrom<i64> for {} {{", name).unwrap();
+    writeln!(out, "    type Error = {CAN}::DecodeError;").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    fn try_from(value: i64) -> Result<Self, Self::Error> {{").unwrap();
+    writeln!(out, "        match value {{").unwrap();
+    for ((value, _), variant) in values.iter().zip(&variants) {
+        writeln!(out, "            {} => Ok({}::{}),", value, name, variant).unwrap();
+    }
+    writeln!(out, "            _ => Err({CAN}::DecodeError::UnknownEnumValue {{ name: \"{}\", value: value as u32 }}),", name).unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+}
+
+/// Variant names for a value table, unique within it.
+fn variant_names(values: &[(i64, String)]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    values
+        .iter()
+        .map(|(value, description)| {
+            let mut name = camel_case(description);
+            if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+                name = format!("Value{}", name);
+            }
+            if !seen.insert(name.clone()) {
+                name = format!("{}{}", name, value.unsigned_abs());
+                seen.insert(name.clone());
+            }
+            name
+        })
+        .collect()
+}
+
+fn write_doc(out: &mut String, indent: &str, doc: Option<&str>) {
+    for line in doc.into_iter().flat_map(str::lines) {
+        writeln!(out, "{}/// {}", indent, line.trim()).unwrap();
+    }
+}
+
+fn float_literal(value: f64, float: &str) -> String {
+    match float {
+        "f32" => format!("{:?}f32", value as f32),
+        _ => format!("{:?}f64", value),
+    }
+}
+
+fn mask_literal(size: u32) -> String {
+    if size >= 64 {
+        "u64::MAX".into()
+    } else {
+        format!("0x{:X}", (1u64 << size) - 1)
+    }
+}
+
+/// Splits a DBC name into words at underscores, spaces, punctuation and lower-to-upper changes.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+            prev_lower = false;
+            continue;
+        }
+        if c.is_ascii_uppercase() && prev_lower {
+            words.push(std::mem::take(&mut word));
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        word.push(c);
+    }
+    words.extend((!word.is_empty()).then_some(word));
+    words
+}
+
+fn camel_case(name: &str) -> String {
+    words(name)
+        .iter()
+        .map(|word| {
+            // Keep mixed-case words such as `ODrive`; tame all-caps ones.
+            let word = if word.chars().any(|c| c.is_ascii_lowercase()) { word.clone() } else { word.to_ascii_lowercase() };
+            let mut chars = word.chars();
+            chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+        })
+        .collect()
+}
+
+fn snake_case(name: &str) -> String {
+    let mut ident = words(name).join("_").to_ascii_lowercase();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident = format!("signal_{}", ident);
+    }
+    if RUST_KEYWORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+    ident
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(name: &str, signals: Vec<Signal>) -> Message {
+        Message { id: 0x10, extended: false, name: name.to_string(), dlc: 8, sender: String::new(), signals, comment: None }
+    }
+
+    #[test]
+    fn rust_names() {
+        assert_eq!(camel_case("Axis0_Get_Encoder_Estimates"), "Axis0GetEncoderEstimates");
+        assert_eq!(camel_case("ODrive_HEARTBEAT"), "ODriveHeartbeat");
+        assert_eq!(snake_case("Pos_Estimate"), "pos_estimate");
+        assert_eq!(snake_case("velEstimate"), "vel_estimate");
+        assert_eq!(snake_case("type"), "type_");
+        assert_eq!(snake_case("3V3_Rail"), "signal_3_v3_rail");
+    }
+
+    #[test]
+    fn variant_names_are_unique() {
+        let values = [(0, "Idle".to_string()), (1, "idle".to_string()), (2, "5V".to_string()), (3, "".to_string())];
+        assert_eq!(variant_names(&values), ["Idle", "Idle1", "Value5V", "Value"]);
+    }
+
+    #[test]
+    fn field_types() {
+        let unsigned = |size| Signal::new("s", 0, size, ByteOrder::LittleEndian, ValueType::Unsigned);
+        assert_eq!(field_type(&unsigned(1), "E").name(), "bool");
+        assert_eq!(field_type(&unsigned(12), "E").name(), "u16");
+        assert_eq!(field_type(&Signal::new("s", 0, 33, ByteOrder::LittleEndian, ValueType::Signed), "E").name(), "i64");
+        assert_eq!(field_type(&Signal { factor: 0.5, ..unsigned(8) }, "E").name(), "f64");
+        assert_eq!(field_type(&Signal { values: vec![(0, "Off".into())], ..unsigned(8) }, "E").name(), "E");
+        assert_eq!(field_type(&Signal::new("s", 0, 32, ByteOrder::LittleEndian, ValueType::Float32), "E").name(), "f32");
+    }
+
+    #[test]
+    fn multiplexed_signals_need_a_multiplexor() {
+        let signal = Signal { multiplex: Multiplex::Multiplexed(1), ..Signal::new("s", 0, 8, ByteOrder::LittleEndian, ValueType::Unsigned) };
+        let dbc = Dbc { messages: vec![message("Msg", vec![signal])], ..Default::default() };
+        assert!(generate(&dbc, &CodegenOptions::default()).is_err());
+    }
+
+    #[test]
+    fn duplicate_rust_names_fail() {
+        let dbc = Dbc { messages: vec![message("Set_Limits", vec![]), message("SetLimits", vec![])], ..Default::default() };
+        assert!(generate(&dbc, &CodegenOptions::default()).is_err());
+    }
+}
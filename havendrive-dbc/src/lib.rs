@@ -0,0 +1,162 @@
+//! DBC files: parsing, and generating Rust message types from them.
+//!
+//! A build script turns a vendor DBC into structs implementing `CanMessageTrait`:
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("odrive_dbc.rs");
+//!     havendrive_dbc::codegen::generate_file("odrive-cansimple.dbc", out, &Default::default()).unwrap();
+//! }
+//!
+//! // src/odrive_dbc.rs
+//! include!(concat!(env!("OUT_DIR"), "/odrive_dbc.rs"));
+//! ```
+//!
+//! The generated code refers to `havendrive::drivers::can::dbc`, which re-exports
+//! this crate, so only the build script needs to depend on it directly.
+//!
+//! `Dbc` also goes the other way: its `Display` writes it out as a DBC file.
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+pub mod codegen;
+mod parse;
+pub mod signal;
+mod write;
+
+/// The contents of a DBC file that describe messages. Attributes and
+/// environment variables are skipped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Dbc {
+    pub nodes: Vec<String>,
+    pub messages: Vec<Message>,
+    /// Named tables from `VAL_TABLE_`. Tables attached to signals are in `Signal::values`.
+    pub value_tables: Vec<ValueTable>,
+}
+
+impl Dbc {
+    pub fn parse(text: &str) -> Result<Self> {
+        parse::parse(text)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn message(&self, name: &str) -> Option<&Message> {
+        self.messages.iter().find(|m| m.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Without the extended-id flag DBC files keep in bit 31.
+    pub id: u32,
+    pub extended: bool,
+    pub name: String,
+    /// Payload length in bytes.
+    pub dlc: u8,
+    pub sender: String,
+    pub signals: Vec<Signal>,
+    pub comment: Option<String>,
+}
+
+impl Message {
+    /// The signal that selects which multiplexed signals are present, if any.
+    pub fn multiplexor(&self) -> Option<&Signal> {
+        self.signals.iter().find(|s| s.multiplex == Multiplex::Multiplexor)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal {
+    pub name: String,
+    pub multiplex: Multiplex,
+    /// For little-endian signals the least significant bit, for big-endian ones
+    /// the most significant, both in DBC bit numbering.
+    pub start_bit: u32,
+    pub size: u32,
+    pub byte_order: ByteOrder,
+    pub value_type: ValueType,
+    /// physical = raw * factor + offset
+    pub factor: f64,
+    pub offset: f64,
+    /// Both zero when the file gives no range.
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+    pub receivers: Vec<String>,
+    /// Names for raw values, from `VAL_`.
+    pub values: Vec<(i64, String)>,
+    pub comment: Option<String>,
+}
+
+impl Signal {
+    /// An unscaled signal with no range, unit or receivers.
+    pub fn new(name: impl Into<String>, start_bit: u32, size: u32, byte_order: ByteOrder, value_type: ValueType) -> Self {
+        Self {
+            name: name.into(),
+            multiplex: Multiplex::None,
+            start_bit,
+            size,
+            byte_order,
+            value_type,
+            factor: 1.0,
+            offset: 0.0,
+            min: 0.0,
+            max: 0.0,
+            unit: String::new(),
+            receivers: vec![],
+            values: vec![],
+            comment: None,
+        }
+    }
+
+    /// Whether the physical value differs from the raw one.
+    pub fn is_scaled(&self) -> bool {
+        self.factor != 1.0 || self.offset != 0.0
+    }
+
+    /// Whether `min` and `max` actually limit the value.
+    pub fn has_range(&self) -> bool {
+        self.min < self.max
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplex {
+    None,
+    /// Its raw value selects the multiplexed signals.
+    Multiplexor,
+    /// Present only when the multiplexor's raw value is this.
+    Multiplexed(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// `@1`, Intel.
+    LittleEndian,
+    /// `@0`, Motorola.
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Unsigned,
+    Signed,
+    /// IEEE float, from `SIG_VALTYPE_ ... : 1`.
+    Float32,
+    /// IEEE double, from `SIG_VALTYPE_ ... : 2`.
+    Float64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueTable {
+    pub name: String,
+    pub values: Vec<(i64, String)>,
+}
@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::{ByteOrder, Dbc, Message, Multiplex, Signal, ValueTable, ValueType};
+
+/// Statements we read, plus the ones we skip. A skipped statement runs until the
+/// next keyword that starts a line, as not all of them end in `;`.
+const KEYWORDS: &[&str] = &[
+    "VERSION", "NS_", "BS_", "BU_", "BO_", "SG_", "EV_", "CM_", "BA_DEF_", "BA_DEF_DEF_", "BA_", "VAL_", "VAL_TABLE_",
+    "SIG_VALTYPE_", "BO_TX_BU_", "SIG_GROUP_", "SIG_TYPE_REF_", "SGTYPE_", "SGTYPE_VAL_", "BA_DEF_SGTYPE_", "BA_SGTYPE_",
+    "ENVVAR_DATA_", "BA_DEF_REL_", "BA_REL_", "BA_DEF_DEF_REL_", "BU_SG_REL_", "BU_EV_REL_", "BU_BO_REL_", "SG_MUL_VAL_",
+    "CAT_DEF_", "CAT_", "FILTER",
+];
+
+const PUNCTUATION: &[char] = &[':', ';', '|', '@', '(', ')', '[', ']', ','];
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Str(String),
+    Punct(char),
+}
+
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    starts_line: bool,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let mut starts_line = true;
+    while let Some(&c) = chars.peek() {
+        let token_line = line;
+        let kind = match c {
+            '\n' => {
+                chars.next();
+                line += 1;
+                starts_line = true;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => s.extend(chars.next()),
+                        Some(c) => s.push(c),
+                        None => return Err(anyhow!("Line {}: unterminated string", token_line)),
+                    }
+                }
+                line += s.matches('\n').count();
+                TokenKind::Str(s)
+            }
+            c if PUNCTUATION.contains(&c) => {
+                chars.next();
+                TokenKind::Punct(c)
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '"' || PUNCTUATION.contains(&c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                TokenKind::Word(word)
+            }
+        };
+        tokens.push(Token { kind, line: token_line, starts_line });
+        starts_line = false;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos.min(self.tokens.len().saturating_sub(1))).map_or(1, |t| t.line)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<TokenKind> {
+        let token = self.tokens.get(self.pos).ok_or_else(|| anyhow!("Line {}: expected {}, got end of file", self.line(), expected))?;
+        self.pos += 1;
+        Ok(token.kind.clone())
+    }
+
+    fn error(&self, expected: &str, got: &TokenKind) -> anyhow::Error {
+        anyhow!("Line {}: expected {}, got {:?}", self.tokens[self.pos - 1].line, expected, got)
+    }
+
+    fn word(&mut self, expected: &str) -> Result<String> {
+        match self.next(expected)? {
+            TokenKind::Word(word) => Ok(word),
+            other => Err(self.error(expected, &other)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.next("a string")? {
+            TokenKind::Str(s) => Ok(s),
+            other => Err(self.error("a string", &other)),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self, expected: &str) -> Result<T> {
+        let word = self.word(expected)?;
+        word.parse().map_err(|_| anyhow!("Line {}: expected {}, got {:?}", self.tokens[self.pos - 1].line, expected, word))
+    }
+
+    /// Value table entries are integers, though some tools write them as `1.0`.
+    fn integer(&mut self) -> Result<i64> {
+        let value: f64 = self.number("a value")?;
+        Ok(value as i64)
+    }
+
+    fn punct(&mut self, c: char) -> Result<()> {
+        let expected = format!("'{}'", c);
+        match self.next(&expected)? {
+            TokenKind::Punct(p) if p == c => Ok(()),
+            other => Err(self.error(&expected, &other)),
+        }
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        let found = matches!(self.peek(), Some(Token { kind: TokenKind::Punct(p), .. }) if *p == c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// Whether the next token is on the same line as the previous one.
+    fn continues_line(&self) -> bool {
+        self.peek().is_some_and(|t| !t.starts_line)
+    }
+
+    fn at_statement(&self) -> bool {
+        match self.peek() {
+            Some(Token { kind: TokenKind::Word(w), starts_line: true, .. }) => KEYWORDS.contains(&w.as_str()),
+            _ => self.peek().is_none(),
+        }
+    }
+
+    fn skip_statement(&mut self) {
+        while !self.at_statement() {
+            self.pos += 1;
+        }
+    }
+
+    fn message(&mut self) -> Result<Message> {
+        let raw_id: u32 = self.number("a message id")?;
+        let name = self.word("a message name")?;
+        self.punct(':')?;
+        let dlc = self.number("a message length")?;
+        let sender = self.word("a sender")?;
+        Ok(Message {
+            id: raw_id & 0x1FFF_FFFF,
+            extended: raw_id & 0x8000_0000 != 0,
+            name,
+            dlc,
+            sender,
+            signals: vec![],
+            comment: None,
+        })
+    }
+
+    fn signal(&mut self) -> Result<Signal> {
+        let name = self.word("a signal name")?;
+        let mut multiplex = Multiplex::None;
+        if !self.eat_punct(':') {
+            let indicator = self.word("a multiplex indicator")?;
+            multiplex = match indicator.strip_prefix('m') {
+                _ if indicator == "M" => Multiplex::Multiplexor,
+                // `m3M` switches further signals too; only the outer level is kept.
+                Some(value) => Multiplex::Multiplexed(value.trim_end_matches('M').parse().map_err(|_| anyhow!("Line {}: bad multiplex indicator {:?}", self.line(), indicator))?),
+                None => return Err(anyhow!("Line {}: bad multiplex indicator {:?}", self.line(), indicator)),
+            };
+            self.punct(':')?;
+        }
+        let start_bit = self.number("a start bit")?;
+        self.punct('|')?;
+        let size = self.number("a signal size")?;
+        self.punct('@')?;
+        let format = self.word("a byte order and sign such as 1+")?;
+        let (byte_order, value_type) = match format.as_str() {
+            "1+" => (ByteOrder::LittleEndian, ValueType::Unsigned),
+            "1-" => (ByteOrder::LittleEndian, ValueType::Signed),
+            "0+" => (ByteOrder::BigEndian, ValueType::Unsigned),
+            "0-" => (ByteOrder::BigEndian, ValueType::Signed),
+            _ => return Err(anyhow!("Line {}: bad byte order and sign {:?}", self.line(), format)),
+        };
+        self.punct('(')?;
+        let factor = self.number("a factor")?;
+        self.punct(',')?;
+        let offset = self.number("an offset")?;
+        self.punct(')')?;
+        self.punct('[')?;
+        let min = self.number("a minimum")?;
+        self.punct('|')?;
+        let max = self.number("a maximum")?;
+        self.punct(']')?;
+        let unit = self.string()?;
+        let mut receivers = Vec::new();
+        while self.continues_line() {
+            if !self.eat_punct(',') {
+                receivers.push(self.word("a receiver")?);
+            }
+        }
+        Ok(Signal {
+            name,
+            multiplex,
+            start_bit,
+            size,
+            byte_order,
+            value_type,
+            factor,
+            offset,
+            min,
+            max,
+            unit,
+            receivers,
+            values: vec![],
+            comment: None,
+        })
+    }
+
+    fn value_descriptions(&mut self) -> Result<Vec<(i64, String)>> {
+        let mut values = Vec::new();
+        while !self.eat_punct(';') {
+            values.push((self.integer()?, self.string()?));
+        }
+        Ok(values)
+    }
+}
+
+/// Comments, value tables and float types come after the messages and name
+/// their signal by message id and signal name.
+#[derive(Default)]
+struct SignalExtras {
+    comment: Option<String>,
+    values: Vec<(i64, String)>,
+    value_type: Option<ValueType>,
+}
+
+pub(crate) fn parse(text: &str) -> Result<Dbc> {
+    let mut p = Parser { tokens: tokenize(text)?, pos: 0 };
+    let mut dbc = Dbc::default();
+    let mut message_comments = HashMap::new();
+    let mut extras: HashMap<(u32, String), SignalExtras> = HashMap::new();
+    while let Some(token) = p.peek() {
+        let keyword = match &token.kind {
+            TokenKind::Word(word) => word.clone(),
+            other => return Err(anyhow!("Line {}: expected a keyword, got {:?}", token.line, other)),
+        };
+        p.pos += 1;
+        match keyword.as_str() {
+            "BU_" => {
+                p.punct(':')?;
+                while p.continues_line() {
+                    dbc.nodes.push(p.word("a node name")?);
+                }
+            }
+            "BO_" => dbc.messages.push(p.message()?),
+            "SG_" => {
+                let line = p.line();
+                let signal = p.signal()?;
+                let message = dbc.messages.last_mut().ok_or_else(|| anyhow!("Line {}: signal {} outside a message", line, signal.name))?;
+                message.signals.push(signal);
+            }
+            "CM_" => match p.next("a comment")? {
+                TokenKind::Str(_) => p.punct(';')?,
+                TokenKind::Word(kind) if kind == "BO_" => {
+                    let id: u32 = p.number("a message id")?;
+                    message_comments.insert(id & 0x1FFF_FFFF, p.string()?);
+                    p.punct(';')?;
+                }
+                TokenKind::Word(kind) if kind == "SG_" => {
+                    let id: u32 = p.number("a message id")?;
+                    let signal = p.word("a signal name")?;
+                    extras.entry((id & 0x1FFF_FFFF, signal)).or_default().comment = Some(p.string()?);
+                    p.punct(';')?;
+                }
+                _ => p.skip_statement(),
+            },
+            "VAL_TABLE_" => {
+                let name = p.word("a value table name")?;
+                dbc.value_tables.push(ValueTable { name, values: p.value_descriptions()? });
+            }
+            "VAL_" => {
+                let id: u32 = p.number("a message id")?;
+                let signal = p.word("a signal name")?;
+                extras.entry((id & 0x1FFF_FFFF, signal)).or_default().values = p.value_descriptions()?;
+            }
+            "SIG_VALTYPE_" => {
+                let id: u32 = p.number("a message id")?;
+                let signal = p.word("a signal name")?;
+                p.eat_punct(':');
+                let value_type = match p.number::<u32>("a value type")? {
+                    1 => Some(ValueType::Float32),
+                    2 => Some(ValueType::Float64),
+                    _ => None,
+                };
+                p.punct(';')?;
+                extras.entry((id & 0x1FFF_FFFF, signal)).or_default().value_type = value_type;
+            }
+            // The symbol list is indented keywords, so it would look like statements.
+            "NS_" => {
+                while p.peek().is_some_and(|t| t.kind != TokenKind::Word("BS_".into())) {
+                    p.pos += 1;
+                }
+            }
+            _ => p.skip_statement(),
+        }
+    }
+
+    for message in &mut dbc.messages {
+        message.comment = message_comments.remove(&message.id);
+        for signal in &mut message.signals {
+            if let Some(extra) = extras.remove(&(message.id, signal.name.clone())) {
+                signal.comment = extra.comment;
+                signal.values = extra.values;
+                signal.value_type = extra.value_type.unwrap_or(signal.value_type);
+            }
+        }
+    }
+    Ok(dbc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"VERSION ""
+
+NS_ :
+	NS_DESC_
+	CM_
+	BA_DEF_
+	VAL_
+	SIG_VALTYPE_
+
+BS_:
+
+BU_: Master ODrive_Axis0
+
+VAL_TABLE_ AxisStates 0 "UNDEFINED" 1 "IDLE" 8 "CLOSED_LOOP_CONTROL" ;
+
+BO_ 1 Axis0_Heartbeat: 8 ODrive_Axis0
+ SG_ Axis_Error : 0|32@1+ (1,0) [0|0] "" Master
+ SG_ Axis_State : 32|8@1+ (1,0) [0|0] "" Master
+ SG_ Trajectory_Done_Flag : 55|1@1+ (1,0) [0|0] "" Master,ODrive_Axis0
+
+BO_ 9 Axis0_Get_Encoder_Estimates: 8 ODrive_Axis0
+ SG_ Pos_Estimate : 0|32@1- (1,0) [0|0] "rev" Master
+ SG_ Vel_Estimate : 32|32@1- (1,0) [0|0] "rev/s" Master
+
+BO_ 2147484672 Debug_Frame: 8 Master
+ SG_ Mode M : 7|4@0+ (1,0) [0|0] "" ODrive_Axis0
+ SG_ Temperature m0 : 3|12@0- (0.1,-40) [-40|100] "degC" ODrive_Axis0
+ SG_ Counter m1 : 15|16@0+ (1,0) [0|0] "" ODrive_Axis0
+ SG_ Nested m2M : 23|8@0+ (1,0) [0|0] "" ODrive_Axis0
+
+BA_DEF_ BO_ "GenMsgCycleTime" INT 0 65535;
+BA_ "GenMsgCycleTime" BO_ 1 100;
+CM_ "A file comment";
+CM_ BO_ 1 "Sent by each axis at a fixed rate.";
+CM_ SG_ 1 Axis_State "Current state of the axis.";
+VAL_ 1 Axis_State 0 "UNDEFINED" 1 "IDLE" 8 "CLOSED_LOOP_CONTROL" ;
+SIG_VALTYPE_ 9 Pos_Estimate : 1;
+SIG_VALTYPE_ 9 Vel_Estimate : 1;
+"#;
+
+    fn sample() -> Dbc {
+        parse(SAMPLE).unwrap()
+    }
+
+    #[test]
+    fn messages_and_nodes() {
+        let dbc = sample();
+        assert_eq!(dbc.nodes, ["Master", "ODrive_Axis0"]);
+        let names: Vec<&str> = dbc.messages.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["Axis0_Heartbeat", "Axis0_Get_Encoder_Estimates", "Debug_Frame"]);
+        let heartbeat = dbc.message("Axis0_Heartbeat").unwrap();
+        assert_eq!((heartbeat.id, heartbeat.extended, heartbeat.dlc), (1, false, 8));
+        assert_eq!(heartbeat.sender, "ODrive_Axis0");
+        assert_eq!(heartbeat.comment.as_deref(), Some("Sent by each axis at a fixed rate."));
+        assert_eq!(heartbeat.signals[2].receivers, ["Master", "ODrive_Axis0"]);
+    }
+
+    #[test]
+    fn extended_id_flag() {
+        let debug = sample().message("Debug_Frame").unwrap().clone();
+        assert_eq!((debug.id, debug.extended), (0x400, true));
+    }
+
+    #[test]
+    fn signal_layout_and_scaling() {
+        let dbc = sample();
+        let debug = dbc.message("Debug_Frame").unwrap();
+        let temperature = &debug.signals[1];
+        assert_eq!((temperature.start_bit, temperature.size), (3, 12));
+        assert_eq!((temperature.byte_order, temperature.value_type), (ByteOrder::BigEndian, ValueType::Signed));
+        assert_eq!((temperature.factor, temperature.offset), (0.1, -40.0));
+        assert_eq!((temperature.min, temperature.max), (-40.0, 100.0));
+        assert_eq!(temperature.unit, "degC");
+    }
+
+    #[test]
+    fn multiplexing() {
+        let dbc = sample();
+        let debug = dbc.message("Debug_Frame").unwrap();
+        let multiplex: Vec<Multiplex> = debug.signals.iter().map(|s| s.multiplex).collect();
+        // `m2M` is both multiplexed and a nested multiplexor; only the outer level is kept.
+        assert_eq!(multiplex, [Multiplex::Multiplexor, Multiplex::Multiplexed(0), Multiplex::Multiplexed(1), Multiplex::Multiplexed(2)]);
+        assert_eq!(debug.multiplexor().unwrap().name, "Mode");
+    }
+
+    #[test]
+    fn sig_valtype_makes_floats() {
+        let dbc = sample();
+        let estimates = dbc.message("Axis0_Get_Encoder_Estimates").unwrap();
+        assert!(estimates.signals.iter().all(|s| s.value_type == ValueType::Float32));
+    }
+
+    #[test]
+    fn value_descriptions() {
+        let dbc = sample();
+        let state = &dbc.message("Axis0_Heartbeat").unwrap().signals[1];
+        let expected = [(0, "UNDEFINED"), (1, "IDLE"), (8, "CLOSED_LOOP_CONTROL")];
+        assert_eq!(state.values, expected.map(|(v, n)| (v, n.to_string())));
+        assert_eq!(state.comment.as_deref(), Some("Current state of the axis."));
+        assert_eq!(dbc.value_tables[0].name, "AxisStates");
+        assert_eq!(dbc.value_tables[0].values, state.values);
+    }
+
+    #[test]
+    fn written_file_parses_back() {
+        let dbc = sample();
+        assert_eq!(parse(&dbc.to_string()).unwrap(), dbc);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let err = parse("BO_ 1 Msg: 8 Node\n SG_ Bad : 0|8@2+ (1,0) [0|0] \"\" Node\n").unwrap_err();
+        assert!(err.to_string().starts_with("Line 2:"), "{}", err);
+        let err = parse("BU_: A\n SG_ Orphan : 0|8@1+ (1,0) [0|0] \"\" A\n").unwrap_err();
+        assert!(err.to_string().contains("outside a message"), "{}", err);
+    }
+}
@@ -0,0 +1,119 @@
+//! Reading and writing signals in a payload. Generated code calls these at runtime.
+use crate::ByteOrder;
+
+/// Payload bit positions of a signal, most significant first. Bit `n` is bit
+/// `n % 8` of byte `n / 8`. Big-endian signals run down a byte and continue at
+/// the top of the next one.
+fn bit_positions(start_bit: u32, size: u32, order: ByteOrder) -> impl Iterator<Item = u32> {
+    let mut next = match order {
+        ByteOrder::LittleEndian => start_bit + size.saturating_sub(1),
+        ByteOrder::BigEndian => start_bit,
+    };
+    (0..size).map(move |_| {
+        let pos = next;
+        next = match order {
+            ByteOrder::LittleEndian => pos.saturating_sub(1),
+            ByteOrder::BigEndian if pos % 8 == 0 => pos + 15,
+            ByteOrder::BigEndian => pos - 1,
+        };
+        pos
+    })
+}
+
+/// The raw value of a signal. Bits past the end of `data` read as zero.
+pub fn extract(data: &[u8], start_bit: u32, size: u32, order: ByteOrder) -> u64 {
+    bit_positions(start_bit, size, order).fold(0, |value, pos| {
+        let bit = data.get(pos as usize / 8).map_or(0, |byte| (byte >> (pos % 8)) & 1);
+        (value << 1) | bit as u64
+    })
+}
+
+/// Writes the low `size` bits of `value`. Bits past the end of `data` are dropped.
+pub fn insert(data: &mut [u8], start_bit: u32, size: u32, order: ByteOrder, value: u64) {
+    for (i, pos) in bit_positions(start_bit, size, order).enumerate() {
+        let bit = (value >> (size as usize - 1 - i)) & 1;
+        if let Some(byte) = data.get_mut(pos as usize / 8) {
+            *byte = (*byte & !(1 << (pos % 8))) | ((bit as u8) << (pos % 8));
+        }
+    }
+}
+
+/// Reads the low `size` bits of `raw` as two's complement.
+pub fn sign_extend(raw: u64, size: u32) -> i64 {
+    if size == 0 || size >= 64 {
+        return raw as i64;
+    }
+    let shift = 64 - size;
+    ((raw << shift) as i64) >> shift
+}
+
+/// Bytes a payload needs to hold the signal.
+pub fn bytes_needed(start_bit: u32, size: u32, order: ByteOrder) -> usize {
+    bit_positions(start_bit, size, order).map(|pos| pos as usize / 8 + 1).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn big_endian_runs_down_into_the_next_byte() {
+        let mut data = [0u8; 2];
+        insert(&mut data, 7, 12, ByteOrder::BigEndian, 0xABC);
+        assert_eq!(data, [0xAB, 0xC0]);
+        assert_eq!(extract(&data, 7, 12, ByteOrder::BigEndian), 0xABC);
+    }
+
+    #[test]
+    fn big_endian_mid_byte_start() {
+        // The X4-24 new motor id: 16 bits whose msb is bit 7 of byte 4.
+        let mut data = [0u8; 8];
+        insert(&mut data, 39, 16, ByteOrder::BigEndian, 0x0123);
+        assert_eq!(data, [0, 0, 0, 0, 0x01, 0x23, 0, 0]);
+        // Starting at bit 3 leaves the top nibble of the first byte alone.
+        let mut data = [0xF0, 0x00];
+        insert(&mut data, 3, 8, ByteOrder::BigEndian, 0xA5);
+        assert_eq!(data, [0xFA, 0x50]);
+        assert_eq!(extract(&data, 3, 8, ByteOrder::BigEndian), 0xA5);
+    }
+
+    #[test]
+    fn little_endian_across_bytes() {
+        let mut data = [0x0F, 0x00];
+        insert(&mut data, 4, 12, ByteOrder::LittleEndian, 0xABC);
+        assert_eq!(data, [0xCF, 0xAB]);
+        assert_eq!(extract(&data, 4, 12, ByteOrder::LittleEndian), 0xABC);
+    }
+
+    #[test]
+    fn insert_keeps_only_the_low_bits() {
+        let mut data = [0u8; 1];
+        insert(&mut data, 0, 4, ByteOrder::LittleEndian, 0xFF);
+        assert_eq!(data, [0x0F]);
+    }
+
+    #[test]
+    fn bits_past_the_payload() {
+        assert_eq!(extract(&[0xFF], 4, 8, ByteOrder::LittleEndian), 0x0F);
+        let mut data = [0u8; 1];
+        insert(&mut data, 4, 8, ByteOrder::LittleEndian, 0xFF);
+        assert_eq!(data, [0xF0]);
+    }
+
+    #[test]
+    fn sign_extend_twos_complement() {
+        assert_eq!(sign_extend(0xFFF, 12), -1);
+        assert_eq!(sign_extend(0x800, 12), -2048);
+        assert_eq!(sign_extend(0x7FF, 12), 2047);
+        assert_eq!(sign_extend(1, 1), -1);
+        assert_eq!(sign_extend(u64::MAX, 64), -1);
+    }
+
+    #[test]
+    fn bytes_needed_by_order() {
+        assert_eq!(bytes_needed(4, 12, ByteOrder::LittleEndian), 2);
+        assert_eq!(bytes_needed(7, 12, ByteOrder::BigEndian), 2);
+        assert_eq!(bytes_needed(39, 16, ByteOrder::BigEndian), 6);
+        assert_eq!(bytes_needed(0, 0, ByteOrder::LittleEndian), 0);
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{ByteOrder, Dbc, Message, Multiplex, Signal, ValueType};
+
+/// Tools use this name for a node that is not listed in `BU_`.
+const NO_NODE: &str = "Vector__XXX";
+
+struct Quoted<'a>(&'a str);
+
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self.0.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn node(name: &str) -> &str {
+    if name.is_empty() { NO_NODE } else { name }
+}
+
+/// The id as written in the file, with the extended flag in bit 31.
+fn file_id(message: &Message) -> u32 {
+    if message.extended { message.id | 0x8000_0000 } else { message.id }
+}
+
+fn write_values(f: &mut Formatter<'_>, values: &[(i64, String)]) -> fmt::Result {
+    for (value, name) in values {
+        write!(f, " {} {}", value, Quoted(name))?;
+    }
+    writeln!(f, " ;")
+}
+
+fn write_signal(f: &mut Formatter<'_>, signal: &Signal) -> fmt::Result {
+    let multiplex = match signal.multiplex {
+        Multiplex::None => String::new(),
+        Multiplex::Multiplexor => " M".to_string(),
+        Multiplex::Multiplexed(value) => format!(" m{}", value),
+    };
+    let order = match signal.byte_order {
+        ByteOrder::LittleEndian => '1',
+        ByteOrder::BigEndian => '0',
+    };
+    let sign = if signal.value_type == ValueType::Unsigned { '+' } else { '-' };
+    let receivers = if signal.receivers.is_empty() { NO_NODE.to_string() } else { signal.receivers.join(",") };
+    writeln!(
+        f,
+        " SG_ {}{} : {}|{}@{}{} ({},{}) [{}|{}] {} {}",
+        signal.name, multiplex, signal.start_bit, signal.size, order, sign, signal.factor, signal.offset, signal.min, signal.max,
+        Quoted(&signal.unit), receivers
+    )
+}
+
+impl Display for Dbc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "VERSION \"\"\n\nNS_ :\n\tCM_\n\tVAL_\n\tVAL_TABLE_\n\tSIG_VALTYPE_\n\nBS_:\n")?;
+        writeln!(f, "BU_:{}\n", self.nodes.iter().map(|n| format!(" {}", n)).collect::<String>())?;
+        for table in &self.value_tables {
+            write!(f, "VAL_TABLE_ {}", table.name)?;
+            write_values(f, &table.values)?;
+        }
+        for message in &self.messages {
+            writeln!(f, "\nBO_ {} {}: {} {}", file_id(message), message.name, message.dlc, node(&message.sender))?;
+            for signal in &message.signals {
+                write_signal(f, signal)?;
+            }
+        }
+        writeln!(f)?;
+        for message in &self.messages {
+            if let Some(comment) = &message.comment {
+                writeln!(f, "CM_ BO_ {} {};", file_id(message), Quoted(comment))?;
+            }
+            for signal in message.signals.iter().filter(|s| s.comment.is_some()) {
+                writeln!(f, "CM_ SG_ {} {} {};", file_id(message), signal.name, Quoted(signal.comment.as_deref().unwrap_or_default()))?;
+            }
+        }
+        for message in &self.messages {
+            for signal in message.signals.iter().filter(|s| !s.values.is_empty()) {
+                write!(f, "VAL_ {} {}", file_id(message), signal.name)?;
+                write_values(f, &signal.values)?;
+            }
+        }
+        for message in &self.messages {
+            for signal in &message.signals {
+                let code = match signal.value_type {
+                    ValueType::Float32 => 1,
+                    ValueType::Float64 => 2,
+                    _ => continue,
+                };
+                writeln!(f, "SIG_VALTYPE_ {} {} : {};", file_id(message), signal.name, code)?;
+            }
+        }
+        Ok(())
+    }
+}
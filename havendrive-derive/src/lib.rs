@@ -0,0 +1,597 @@
+//! `#[derive(CanMessage)]` implements `CanMessageTrait` from a payload layout, so a
+//! message is a struct definition instead of hand-written shifts and slices.
+//!
+//! ```ignore
+//! #[derive(Debug, Clone, CanMessage)]
+//! #[can(protocol = "odrive", cmd_id = 0x0C)]
+//! pub struct SetPositionMessage {
+//!     base: OdriveCanMessage,
+//!     #[can(offset = 0)]
+//!     pub input_position: f32,
+//!     #[can(offset = 4)]
+//!     pub velocity_ff: i16,
+//!     #[can(offset = 6)]
+//!     pub torque_ff: i16,
+//! }
+//! ```
+//!
+//! Message attributes:
+//! - `protocol`: `"odrive"`, `"myactuator"` or `"x424"`. Decides how the id is
+//!   matched and where the command id goes: the arbitration id for ODrive, the
+//!   first payload byte for MyActuator and its top three bits for X4-24.
+//! - `cmd_id`
+//! - `len`: payload length when encoding. Defaults to the end of the last field,
+//!   and to at least 8 bytes for MyActuator.
+//! - `endian`: `"little"` (the default) or `"big"`, for every field.
+//! - `priority`: the `TxPriority` to send with, e.g. `"Emergency"`.
+//!
+//! Field attributes:
+//! - `offset`: the first payload byte of the field.
+//! - `raw`: the integer type on the wire, if not the field type.
+//! - `endian`: overrides the message's.
+//! - `scale`, `bias`: the field is `raw * scale + bias`. Float fields only.
+//! - `bits = "start..end"`: the field is bits `start..end` of the `raw` word at
+//!   `offset`, counted from its least significant bit.
+//! - `enum`: a fieldless enum, decoded with `TryFrom<raw>` and encoded with `as`.
+//! - `skip`: not on the wire. Decodes as `Default::default()`.
+//! - `unit`: the physical unit, e.g. `"rev/s"`. Only used in the DBC export.
+//!
+//! The field named `base` holds the protocol's base message. It provides the node
+//! id, timestamp and arbitration id, and is built with `new(node_id, cmd_id)`.
+//!
+//! The derive also implements `DbcLayout`, describing each wire field as a DBC
+//! signal. Doc comments on fields become signal comments.
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, BinOp, Data, DeriveInput, Error, Expr, Fields, Ident, Lit, LitStr, Result, Type, UnOp};
+
+#[proc_macro_derive(CanMessage, attributes(can))]
+pub fn derive_can_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Protocol {
+    Odrive,
+    MyActuator,
+    X424,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn parse(lit: &LitStr) -> Result<Self> {
+        match lit.value().as_str() {
+            "little" => Ok(Endian::Little),
+            "big" => Ok(Endian::Big),
+            _ => Err(Error::new(lit.span(), "expected \"little\" or \"big\"")),
+        }
+    }
+
+    fn decode_fn(self) -> Ident {
+        match self {
+            Endian::Little => format_ident!("from_le_bytes"),
+            Endian::Big => format_ident!("from_be_bytes"),
+        }
+    }
+
+    fn encode_fn(self) -> Ident {
+        match self {
+            Endian::Little => format_ident!("to_le_bytes"),
+            Endian::Big => format_ident!("to_be_bytes"),
+        }
+    }
+}
+
+struct MessageAttrs {
+    protocol: Protocol,
+    cmd_id: u32,
+    len: Option<usize>,
+    endian: Endian,
+    priority: Option<Ident>,
+}
+
+struct WireField {
+    ident: Ident,
+    ty: Type,
+    offset: usize,
+    raw: Ident,
+    endian: Endian,
+    scale: Option<f64>,
+    bias: Option<f64>,
+    bits: Option<(u32, u32)>,
+    is_enum: bool,
+    unit: Option<String>,
+    doc: Option<String>,
+}
+
+enum FieldKind {
+    Base(Box<Type>),
+    Skip,
+    Wire(Box<WireField>),
+}
+
+fn parse_message_attrs(input: &DeriveInput) -> Result<MessageAttrs> {
+    let mut protocol = None;
+    let mut cmd_id = None;
+    let mut len = None;
+    let mut endian = Endian::Little;
+    let mut priority = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("can")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("protocol") {
+                let lit: LitStr = meta.value()?.parse()?;
+                protocol = Some(match lit.value().as_str() {
+                    "odrive" => Protocol::Odrive,
+                    "myactuator" => Protocol::MyActuator,
+                    "x424" => Protocol::X424,
+                    _ => return Err(Error::new(lit.span(), "expected \"odrive\", \"myactuator\" or \"x424\"")),
+                });
+            } else if meta.path.is_ident("cmd_id") {
+                cmd_id = Some(meta.value()?.parse::<syn::LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("len") {
+                len = Some(meta.value()?.parse::<syn::LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("endian") {
+                endian = Endian::parse(&meta.value()?.parse()?)?;
+            } else if meta.path.is_ident("priority") {
+                let lit: LitStr = meta.value()?.parse()?;
+                priority = Some(Ident::new(&lit.value(), lit.span()));
+            } else {
+                return Err(meta.error("unknown message attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    let span = input.ident.span();
+    Ok(MessageAttrs {
+        protocol: protocol.ok_or_else(|| Error::new(span, "missing #[can(protocol = ...)]"))?,
+        cmd_id: cmd_id.ok_or_else(|| Error::new(span, "missing #[can(cmd_id = ...)]"))?,
+        len,
+        endian,
+        priority,
+    })
+}
+
+fn parse_field(field: &syn::Field, endian: Endian) -> Result<FieldKind> {
+    let ident = field.ident.clone().ok_or_else(|| Error::new(field.span(), "expected a named field"))?;
+    if ident == "base" {
+        return Ok(FieldKind::Base(Box::new(field.ty.clone())));
+    }
+    let mut skip = false;
+    let mut offset = None;
+    let mut raw = None;
+    let mut field_endian = endian;
+    let mut scale = None;
+    let mut bias = None;
+    let mut bits = None;
+    let mut is_enum = false;
+    let mut unit = None;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("can")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+            } else if meta.path.is_ident("enum") {
+                is_enum = true;
+            } else if meta.path.is_ident("offset") {
+                offset = Some(meta.value()?.parse::<syn::LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("raw") {
+                let lit: LitStr = meta.value()?.parse()?;
+                raw = Some(Ident::new(&lit.value(), lit.span()));
+            } else if meta.path.is_ident("unit") {
+                unit = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("endian") {
+                field_endian = Endian::parse(&meta.value()?.parse()?)?;
+            } else if meta.path.is_ident("scale") {
+                scale = Some(eval(&meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("bias") {
+                bias = Some(eval(&meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("bits") {
+                let lit: LitStr = meta.value()?.parse()?;
+                let range = lit.value();
+                let parsed = range.split_once("..").and_then(|(start, end)| Some((start.trim().parse().ok()?, end.trim().parse().ok()?)));
+                match parsed {
+                    Some((start, end)) if start < end => bits = Some((start, end)),
+                    _ => return Err(Error::new(lit.span(), "expected a bit range such as \"40..56\"")),
+                }
+            } else {
+                return Err(meta.error("unknown field attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    if skip {
+        return Ok(FieldKind::Skip);
+    }
+    let offset = offset.ok_or_else(|| Error::new(ident.span(), "field needs #[can(offset = ...)] or #[can(skip)]"))?;
+    let ty_name = type_name(&field.ty);
+    let raw = match (raw, ty_name.as_deref()) {
+        (Some(raw), _) => raw,
+        (None, Some("bool")) => format_ident!("u8"),
+        (None, Some(name)) if !is_enum && byte_size(name).is_some() => Ident::new(name, field.ty.span()),
+        _ => return Err(Error::new(field.ty.span(), "field needs #[can(raw = ...)] to say its type on the wire")),
+    };
+    let raw_size = byte_size(&raw.to_string()).ok_or_else(|| Error::new(raw.span(), "raw must be a primitive integer or float type"))?;
+    if let Some((_, end)) = bits {
+        if end as usize > raw_size * 8 || !raw.to_string().starts_with('u') {
+            return Err(Error::new(raw.span(), "bits must lie within an unsigned raw word"));
+        }
+    }
+    let is_float = matches!(ty_name.as_deref(), Some("f32") | Some("f64"));
+    if (scale.is_some() || bias.is_some()) && !is_float {
+        return Err(Error::new(field.ty.span(), "scale and bias need an f32 or f64 field"));
+    }
+    let doc = doc_comment(&field.attrs);
+    Ok(FieldKind::Wire(Box::new(WireField { ident, ty: field.ty.clone(), offset, raw, endian: field_endian, scale, bias, bits, is_enum, unit, doc })))
+}
+
+/// The `///` lines on a field, joined into one.
+fn doc_comment(attrs: &[syn::Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            syn::Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(syn::ExprLit { lit: Lit::Str(s), .. }) => Some(s.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    (!lines.is_empty()).then(|| lines.join(" "))
+}
+
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident().map(|ident| ident.to_string()),
+        _ => None,
+    }
+}
+
+fn byte_size(name: &str) -> Option<usize> {
+    match name {
+        "u8" | "i8" => Some(1),
+        "u16" | "i16" => Some(2),
+        "u32" | "i32" | "f32" => Some(4),
+        "u64" | "i64" | "f64" => Some(8),
+        _ => None,
+    }
+}
+
+/// Evaluates a numeric literal or arithmetic on literals, e.g. `36.0 / 4095.0`.
+fn eval(expr: &Expr) -> Result<f64> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Float(f) => f.base10_parse(),
+            Lit::Int(i) => i.base10_parse::<i64>().map(|i| i as f64),
+            _ => Err(Error::new(lit.span(), "expected a number")),
+        },
+        Expr::Unary(unary) if matches!(unary.op, UnOp::Neg(_)) => Ok(-eval(&unary.expr)?),
+        Expr::Paren(paren) => eval(&paren.expr),
+        Expr::Binary(binary) => {
+            let (left, right) = (eval(&binary.left)?, eval(&binary.right)?);
+            match binary.op {
+                BinOp::Add(_) => Ok(left + right),
+                BinOp::Sub(_) => Ok(left - right),
+                BinOp::Mul(_) => Ok(left * right),
+                BinOp::Div(_) => Ok(left / right),
+                _ => Err(Error::new(binary.span(), "expected +, -, * or /")),
+            }
+        }
+        _ => Err(Error::new(expr.span(), "expected a number")),
+    }
+}
+
+fn float_literal(value: f64, ty: &Type) -> Literal {
+    if type_name(ty).as_deref() == Some("f64") {
+        Literal::f64_suffixed(value)
+    } else {
+        Literal::f32_suffixed(value as f32)
+    }
+}
+
+impl WireField {
+    fn size(&self) -> usize {
+        byte_size(&self.raw.to_string()).unwrap_or(0)
+    }
+
+    fn end(&self) -> usize {
+        self.offset + self.size()
+    }
+
+    /// DBC start bit and size. Big-endian signals start at their most significant bit.
+    fn dbc_bits(&self) -> (u32, u32) {
+        let (offset, size) = (self.offset as u32, self.size() as u32);
+        let (low, high) = self.bits.unwrap_or((0, size * 8));
+        match self.endian {
+            Endian::Little => (offset * 8 + low, high - low),
+            Endian::Big => {
+                let msb = high - 1;
+                ((offset + size - 1 - msb / 8) * 8 + msb % 8, high - low)
+            }
+        }
+    }
+
+    /// The field as a `Signal` literal.
+    fn dbc_signal(&self) -> TokenStream2 {
+        let dbc = quote! { ::havendrive::drivers::can::dbc };
+        let name = self.ident.to_string();
+        let (start_bit, size) = self.dbc_bits();
+        let order = match self.endian {
+            Endian::Little => quote! { #dbc::ByteOrder::LittleEndian },
+            Endian::Big => quote! { #dbc::ByteOrder::BigEndian },
+        };
+        let raw = self.raw.to_string();
+        let value_type = match raw.as_str() {
+            "f32" => quote! { #dbc::ValueType::Float32 },
+            "f64" => quote! { #dbc::ValueType::Float64 },
+            _ if raw.starts_with('i') => quote! { #dbc::ValueType::Signed },
+            _ => quote! { #dbc::ValueType::Unsigned },
+        };
+        let mut overrides = Vec::new();
+        if let Some(scale) = self.scale {
+            let scale = Literal::f64_suffixed(scale);
+            overrides.push(quote! { factor: #scale, });
+        }
+        if let Some(bias) = self.bias {
+            let bias = Literal::f64_suffixed(bias);
+            overrides.push(quote! { offset: #bias, });
+        }
+        if let Some(unit) = &self.unit {
+            overrides.push(quote! { unit: #unit.to_string(), });
+        }
+        if self.is_enum {
+            let (ty, raw) = (&self.ty, &self.raw);
+            overrides.push(quote! { values: ::havendrive::drivers::can::dbc_export::value_names(|v: #raw| <#ty>::try_from(v).ok()), });
+        }
+        if let Some(doc) = &self.doc {
+            overrides.push(quote! { comment: Some(#doc.to_string()), });
+        }
+        quote! {
+            #dbc::Signal {
+                #(#overrides)*
+                ..#dbc::Signal::new(#name, #start_bit, #size, #order, #value_type)
+            }
+        }
+    }
+
+    /// Reads the field from `msg`, propagating a `DecodeError`.
+    fn decode(&self) -> TokenStream2 {
+        let (raw, ty, offset, size) = (&self.raw, &self.ty, self.offset, self.size());
+        let from_bytes = self.endian.decode_fn();
+        let mut value = quote! { #raw::#from_bytes(msg.bytes::<#size>(#offset)?) };
+        if let Some((start, end)) = self.bits {
+            let mask = Literal::u64_unsuffixed((1u64 << (end - start) as u64).wrapping_sub(1));
+            let start = Literal::u32_unsuffixed(start);
+            value = quote! { ((#value >> #start) & #mask) };
+        }
+        if type_name(ty).as_deref() == Some("bool") {
+            quote! { #value != 0 }
+        } else if self.is_enum {
+            quote! { <#ty>::try_from(#value)? }
+        } else if self.scale.is_some() || self.bias.is_some() {
+            let scale = self.scale.map(|scale| {
+                let scale = float_literal(scale, ty);
+                quote! { * #scale }
+            });
+            let bias = self.bias.map(|bias| {
+                let bias = float_literal(bias, ty);
+                quote! { + #bias }
+            });
+            quote! { (#value as #ty) #scale #bias }
+        } else if type_name(ty).as_deref() != Some(&raw.to_string()) {
+            quote! { #value as #ty }
+        } else {
+            value
+        }
+    }
+
+    /// The field as its `raw` type, ready to be written.
+    fn encode(&self) -> TokenStream2 {
+        let (raw, ty, ident) = (&self.raw, &self.ty, &self.ident);
+        if self.scale.is_some() || self.bias.is_some() {
+            let unbiased = match self.bias {
+                Some(bias) => {
+                    let bias = float_literal(bias, ty);
+                    quote! { (self.#ident - #bias) }
+                }
+                None => quote! { self.#ident },
+            };
+            let inverse = self.scale.map(|scale| {
+                let inverse = float_literal(1.0 / scale, ty);
+                quote! { * #inverse }
+            });
+            // Rounded like the DBC codegen does; `as` saturates to the raw type's range,
+            // and a bit field is held to its width.
+            let rounded = quote! { (#unbiased #inverse).round() as #raw };
+            match self.bits {
+                Some((start, end)) => {
+                    let max = Literal::u64_unsuffixed((1u64 << (end - start) as u64).wrapping_sub(1));
+                    quote! { (#rounded).min(#max) }
+                }
+                None => rounded,
+            }
+        } else if type_name(ty).as_deref() == Some(&raw.to_string()) {
+            quote! { self.#ident }
+        } else {
+            quote! { self.#ident as #raw }
+        }
+    }
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let attrs = parse_message_attrs(input)?;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new(input.ident.span(), "CanMessage needs named fields")),
+        },
+        _ => return Err(Error::new(input.ident.span(), "CanMessage can only be derived for structs")),
+    };
+    let mut base_ty = None;
+    let mut skipped = Vec::new();
+    let mut wire = Vec::new();
+    for field in fields {
+        match parse_field(field, attrs.endian)? {
+            FieldKind::Base(ty) => base_ty = Some(ty),
+            FieldKind::Skip => skipped.push(field.ident.clone()),
+            FieldKind::Wire(field) => wire.push(*field),
+        }
+    }
+    let base_ty = base_ty.ok_or_else(|| Error::new(input.ident.span(), "CanMessage needs a `base` field holding the protocol's base message"))?;
+
+    let can = quote! { ::havendrive::drivers::can::messages };
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let cmd_id = Literal::u32_unsuffixed(attrs.cmd_id);
+    let cmd_byte = Literal::u8_suffixed(attrs.cmd_id as u8);
+
+    let fields_end = wire.iter().map(WireField::end).max().unwrap_or(0);
+    let (min_len, default_len) = match attrs.protocol {
+        Protocol::Odrive => (fields_end, fields_end),
+        Protocol::MyActuator => (fields_end.max(1), fields_end.max(8)),
+        Protocol::X424 => (fields_end.max(1), fields_end.max(1)),
+    };
+    let len = attrs.len.unwrap_or(default_len);
+    if len < fields_end {
+        return Err(Error::new(Span::call_site(), format!("len {} is shorter than the fields, which end at byte {}", len, fields_end)));
+    }
+
+    let (matches, filters, arb) = match attrs.protocol {
+        Protocol::Odrive => (
+            quote! { #can::OdriveArbitrationId::for_cmd(msg, Self::cmd_id()).is_ok() },
+            quote! { #can::OdriveArbitrationId::can_filters(Self::cmd_id(), node_id) },
+            quote! { let arb = #can::OdriveArbitrationId::for_cmd(&msg, Self::cmd_id())?; },
+        ),
+        Protocol::MyActuator => (
+            quote! { #can::MyActuatorArbitrationId::for_cmd(msg, Self::cmd_id()).is_ok() },
+            quote! { #can::MyActuatorArbitrationId::can_filters(node_id) },
+            quote! { let arb = #can::MyActuatorArbitrationId::for_cmd(&msg, Self::cmd_id())?; },
+        ),
+        Protocol::X424 => (
+            quote! { msg.data.first().is_some_and(|&d| (d >> 5) as u32 == Self::cmd_id()) },
+            quote! { #can::X424ArbitrationId::can_filters(node_id) },
+            quote! {
+                msg.require_len(1)?;
+                #can::DecodeError::check_command(#cmd_byte, msg.data[0] >> 5)?;
+                let arb = #can::X424ArbitrationId::from_can_message(&msg);
+            },
+        ),
+    };
+
+    // MyActuator replies come from 0x240 + id, so a parsed reply still names its node.
+    let refresh_node = (attrs.protocol == Protocol::MyActuator).then(|| {
+        quote! { self.base.node_id = #can::MyActuatorArbitrationId::from_can_message(msg)?.node_id; }
+    });
+
+    let idents: Vec<&Ident> = wire.iter().map(|f| &f.ident).collect();
+    let decoded: Vec<TokenStream2> = wire.iter().map(WireField::decode).collect();
+
+    let mut writes = Vec::new();
+    if attrs.protocol == Protocol::MyActuator {
+        writes.push(quote! { data[0] = #cmd_byte; });
+    }
+    let mut words: Vec<(usize, Ident, Endian, Vec<TokenStream2>)> = Vec::new();
+    for field in &wire {
+        let encoded = field.encode();
+        match field.bits {
+            None => {
+                let (offset, end, to_bytes) = (field.offset, field.end(), field.endian.encode_fn());
+                writes.push(quote! { data[#offset..#end].copy_from_slice(&(#encoded).#to_bytes()); });
+            }
+            Some((start, end)) => {
+                let mask = Literal::u64_unsuffixed((1u64 << (end - start) as u64).wrapping_sub(1));
+                let start = Literal::u32_unsuffixed(start);
+                let part = quote! { ((#encoded) & #mask) << #start };
+                match words.iter_mut().find(|(offset, raw, _, _)| *offset == field.offset && *raw == field.raw) {
+                    Some(word) => word.3.push(part),
+                    None => words.push((field.offset, field.raw.clone(), field.endian, vec![part])),
+                }
+            }
+        }
+    }
+    for (offset, raw, endian, parts) in &words {
+        let end = offset + byte_size(&raw.to_string()).unwrap_or(0);
+        let to_bytes = endian.encode_fn();
+        writes.push(quote! {
+            let word: #raw = #(#parts)|*;
+            data[#offset..#end].copy_from_slice(&word.#to_bytes());
+        });
+    }
+    if attrs.protocol == Protocol::X424 {
+        writes.push(quote! { data[0] |= #cmd_byte << 5; });
+    }
+
+    let signals: Vec<TokenStream2> = wire.iter().map(WireField::dbc_signal).collect();
+    let dbc_len = u8::try_from(len).map_err(|_| Error::new(Span::call_site(), "len must fit in a u8"))?;
+    let dbc_name = name.to_string();
+
+    let priority = attrs.priority.map(|p| {
+        quote! {
+            fn tx_priority(&self) -> ::havendrive::drivers::can::tx_queue::TxPriority {
+                ::havendrive::drivers::can::tx_queue::TxPriority::#p
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics #can::CanMessageTrait for #name #ty_generics #where_clause {
+            fn cmd_id() -> u32 { #cmd_id }
+
+            fn node_id(&self) -> u32 { #can::CanMessageTrait::node_id(&self.base) }
+
+            fn timestamp(&self) -> Option<#can::RxTimestamp> { #can::CanMessageTrait::timestamp(&self.base) }
+
+            fn set_timestamp(&mut self, timestamp: Option<#can::RxTimestamp>) {
+                #can::CanMessageTrait::set_timestamp(&mut self.base, timestamp)
+            }
+
+            fn matches(msg: &#can::RawCanMessage) -> bool { #matches }
+
+            fn can_filters(node_id: Option<u32>) -> Vec<#can::CanIdFilter> { #filters }
+
+            fn try_from_can_message(msg: #can::RawCanMessage) -> Result<Self, #can::DecodeError> {
+                #arb
+                msg.require_len(#min_len)?;
+                Ok(Self {
+                    base: <#base_ty>::new(arb.node_id, Self::cmd_id()),
+                    #(#idents: #decoded,)*
+                    #(#skipped: Default::default(),)*
+                })
+            }
+
+            #priority
+
+            fn gen_arbitration_id(&self) -> #can::ArbitrationId { #can::CanMessageTrait::gen_arbitration_id(&self.base) }
+
+            fn gen_can_msg_data(&self) -> Vec<u8> {
+                let mut data = vec![0u8; #len];
+                #(#writes)*
+                data
+            }
+
+            fn parse_can_msg_data(&mut self, msg: &#can::RawCanMessage) -> Result<(), #can::DecodeError> {
+                msg.require_len(#min_len)?;
+                #(self.#idents = #decoded;)*
+                #refresh_node
+                Ok(())
+            }
+        }
+
+        impl #impl_generics ::havendrive::drivers::can::dbc_export::DbcLayout for #name #ty_generics #where_clause {
+            const DBC_NAME: &'static str = #dbc_name;
+
+            const DBC_LEN: u8 = #dbc_len;
+
+            fn dbc_signals() -> Vec<::havendrive::drivers::can::dbc::Signal> {
+                vec![#(#signals),*]
+            }
+        }
+    })
+}
@@ -0,0 +1,168 @@
+//! Periodic transmission through the SocketCAN broadcast manager (CAN_BCM).
+//!
+//! The kernel sends the frame from an hrtimer, so the period does not depend on
+//! the scheduling of this process. Every task gets its own BCM socket: tasks
+//! never share a BCM op, and closing the socket removes the op.
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use super::messages::RawCanMessage;
+use super::periodic::PeriodicTx;
+
+// Opcodes and flags from linux/can/bcm.h.
+const TX_SETUP: u32 = 1;
+const TX_DELETE: u32 = 2;
+const SETTIMER: u32 = 0x0001;
+const STARTTIMER: u32 = 0x0002;
+const TX_ANNOUNCE: u32 = 0x0008;
+const CAN_FD_FRAME: u32 = 0x0800;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct BcmTimeval {
+    tv_sec: libc::c_long,
+    tv_usec: libc::c_long,
+}
+
+const NO_TIMER: BcmTimeval = BcmTimeval { tv_sec: 0, tv_usec: 0 };
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct BcmMsgHead {
+    opcode: u32,
+    flags: u32,
+    count: u32,
+    ival1: BcmTimeval,
+    ival2: BcmTimeval,
+    can_id: u32,
+    nframes: u32,
+}
+
+/// A `bcm_msg_head` followed by a single `can_frame` or `canfd_frame`.
+#[repr(C)]
+struct BcmMsg<F> {
+    head: BcmMsgHead,
+    frame: F,
+}
+
+pub struct BcmTask {
+    socket: OwnedFd,
+    can_id: u32,
+    fd_flag: u32,
+    cancelled: AtomicBool,
+}
+
+impl BcmTask {
+    /// Starts sending `msg` on `channel` now and every `period` after.
+    pub fn start(channel: &str, msg: &RawCanMessage, period: Duration) -> Result<Self> {
+        if period < Duration::from_micros(1) {
+            return Err(anyhow!("BCM period must be at least 1µs, got {:?}", period));
+        }
+        let task = Self {
+            socket: Self::connect(channel)?,
+            can_id: Self::can_id(msg),
+            fd_flag: if msg.is_fd { CAN_FD_FRAME } else { 0 },
+            cancelled: AtomicBool::new(false),
+        };
+        let ival2 = BcmTimeval { tv_sec: period.as_secs() as libc::c_long, tv_usec: period.subsec_micros() as libc::c_long };
+        task.write_op(TX_SETUP, SETTIMER | STARTTIMER | TX_ANNOUNCE, ival2, Some(msg))?;
+        Ok(task)
+    }
+
+    fn connect(channel: &str) -> Result<OwnedFd> {
+        let name = CString::new(channel)?;
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(anyhow!("No CAN interface {}: {}", channel, io::Error::last_os_error()));
+        }
+        let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::CAN_BCM) };
+        if fd < 0 {
+            return Err(anyhow!("Failed to open BCM socket: {}", io::Error::last_os_error()));
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut addr: libc::sockaddr_can = unsafe { mem::zeroed() };
+        addr.can_family = libc::AF_CAN as libc::sa_family_t;
+        addr.can_ifindex = ifindex as libc::c_int;
+        let res = unsafe {
+            libc::connect(
+                socket.as_raw_fd(),
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of_val(&addr) as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(anyhow!("Failed to connect BCM socket to {}: {}", channel, io::Error::last_os_error()));
+        }
+        Ok(socket)
+    }
+
+    fn can_id(msg: &RawCanMessage) -> u32 {
+        if msg.is_extended_id {
+            msg.arbitration_id | libc::CAN_EFF_FLAG
+        } else {
+            msg.arbitration_id
+        }
+    }
+
+    fn write_op(&self, opcode: u32, flags: u32, ival2: BcmTimeval, msg: Option<&RawCanMessage>) -> Result<()> {
+        let head = BcmMsgHead {
+            opcode,
+            flags: flags | self.fd_flag,
+            count: 0,
+            ival1: NO_TIMER,
+            ival2,
+            can_id: self.can_id,
+            nframes: msg.is_some() as u32,
+        };
+        match msg {
+            None => self.write(&head),
+            Some(msg) if msg.is_fd => {
+                let mut frame: libc::canfd_frame = unsafe { mem::zeroed() };
+                frame.can_id = self.can_id;
+                frame.len = msg.data.len() as u8;
+                frame.flags = (if msg.bitrate_switch { libc::CANFD_BRS } else { 0 } | if msg.error_state_indicator { libc::CANFD_ESI } else { 0 }) as u8;
+                frame.data[..msg.data.len()].copy_from_slice(&msg.data);
+                self.write(&BcmMsg { head, frame })
+            }
+            Some(msg) => {
+                let mut frame: libc::can_frame = unsafe { mem::zeroed() };
+                frame.can_id = self.can_id;
+                frame.can_dlc = msg.data.len() as u8;
+                frame.data[..msg.data.len()].copy_from_slice(&msg.data);
+                self.write(&BcmMsg { head, frame })
+            }
+        }
+    }
+
+    fn write<T>(&self, op: &T) -> Result<()> {
+        let len = mem::size_of::<T>();
+        let n = unsafe { libc::write(self.socket.as_raw_fd(), op as *const T as *const libc::c_void, len) };
+        if n < 0 {
+            return Err(anyhow!("BCM write failed: {}", io::Error::last_os_error()));
+        }
+        if n as usize != len {
+            return Err(anyhow!("Short BCM write: {} of {} bytes", n, len));
+        }
+        Ok(())
+    }
+}
+
+impl PeriodicTx for BcmTask {
+    fn update(&self, msg: &RawCanMessage) -> Result<()> {
+        // TX_SETUP without SETTIMER swaps the frame and leaves the timer running.
+        self.write_op(TX_SETUP, 0, NO_TIMER, Some(msg))
+    }
+
+    fn cancel(&self) -> Result<()> {
+        if self.cancelled.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.write_op(TX_DELETE, 0, NO_TIMER, None)
+    }
+}
@@ -0,0 +1,287 @@
+//! Controller error reporting.
+//!
+//! SocketCAN reports controller problems as error frames whose id carries a set
+//! of `CAN_ERR_*` class bits and whose payload carries the details (see
+//! `linux/can/error.h`). They are decoded here into `BusErrorFrame`s, and the
+//! connection turns them into `BusEvent`s for listeners.
+use std::fmt;
+
+// Error classes in the id of an error frame.
+const CAN_ERR_TX_TIMEOUT: u32 = 0x0001;
+const CAN_ERR_LOSTARB: u32 = 0x0002;
+const CAN_ERR_CRTL: u32 = 0x0004;
+const CAN_ERR_PROT: u32 = 0x0008;
+const CAN_ERR_TRX: u32 = 0x0010;
+const CAN_ERR_ACK: u32 = 0x0020;
+const CAN_ERR_BUSOFF: u32 = 0x0040;
+const CAN_ERR_BUSERROR: u32 = 0x0080;
+const CAN_ERR_RESTARTED: u32 = 0x0100;
+const CAN_ERR_CNT: u32 = 0x0200;
+
+/// All error classes, as passed to `CAN_RAW_ERR_FILTER`.
+pub const CAN_ERR_MASK_ALL: u32 = 0x1FFF_FFFF;
+
+// Controller status, data[1] of a CAN_ERR_CRTL frame.
+const CAN_ERR_CRTL_RX_OVERFLOW: u8 = 0x01;
+const CAN_ERR_CRTL_TX_OVERFLOW: u8 = 0x02;
+pub const CAN_ERR_CRTL_RX_WARNING: u8 = 0x04;
+pub const CAN_ERR_CRTL_TX_WARNING: u8 = 0x08;
+pub const CAN_ERR_CRTL_RX_PASSIVE: u8 = 0x10;
+pub const CAN_ERR_CRTL_TX_PASSIVE: u8 = 0x20;
+pub const CAN_ERR_CRTL_ACTIVE: u8 = 0x40;
+
+const CAN_ERR_LOSTARB_UNSPEC: u8 = 0x00;
+
+/// Fault confinement state of the controller, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum BusState {
+    #[default]
+    ErrorActive,
+    /// An error counter passed 96.
+    ErrorWarning,
+    /// An error counter passed 127; the node may no longer signal errors actively.
+    ErrorPassive,
+    /// The transmit error counter passed 255; the node is off the bus until restarted.
+    BusOff,
+}
+
+impl BusState {
+    pub fn value(&self) -> &'static str {
+        match self {
+            BusState::ErrorActive => "error-active",
+            BusState::ErrorWarning => "error-warning",
+            BusState::ErrorPassive => "error-passive",
+            BusState::BusOff => "bus-off",
+        }
+    }
+}
+
+impl fmt::Display for BusState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.value())
+    }
+}
+
+/// Transmit and receive error counters (TEC/REC) as reported by the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ErrorCounters {
+    pub tx: u8,
+    pub rx: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BusErrorKind {
+    TxTimeout,
+    /// Lost arbitration at `bit`, if the controller reports it.
+    ArbitrationLost { bit: Option<u8> },
+    RxOverflow,
+    TxOverflow,
+    /// A protocol violation; `kind` and `location` are the raw `CAN_ERR_PROT_*` bytes.
+    ProtocolViolation { kind: u8, location: u8 },
+    /// A transceiver fault; the raw `CAN_ERR_TRX_*` byte.
+    TransceiverError(u8),
+    /// Nobody acknowledged a transmitted frame, e.g. no other node is listening.
+    AckError,
+    BusOff,
+    BusError,
+    /// The controller was restarted after bus-off.
+    Restarted,
+}
+
+/// A decoded SocketCAN error frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusErrorFrame {
+    pub kinds: Vec<BusErrorKind>,
+    /// Controller status bits (`CAN_ERR_CRTL_*`), zero if not reported.
+    pub controller: u8,
+    pub counters: Option<ErrorCounters>,
+}
+
+impl BusErrorFrame {
+    /// Decodes an error frame from its error class bits and payload.
+    pub fn decode(error_bits: u32, data: &[u8]) -> Self {
+        let byte = |i: usize| data.get(i).copied().unwrap_or(0);
+        let mut kinds = Vec::new();
+        if error_bits & CAN_ERR_TX_TIMEOUT != 0 {
+            kinds.push(BusErrorKind::TxTimeout);
+        }
+        if error_bits & CAN_ERR_LOSTARB != 0 {
+            let bit = byte(0);
+            kinds.push(BusErrorKind::ArbitrationLost { bit: (bit != CAN_ERR_LOSTARB_UNSPEC).then_some(bit) });
+        }
+        let controller = if error_bits & CAN_ERR_CRTL != 0 { byte(1) } else { 0 };
+        if controller & CAN_ERR_CRTL_RX_OVERFLOW != 0 {
+            kinds.push(BusErrorKind::RxOverflow);
+        }
+        if controller & CAN_ERR_CRTL_TX_OVERFLOW != 0 {
+            kinds.push(BusErrorKind::TxOverflow);
+        }
+        if error_bits & CAN_ERR_PROT != 0 {
+            kinds.push(BusErrorKind::ProtocolViolation { kind: byte(2), location: byte(3) });
+        }
+        if error_bits & CAN_ERR_TRX != 0 {
+            kinds.push(BusErrorKind::TransceiverError(byte(4)));
+        }
+        if error_bits & CAN_ERR_ACK != 0 {
+            kinds.push(BusErrorKind::AckError);
+        }
+        if error_bits & CAN_ERR_BUSOFF != 0 {
+            kinds.push(BusErrorKind::BusOff);
+        }
+        if error_bits & CAN_ERR_BUSERROR != 0 {
+            kinds.push(BusErrorKind::BusError);
+        }
+        if error_bits & CAN_ERR_RESTARTED != 0 {
+            kinds.push(BusErrorKind::Restarted);
+        }
+        let counters = (error_bits & CAN_ERR_CNT != 0).then(|| ErrorCounters { tx: byte(6), rx: byte(7) });
+        Self { kinds, controller, counters }
+    }
+
+    /// Encodes the frame back into error class bits and an 8-byte payload.
+    pub fn encode(&self) -> (u32, [u8; 8]) {
+        let mut bits = 0;
+        let mut data = [0u8; 8];
+        for kind in &self.kinds {
+            match kind {
+                BusErrorKind::TxTimeout => bits |= CAN_ERR_TX_TIMEOUT,
+                BusErrorKind::ArbitrationLost { bit } => {
+                    bits |= CAN_ERR_LOSTARB;
+                    data[0] = bit.unwrap_or(CAN_ERR_LOSTARB_UNSPEC);
+                }
+                BusErrorKind::RxOverflow | BusErrorKind::TxOverflow => {}
+                BusErrorKind::ProtocolViolation { kind, location } => {
+                    bits |= CAN_ERR_PROT;
+                    data[2] = *kind;
+                    data[3] = *location;
+                }
+                BusErrorKind::TransceiverError(status) => {
+                    bits |= CAN_ERR_TRX;
+                    data[4] = *status;
+                }
+                BusErrorKind::AckError => bits |= CAN_ERR_ACK,
+                BusErrorKind::BusOff => bits |= CAN_ERR_BUSOFF,
+                BusErrorKind::BusError => bits |= CAN_ERR_BUSERROR,
+                BusErrorKind::Restarted => bits |= CAN_ERR_RESTARTED,
+            }
+        }
+        let controller = self.controller
+            | if self.kinds.contains(&BusErrorKind::RxOverflow) { CAN_ERR_CRTL_RX_OVERFLOW } else { 0 }
+            | if self.kinds.contains(&BusErrorKind::TxOverflow) { CAN_ERR_CRTL_TX_OVERFLOW } else { 0 };
+        if controller != 0 {
+            bits |= CAN_ERR_CRTL;
+            data[1] = controller;
+        }
+        if let Some(counters) = self.counters {
+            bits |= CAN_ERR_CNT;
+            data[6] = counters.tx;
+            data[7] = counters.rx;
+        }
+        (bits, data)
+    }
+
+    /// A frame reporting only `kind`.
+    pub fn single(kind: BusErrorKind) -> Self {
+        Self { kinds: vec![kind], controller: 0, counters: None }
+    }
+
+    /// A controller status report for `state`, e.g. to simulate a degrading bus.
+    pub fn state_change(state: BusState, counters: ErrorCounters) -> Self {
+        let (kinds, controller) = match state {
+            BusState::ErrorActive => (vec![], CAN_ERR_CRTL_ACTIVE),
+            BusState::ErrorWarning => (vec![], CAN_ERR_CRTL_RX_WARNING | CAN_ERR_CRTL_TX_WARNING),
+            BusState::ErrorPassive => (vec![], CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE),
+            BusState::BusOff => (vec![BusErrorKind::BusOff], 0),
+        };
+        Self { kinds, controller, counters: Some(counters) }
+    }
+
+    pub fn has(&self, kind: &BusErrorKind) -> bool {
+        self.kinds.contains(kind)
+    }
+
+    /// The controller state this frame reports, if it reports one.
+    pub fn state(&self) -> Option<BusState> {
+        if self.has(&BusErrorKind::BusOff) {
+            Some(BusState::BusOff)
+        } else if self.controller & (CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE) != 0 {
+            Some(BusState::ErrorPassive)
+        } else if self.controller & (CAN_ERR_CRTL_RX_WARNING | CAN_ERR_CRTL_TX_WARNING) != 0 {
+            Some(BusState::ErrorWarning)
+        } else if self.controller & CAN_ERR_CRTL_ACTIVE != 0 || self.has(&BusErrorKind::Restarted) {
+            Some(BusState::ErrorActive)
+        } else {
+            // Counters alone say nothing: they can be low while the controller is still bus-off.
+            None
+        }
+    }
+}
+
+/// Something that happened to the bus itself rather than a frame on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BusEvent {
+    /// The controller reported an error.
+    Error(BusErrorFrame),
+    StateChanged { from: BusState, to: BusState },
+    /// The transport failed; the connection keeps trying to reopen it.
+    TransportLost { error: String },
+    TransportReopened,
+    /// The transport failed and cannot be reopened. No more frames will arrive.
+    Closed { error: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_error_frames() {
+        // Lost arbitration at bit 5, rx passive, a bit error in the data field, counters 10/140.
+        let bits = CAN_ERR_LOSTARB | CAN_ERR_CRTL | CAN_ERR_PROT | CAN_ERR_CNT;
+        let frame = BusErrorFrame::decode(bits, &[5, CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_RX_OVERFLOW, 0x01, 0x0A, 0, 0, 10, 140]);
+        assert_eq!(frame.kinds, [BusErrorKind::ArbitrationLost { bit: Some(5) }, BusErrorKind::RxOverflow, BusErrorKind::ProtocolViolation { kind: 0x01, location: 0x0A }]);
+        assert_eq!(frame.controller, CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_RX_OVERFLOW);
+        assert_eq!(frame.counters, Some(ErrorCounters { tx: 10, rx: 140 }));
+        assert_eq!(frame.state(), Some(BusState::ErrorPassive));
+
+        // Payload bytes of classes that are not set are ignored, and short payloads read as zero.
+        let frame = BusErrorFrame::decode(CAN_ERR_ACK | CAN_ERR_LOSTARB, &[0]);
+        assert_eq!(frame, BusErrorFrame { kinds: vec![BusErrorKind::ArbitrationLost { bit: None }, BusErrorKind::AckError], controller: 0, counters: None });
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let frames = [
+            BusErrorFrame::decode(CAN_ERR_TX_TIMEOUT | CAN_ERR_TRX | CAN_ERR_BUSERROR, &[0, 0, 0, 0, 0x04, 0, 0, 0]),
+            BusErrorFrame::decode(CAN_ERR_LOSTARB | CAN_ERR_CRTL | CAN_ERR_CNT, &[3, CAN_ERR_CRTL_TX_OVERFLOW | CAN_ERR_CRTL_TX_WARNING, 0, 0, 0, 0, 100, 0]),
+            BusErrorFrame::decode(CAN_ERR_BUSOFF | CAN_ERR_RESTARTED, &[]),
+            BusErrorFrame::single(BusErrorKind::ProtocolViolation { kind: 0x10, location: 0x08 }),
+        ];
+        for frame in frames {
+            let (bits, data) = frame.encode();
+            assert_eq!(BusErrorFrame::decode(bits, &data), frame);
+        }
+    }
+
+    #[test]
+    fn state_changes_report_their_state() {
+        let counters = ErrorCounters { tx: 130, rx: 5 };
+        for state in [BusState::ErrorActive, BusState::ErrorWarning, BusState::ErrorPassive, BusState::BusOff] {
+            let frame = BusErrorFrame::state_change(state, counters);
+            assert_eq!(frame.state(), Some(state));
+            let (bits, data) = frame.encode();
+            let decoded = BusErrorFrame::decode(bits, &data);
+            assert_eq!((decoded.state(), decoded.counters), (Some(state), Some(counters)));
+        }
+        assert_eq!(BusErrorFrame::single(BusErrorKind::Restarted).state(), Some(BusState::ErrorActive));
+    }
+
+    #[test]
+    fn counters_alone_report_no_state() {
+        // A bus-off controller reports low counters until it is restarted.
+        let frame = BusErrorFrame { kinds: vec![BusErrorKind::AckError], controller: 0, counters: Some(ErrorCounters { tx: 0, rx: 0 }) };
+        assert_eq!(frame.state(), None);
+        let frame = BusErrorFrame { kinds: vec![], controller: 0, counters: Some(ErrorCounters { tx: 200, rx: 0 }) };
+        assert_eq!(frame.state(), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! Several CAN buses in one process.
+//!
+//! A robot may spread its actuators over `can0..canN`. `BusManager` owns one
+//! `CanSimple` per interface and hands out `DeviceHandle`s that remember which
+//! bus their node lives on.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use super::connection::CanSimple;
+use super::enums::{BusType, CanInterface};
+use super::messages::CanMessageTrait;
+use super::subscription::Subscription;
+
+#[derive(Default)]
+pub struct BusManager {
+    buses: BTreeMap<String, Arc<CanSimple>>,
+}
+
+impl BusManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens `interface` and registers it under its interface name. Returns the
+    /// already open bus if there is one.
+    pub fn open(&mut self, interface: impl Into<CanInterface>, bustype: BusType) -> Result<Arc<CanSimple>> {
+        let interface = interface.into();
+        let name = interface.value().to_string();
+        if let Some(bus) = self.buses.get(&name) {
+            return Ok(bus.clone());
+        }
+        let bus = Arc::new(CanSimple::new(interface, bustype)?);
+        self.buses.insert(name, bus.clone());
+        Ok(bus)
+    }
+
+    /// Registers an already constructed bus under `name`, replacing any previous one.
+    pub fn insert(&mut self, name: &str, bus: CanSimple) -> Arc<CanSimple> {
+        let bus = Arc::new(bus);
+        self.buses.insert(name.to_string(), bus.clone());
+        bus
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<CanSimple>> {
+        self.buses.get(name).cloned()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.buses.keys().map(String::as_str)
+    }
+
+    /// A handle for `node_id` on the bus registered as `bus`.
+    pub fn device(&self, bus: &str, node_id: u32) -> Result<DeviceHandle> {
+        let can = self.get(bus).ok_or_else(|| anyhow!("No CAN bus named {}", bus))?;
+        Ok(DeviceHandle { bus_name: bus.to_string(), bus: can, node_id })
+    }
+
+    /// Shuts down every bus. Handles still holding a bus see it closed.
+    pub async fn shutdown(self) {
+        for bus in self.buses.values() {
+            bus.shutdown().await;
+        }
+    }
+}
+
+/// One node on one bus.
+#[derive(Clone)]
+pub struct DeviceHandle {
+    bus_name: String,
+    bus: Arc<CanSimple>,
+    node_id: u32,
+}
+
+impl DeviceHandle {
+    pub fn bus_name(&self) -> &str {
+        &self.bus_name
+    }
+
+    pub fn bus(&self) -> &Arc<CanSimple> {
+        &self.bus
+    }
+
+    pub fn node_id(&self) -> u32 {
+        self.node_id
+    }
+
+    pub async fn send(&self, msg: impl CanMessageTrait) -> Result<()> {
+        self.check_node(msg.node_id())?;
+        self.bus.send(msg).await
+    }
+
+    pub async fn request<Req, Resp>(&self, msg: Req, timeout: Duration) -> Result<Resp>
+    where
+        Req: CanMessageTrait,
+        Resp: CanMessageTrait + 'static,
+    {
+        self.check_node(msg.node_id())?;
+        self.bus.request(msg, timeout).await
+    }
+
+    /// Every `T` this node sends.
+    pub fn subscribe<T: CanMessageTrait + 'static>(&self) -> Subscription<T> {
+        self.bus.subscribe_node(self.node_id)
+    }
+
+    fn check_node(&self, node_id: u32) -> Result<()> {
+        if node_id != self.node_id {
+            return Err(anyhow!("Message for node {} sent through the handle of node {} on {}", node_id, self.node_id, self.bus_name));
+        }
+        Ok(())
+    }
+}
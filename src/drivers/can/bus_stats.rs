@@ -0,0 +1,453 @@
+//! Rolling traffic statistics of a bus.
+//!
+//! A `CanSimple` counts every frame it receives and sends, per arbitration id and
+//! per node, and keeps rates over about the last `STATS_WINDOW`. Received means
+//! what gets past the transport's id filters, so unless every frame is let through
+//! (see `CanSimple::set_max_bus_load`) the figures cover only the subscribed ids
+//! and what is sent. Received frames
+//! of each id also feed an inter-arrival estimate: the mean interval, the jitter
+//! around it and the frames missing from gaps in a cyclic stream.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::messages::{RawCanMessage, RxTimestamp};
+use super::recorder::Direction;
+use super::tx_queue::TxStats;
+
+/// Time constant of the rates.
+pub const STATS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Gain of the interval and jitter estimates, as in RFC 3550.
+const INTERVAL_GAIN: f64 = 1.0 / 16.0;
+/// Intervals seen before gaps start counting as missed frames.
+const MIN_INTERVALS_FOR_GAPS: u64 = 8;
+/// An interval this many times the mean is a gap.
+const GAP_FACTOR: f64 = 1.5;
+
+const CRC15_POLY: u16 = 0x4599;
+
+/// Says which node a frame belongs to, e.g. `OdriveArbitrationId::from_can_message(raw).node_id`.
+pub type NodeIdFn = Box<dyn Fn(&RawCanMessage) -> Option<u32> + Send + Sync>;
+
+/// Bits a frame occupies on the wire at the nominal bitrate, counting the worst
+/// case of stuff bits and the interframe space. FD data phases are counted at the
+/// nominal bitrate too, so FD frames with bitrate switching come out high.
+pub fn frame_bits(raw: &RawCanMessage) -> u32 {
+    let n = raw.data.len() as u32;
+    if raw.is_fd {
+        // Stuff bits only in the arbitration phase; the CRC field carries its own fixed stuff bits.
+        let (header, crc) = (if raw.is_extended_id { 49 } else { 30 }, if n > 16 { 26 } else { 22 });
+        header + header / 4 + 8 * n + 8 * n / 4 + crc + 13
+    } else {
+        let stuffed = if raw.is_extended_id { 54 } else { 34 } + 8 * n;
+        stuffed + (stuffed - 1) / 4 + 13
+    }
+}
+
+/// Bits `raw` takes on the wire, stuff bits included.
+///
+/// Stuff bits of classic frames are counted exactly from the id, payload and CRC;
+/// FD frames get the worst case of `frame_bits`.
+pub fn wire_bits(raw: &RawCanMessage) -> u32 {
+    if raw.is_fd {
+        return frame_bits(raw);
+    }
+    // SOF to the end of the CRC, the part that is stuffed.
+    let mut bits = Vec::with_capacity(54 + 8 * raw.data.len() + 15);
+    let push = |bits: &mut Vec<bool>, value: u32, width: u32| (0..width).rev().for_each(|i| bits.push(value >> i & 1 == 1));
+    push(&mut bits, 0, 1);
+    if raw.is_extended_id {
+        push(&mut bits, raw.arbitration_id >> 18, 11);
+        // SRR and IDE recessive.
+        push(&mut bits, 0b11, 2);
+        push(&mut bits, raw.arbitration_id & 0x3FFFF, 18);
+        // RTR, r1, r0.
+        push(&mut bits, 0, 3);
+    } else {
+        push(&mut bits, raw.arbitration_id, 11);
+        // RTR, IDE, r0.
+        push(&mut bits, 0, 3);
+    }
+    push(&mut bits, raw.data.len() as u32, 4);
+    raw.data.iter().for_each(|b| push(&mut bits, *b as u32, 8));
+    let crc = bits.iter().fold(0u16, |crc, bit| {
+        let shifted = (crc << 1) & 0x7FFF;
+        if *bit != (crc >> 14 & 1 == 1) { shifted ^ CRC15_POLY } else { shifted }
+    });
+    push(&mut bits, crc as u32, 15);
+
+    let mut stuffed = 0;
+    let (mut run, mut level) = (0, false);
+    for bit in bits.iter() {
+        if run == 5 {
+            // The stuff bit is the opposite level and starts the next run.
+            stuffed += 1;
+            (run, level) = (1, !level);
+        }
+        if *bit == level {
+            run += 1;
+        } else {
+            (run, level) = (1, *bit);
+        }
+    }
+    if run == 5 {
+        stuffed += 1;
+    }
+    // CRC delimiter, ACK slot and delimiter, end of frame and interframe space.
+    bits.len() as u32 + stuffed + 13
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrafficStats {
+    pub rx_frames: u64,
+    pub tx_frames: u64,
+    pub rx_bits: u64,
+    pub tx_bits: u64,
+    pub frames_per_sec: f64,
+    pub bits_per_sec: f64,
+    /// `bits_per_sec` as a fraction of the bitrate; of the frames counted, not
+    /// necessarily of the whole bus.
+    pub utilization: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdStats {
+    pub arbitration_id: u32,
+    pub is_extended_id: bool,
+    pub traffic: TrafficStats,
+    /// Mean time between received frames, once two have been received.
+    pub mean_interval: Option<Duration>,
+    /// Mean deviation of the intervals from `mean_interval`.
+    pub jitter: Option<Duration>,
+    pub max_interval: Option<Duration>,
+    /// Frames that should have arrived in gaps of the stream, going by `mean_interval`.
+    pub missed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeStats {
+    pub node_id: u32,
+    pub traffic: TrafficStats,
+}
+
+/// Everything `CanSimple::bus_stats` knows about the traffic, at one point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusStatsSnapshot {
+    pub bitrate: u32,
+    /// Since the statistics were started or last reset.
+    pub elapsed: Duration,
+    pub total: TrafficStats,
+    /// The highest `total.utilization` reached.
+    pub peak_utilization: f64,
+    /// By arbitration id, standard ids first.
+    pub ids: Vec<IdStats>,
+    /// By node id, counting only frames that could be attributed to a node.
+    pub nodes: Vec<NodeStats>,
+    /// Frames the transport failed to send.
+    pub tx_failed: u64,
+    /// Frames the transmit queue dropped or turned away, among its other counters.
+    pub tx_queue: TxStats,
+    /// Received frames that listeners and subscriptions fell too far behind to get.
+    pub rx_lagged: u64,
+    /// Received frames that listeners and subscriptions could not decode.
+    pub rx_decode_errors: u64,
+}
+
+/// Frames and bits decaying with `STATS_WINDOW`, plus running totals.
+struct Counter {
+    rx_frames: u64,
+    tx_frames: u64,
+    rx_bits: u64,
+    tx_bits: u64,
+    frames: f64,
+    bits: f64,
+    last: Instant,
+}
+
+impl Counter {
+    fn new(now: Instant) -> Self {
+        Self { rx_frames: 0, tx_frames: 0, rx_bits: 0, tx_bits: 0, frames: 0.0, bits: 0.0, last: now }
+    }
+
+    fn decay(&mut self, now: Instant) {
+        let factor = (-now.saturating_duration_since(self.last).as_secs_f64() / STATS_WINDOW.as_secs_f64()).exp();
+        self.frames *= factor;
+        self.bits *= factor;
+        self.last = now;
+    }
+
+    fn add(&mut self, now: Instant, direction: Direction, bits: u32) {
+        self.decay(now);
+        self.frames += 1.0;
+        self.bits += bits as f64;
+        match direction {
+            Direction::Rx => {
+                self.rx_frames += 1;
+                self.rx_bits += bits as u64;
+            }
+            Direction::Tx => {
+                self.tx_frames += 1;
+                self.tx_bits += bits as u64;
+            }
+        }
+    }
+
+    fn snapshot(&mut self, now: Instant, bitrate: u32) -> TrafficStats {
+        self.decay(now);
+        let window = STATS_WINDOW.as_secs_f64();
+        TrafficStats {
+            rx_frames: self.rx_frames,
+            tx_frames: self.tx_frames,
+            rx_bits: self.rx_bits,
+            tx_bits: self.tx_bits,
+            frames_per_sec: self.frames / window,
+            bits_per_sec: self.bits / window,
+            utilization: self.bits / window / bitrate as f64,
+        }
+    }
+}
+
+struct IdState {
+    counter: Counter,
+    last_rx: Option<RxTimestamp>,
+    intervals: u64,
+    mean_interval: f64,
+    jitter: f64,
+    max_interval: f64,
+    missed: u64,
+}
+
+impl IdState {
+    fn new(now: Instant) -> Self {
+        Self { counter: Counter::new(now), last_rx: None, intervals: 0, mean_interval: 0.0, jitter: 0.0, max_interval: 0.0, missed: 0 }
+    }
+
+    fn on_rx(&mut self, at: RxTimestamp) {
+        let Some(interval) = self.last_rx.replace(at).and_then(|last| at.duration_since(&last)) else { return };
+        let interval = interval.as_secs_f64();
+        self.max_interval = self.max_interval.max(interval);
+        if self.intervals >= MIN_INTERVALS_FOR_GAPS && interval > GAP_FACTOR * self.mean_interval {
+            // A gap, not a late frame: keep it out of the estimates.
+            self.missed += ((interval / self.mean_interval).round() as u64).saturating_sub(1).max(1);
+            return;
+        }
+        if self.intervals == 0 {
+            self.mean_interval = interval;
+        } else {
+            self.jitter += ((interval - self.mean_interval).abs() - self.jitter) * INTERVAL_GAIN;
+            self.mean_interval += (interval - self.mean_interval) * INTERVAL_GAIN;
+        }
+        self.intervals += 1;
+    }
+}
+
+struct StatsState {
+    started: Instant,
+    total: Counter,
+    peak_utilization: f64,
+    ids: HashMap<(bool, u32), IdState>,
+    nodes: HashMap<u32, Counter>,
+    tx_failed: u64,
+}
+
+impl StatsState {
+    fn new(now: Instant) -> Self {
+        Self { started: now, total: Counter::new(now), peak_utilization: 0.0, ids: HashMap::new(), nodes: HashMap::new(), tx_failed: 0 }
+    }
+}
+
+/// The statistics of one bus. `CanSimple` feeds it; see `CanSimple::bus_stats`.
+pub struct BusStats {
+    bitrate: u32,
+    node_id_fn: Mutex<Option<NodeIdFn>>,
+    state: Mutex<StatsState>,
+}
+
+impl BusStats {
+    pub fn new(bitrate: u32) -> Self {
+        Self { bitrate, node_id_fn: Mutex::new(None), state: Mutex::new(StatsState::new(Instant::now())) }
+    }
+
+    /// Sets how received frames, and sent frames queued without a node, are told apart by node.
+    pub fn set_node_id_fn(&self, node_id_fn: Option<NodeIdFn>) {
+        *self.node_id_fn.lock().unwrap() = node_id_fn;
+    }
+
+    /// Counts a frame. `node_id` overrides the node id function, as for frames sent to a node.
+    pub fn record(&self, direction: Direction, raw: &RawCanMessage, node_id: Option<u32>) {
+        let now = Instant::now();
+        let bits = wire_bits(raw);
+        let node_id = node_id.or_else(|| self.node_id_fn.lock().unwrap().as_ref().and_then(|f| f(raw)));
+        let mut state = self.state.lock().unwrap();
+        state.total.add(now, direction, bits);
+        let utilization = state.total.bits / STATS_WINDOW.as_secs_f64() / self.bitrate as f64;
+        state.peak_utilization = state.peak_utilization.max(utilization);
+        let id = state.ids.entry((raw.is_extended_id, raw.arbitration_id)).or_insert_with(|| IdState::new(now));
+        id.counter.add(now, direction, bits);
+        if direction == Direction::Rx {
+            id.on_rx(raw.timestamp.unwrap_or_else(RxTimestamp::now));
+        }
+        if let Some(node_id) = node_id {
+            state.nodes.entry(node_id).or_insert_with(|| Counter::new(now)).add(now, direction, bits);
+        }
+    }
+
+    /// The current total utilization of the frames counted, without building a whole snapshot.
+    pub fn utilization(&self) -> f64 {
+        self.state.lock().unwrap().total.snapshot(Instant::now(), self.bitrate).utilization
+    }
+
+    pub fn record_tx_failure(&self) {
+        self.state.lock().unwrap().tx_failed += 1;
+    }
+
+    /// The statistics so far. The queue and dispatch counters are left for the caller to fill in.
+    pub fn snapshot(&self) -> BusStatsSnapshot {
+        let now = Instant::now();
+        let bitrate = self.bitrate;
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let mut ids: Vec<IdStats> = state
+            .ids
+            .iter_mut()
+            .map(|(&(is_extended_id, arbitration_id), id)| {
+                let has_interval = id.intervals > 0;
+                IdStats {
+                    arbitration_id,
+                    is_extended_id,
+                    traffic: id.counter.snapshot(now, bitrate),
+                    mean_interval: has_interval.then(|| Duration::from_secs_f64(id.mean_interval)),
+                    jitter: has_interval.then(|| Duration::from_secs_f64(id.jitter)),
+                    max_interval: (id.max_interval > 0.0).then(|| Duration::from_secs_f64(id.max_interval)),
+                    missed: id.missed,
+                }
+            })
+            .collect();
+        ids.sort_by_key(|id| (id.is_extended_id, id.arbitration_id));
+        let mut nodes: Vec<NodeStats> =
+            state.nodes.iter_mut().map(|(&node_id, counter)| NodeStats { node_id, traffic: counter.snapshot(now, bitrate) }).collect();
+        nodes.sort_by_key(|node| node.node_id);
+        BusStatsSnapshot {
+            bitrate,
+            elapsed: now.saturating_duration_since(state.started),
+            total: state.total.snapshot(now, bitrate),
+            peak_utilization: state.peak_utilization,
+            ids,
+            nodes,
+            tx_failed: state.tx_failed,
+            tx_queue: TxStats::default(),
+            rx_lagged: 0,
+            rx_decode_errors: 0,
+        }
+    }
+
+    /// Starts over, keeping the node id function.
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = StatsState::new(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::can::messages::TimestampSource;
+
+    #[test]
+    fn wire_bits_counts_stuff_bits() {
+        // Id 0 without data is 34 dominant bits up to the end of the all-zero CRC:
+        // a stuff bit after every fifth, 6 in all, plus the 13 unstuffed bits after.
+        assert_eq!(wire_bits(&RawCanMessage::new(0, vec![])), 34 + 6 + 13);
+        // Stuff bits counted from each frame's bit stream and CRC.
+        assert_eq!(wire_bits(&RawCanMessage::new(0x123, vec![0xDE, 0xAD, 0xBE, 0xEF])), 66 + 2 + 13);
+        assert_eq!(wire_bits(&RawCanMessage::new(0x555, vec![0x55; 8])), 98 + 1 + 13);
+        assert_eq!(wire_bits(&RawCanMessage::new(0x7FF, vec![0xFF; 8])), 98 + 15 + 13);
+        assert_eq!(wire_bits(&RawCanMessage::new(0x601, vec![0x40, 0, 0x10, 0, 0, 0, 0, 0])), 98 + 13 + 13);
+        let extended = RawCanMessage { is_extended_id: true, ..RawCanMessage::new(0x1ABC_DEF0, vec![0; 8]) };
+        assert_eq!(wire_bits(&extended), 118 + 15 + 13);
+    }
+
+    #[test]
+    fn wire_bits_within_the_worst_case() {
+        for len in 0..=8 {
+            for (id, byte) in [(0, 0x00), (0x7FF, 0xFF), (0x555, 0xAA), (0x0F0, 0x0F)] {
+                let raw = RawCanMessage::new(id, vec![byte; len]);
+                let unstuffed = 34 + 8 * len as u32 + 13;
+                assert!((unstuffed..=frame_bits(&raw)).contains(&wire_bits(&raw)), "{:?}", raw);
+            }
+        }
+        let fd = RawCanMessage { is_fd: true, ..RawCanMessage::new(1, vec![0; 64]) };
+        assert_eq!(wire_bits(&fd), frame_bits(&fd));
+    }
+
+    #[test]
+    fn rates_decay_over_the_window() {
+        let start = Instant::now();
+        let mut counter = Counter::new(start);
+        counter.add(start, Direction::Rx, 600);
+        counter.add(start, Direction::Tx, 400);
+        let stats = counter.snapshot(start, 1000);
+        assert_eq!((stats.rx_frames, stats.tx_frames, stats.rx_bits, stats.tx_bits), (1, 1, 600, 400));
+        assert!((stats.utilization - 1.0).abs() < 1e-9);
+
+        let stats = counter.snapshot(start + STATS_WINDOW, 1000);
+        assert!((stats.bits_per_sec - 1000.0 / std::f64::consts::E).abs() < 1e-6);
+        assert!((stats.frames_per_sec - 2.0 / std::f64::consts::E).abs() < 1e-9);
+        assert_eq!(stats.rx_bits, 600);
+    }
+
+    fn at(ms: f64) -> RxTimestamp {
+        RxTimestamp { time: Duration::from_secs_f64(ms / 1000.0), source: TimestampSource::Kernel }
+    }
+
+    #[test]
+    fn interval_and_jitter() {
+        let mut id = IdState::new(Instant::now());
+        let mut t = 0.0;
+        id.on_rx(at(t));
+        for i in 0..64 {
+            t += if i % 2 == 0 { 8.0 } else { 12.0 };
+            id.on_rx(at(t));
+        }
+        assert_eq!(id.intervals, 64);
+        assert!((id.mean_interval - 0.010).abs() < 0.000_5, "{}", id.mean_interval);
+        // The first interval sets the mean, every later one is 2 ms off it.
+        assert!((id.jitter - 0.002 * (1.0 - (1.0 - INTERVAL_GAIN).powi(63))).abs() < 0.000_3, "{}", id.jitter);
+        assert!((id.max_interval - 0.012).abs() < 1e-9);
+        assert_eq!(id.missed, 0);
+    }
+
+    #[test]
+    fn gaps_count_missed_frames() {
+        let mut id = IdState::new(Instant::now());
+        let mut t = 0.0;
+        id.on_rx(at(t));
+        // Too few intervals yet to tell a gap.
+        t += 30.0;
+        id.on_rx(at(t));
+        assert_eq!((id.missed, id.mean_interval), (0, 0.030));
+
+        let mut id = IdState::new(Instant::now());
+        for _ in 0..=MIN_INTERVALS_FOR_GAPS {
+            id.on_rx(at(t));
+            t += 10.0;
+        }
+        // Two frames missing between these.
+        t += 20.0;
+        id.on_rx(at(t));
+        assert_eq!(id.missed, 2);
+        // Anything past `GAP_FACTOR` intervals misses at least one.
+        t += 16.0;
+        id.on_rx(at(t));
+        assert_eq!(id.missed, 3);
+        // Gaps stay out of the estimates; a late frame does not.
+        assert!((id.mean_interval - 0.010).abs() < 1e-9);
+        t += 14.0;
+        id.on_rx(at(t));
+        assert_eq!(id.missed, 3);
+        assert!(id.mean_interval > 0.010);
+        assert!((id.max_interval - 0.030).abs() < 1e-9);
+    }
+}
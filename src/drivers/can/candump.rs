@@ -0,0 +1,182 @@
+//! The `candump -l` log format.
+//!
+//! One frame per line: `(seconds.micros) interface frame`, where the frame is
+//! written as `cansend` takes it: `123#DEADBEEF` for classic frames, an 8 digit
+//! id for extended ones, `123##1DEADBEEF` for FD frames with their flags digit,
+//! and error frames with `CAN_ERR_FLAG` set in the id.
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+
+use super::bus_events::{BusErrorFrame, CAN_ERR_MASK_ALL};
+use super::messages::{RawCanMessage, RxTimestamp, TimestampSource};
+use super::recorder::{Direction, FrameSink};
+use super::transport::TransportEvent;
+
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+
+/// One line of a candump log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandumpEntry {
+    /// Since the Unix epoch.
+    pub time: Duration,
+    pub interface: String,
+    pub event: TransportEvent,
+}
+
+impl CandumpEntry {
+    pub fn format(&self) -> String {
+        let mut line = format!("({:010}.{:06}) {} ", self.time.as_secs(), self.time.subsec_micros(), self.interface);
+        let hex = |line: &mut String, data: &[u8]| data.iter().for_each(|b| write!(line, "{:02X}", b).unwrap());
+        match &self.event {
+            TransportEvent::Frame(raw) => {
+                if raw.is_extended_id {
+                    write!(line, "{:08X}", raw.arbitration_id).unwrap();
+                } else {
+                    write!(line, "{:03X}", raw.arbitration_id).unwrap();
+                }
+                if raw.is_fd {
+                    let flags = if raw.bitrate_switch { CANFD_BRS } else { 0 } | if raw.error_state_indicator { CANFD_ESI } else { 0 };
+                    write!(line, "##{:X}", flags).unwrap();
+                } else {
+                    line.push('#');
+                }
+                hex(&mut line, &raw.data);
+            }
+            TransportEvent::Error(frame) => {
+                let (bits, data) = frame.encode();
+                write!(line, "{:08X}#", bits | CAN_ERR_FLAG).unwrap();
+                hex(&mut line, &data);
+            }
+        }
+        line
+    }
+
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut fields = line.split_whitespace();
+        let (time, interface, frame) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(time), Some(interface), Some(frame)) => (time, interface, frame),
+            _ => return Err(anyhow!("Expected `(time) interface frame`, got {:?}", line)),
+        };
+        let time = time.strip_prefix('(').and_then(|t| t.strip_suffix(')')).ok_or_else(|| anyhow!("Bad timestamp {:?}", time))?;
+        let (secs, fraction) = time.split_once('.').ok_or_else(|| anyhow!("Bad timestamp {:?}", time))?;
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(anyhow!("Bad timestamp {:?}", time));
+        }
+        // candump writes microseconds, but take any precision up to nanoseconds.
+        let nanos = format!("{:0<9}", &fraction[..fraction.len().min(9)]).parse()?;
+        let time = Duration::new(secs.parse()?, nanos);
+
+        let (id, rest) = frame.split_once('#').ok_or_else(|| anyhow!("Bad frame {:?}", frame))?;
+        let id_value = u32::from_str_radix(id, 16).with_context(|| format!("Bad id in {:?}", frame))?;
+        let event = if id_value & CAN_ERR_FLAG != 0 {
+            TransportEvent::Error(BusErrorFrame::decode(id_value & CAN_ERR_MASK_ALL, &parse_hex(rest)?))
+        } else {
+            let mut raw = RawCanMessage { arbitration_id: id_value, is_extended_id: id.len() > 3, ..Default::default() };
+            if let Some(fd) = rest.strip_prefix('#') {
+                let flags = fd.get(..1).and_then(|f| u8::from_str_radix(f, 16).ok()).ok_or_else(|| anyhow!("Bad FD flags in {:?}", frame))?;
+                raw.is_fd = true;
+                raw.bitrate_switch = flags & CANFD_BRS != 0;
+                raw.error_state_indicator = flags & CANFD_ESI != 0;
+                raw.data = parse_hex(&fd[1..])?;
+            } else if !rest.starts_with('R') {
+                // Remote frames carry no data and come through as empty data frames.
+                raw.data = parse_hex(rest)?;
+            }
+            raw.timestamp = Some(RxTimestamp { time, source: TimestampSource::Kernel });
+            TransportEvent::Frame(raw)
+        };
+        Ok(Self { time, interface: interface.to_string(), event })
+    }
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = s.bytes().filter(|b| *b != b'.').collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(anyhow!("Odd number of hex digits in {:?}", s));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair)?, 16).map_err(|e| anyhow!("Bad data {:?}: {}", s, e)))
+        .collect()
+}
+
+/// Reads a whole log, skipping blank lines.
+pub fn read_log(path: impl AsRef<Path>) -> Result<Vec<CandumpEntry>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(CandumpEntry::parse(&line).with_context(|| format!("{}:{}", path.display(), i + 1))?);
+    }
+    Ok(entries)
+}
+
+/// Writes every frame it is given as a candump log line. Both directions go to
+/// the same log, as `candump -l` sees our own frames through the loopback too.
+pub struct CandumpWriter {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl CandumpWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self { out: Mutex::new(Box::new(out)) }
+    }
+}
+
+impl FrameSink for CandumpWriter {
+    fn record(&self, channel: &str, _direction: Direction, time: Duration, event: &TransportEvent) -> Result<()> {
+        let entry = CandumpEntry { time, interface: channel.to_string(), event: event.clone() };
+        writeln!(self.out.lock().unwrap(), "{}", entry.format())?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.out.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let raw = RawCanMessage { timestamp: Some(RxTimestamp { time: Duration::new(1_700_000_000, 123_456_000), source: TimestampSource::Kernel }), ..RawCanMessage::new(0x123, vec![0xDE, 0xAD]) };
+        let entry = CandumpEntry { time: Duration::new(1_700_000_000, 123_456_000), interface: "can0".to_string(), event: TransportEvent::Frame(raw) };
+        let line = entry.format();
+        assert_eq!(line, "(1700000000.123456) can0 123#DEAD");
+        assert_eq!(CandumpEntry::parse(&line).unwrap(), entry);
+    }
+
+    #[test]
+    fn timestamp_precision() {
+        assert_eq!(CandumpEntry::parse("(1.5) can0 123#").unwrap().time, Duration::from_millis(1_500));
+        assert_eq!(CandumpEntry::parse("(1.0123456789) can0 123#").unwrap().time, Duration::new(1, 12_345_678));
+    }
+
+    #[test]
+    fn bad_timestamps() {
+        for line in ["(1.2é) can0 123#", "(1.ééééé) can0 123#", "(1.-5) can0 123#", "(1.+5) can0 123#", "(1) can0 123#", "1.5 can0 123#"] {
+            assert!(CandumpEntry::parse(line).is_err(), "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,400 @@
+//! CAN over UDP in the cannelloni wire format.
+//!
+//! Every datagram starts with a five byte header: version 2, an op code, an
+//! 8-bit sequence number and a big-endian frame count. Each frame follows as its
+//! SocketCAN id with flags in network byte order and its length, which for FD
+//! frames has `0x80` set and is followed by a flags byte, then the payload.
+use std::collections::VecDeque;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::net::UdpSocket;
+use tokio::time::{self, Instant};
+
+use super::bus_events::{BusErrorFrame, CAN_ERR_MASK_ALL};
+use super::messages::{CanIdFilter, RawCanMessage, RxTimestamp, CANFD_VALID_DLENS, CAN_MAX_DLEN};
+use super::transport::{CanTransport, TransportEvent, TransportFuture};
+
+/// The port cannelloni uses on both ends unless told otherwise.
+pub const CANNELLONI_PORT: u16 = 20000;
+
+const VERSION: u8 = 2;
+const OP_DATA: u8 = 0;
+const HEADER_LEN: usize = 5;
+/// cannelloni fills datagrams up to the Ethernet MTU.
+const MAX_PACKET: usize = 1500;
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+/// Set in the length byte of an FD frame.
+const CANFD_FRAME: u8 = 0x80;
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+
+/// Sequence gaps at least this large are taken for late packets rather than lost ones.
+const REORDER_WINDOW: u8 = 128;
+
+/// Traffic counters of a `CannelloniTransport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CannelloniStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    /// Packets skipped in the sequence and not seen since.
+    pub packets_lost: u64,
+    /// Packets that arrived after a later one.
+    pub packets_reordered: u64,
+    /// Datagrams that did not parse.
+    pub malformed: u64,
+    /// Times the peer's port was closed, e.g. while it restarts.
+    pub peer_unreachable: u64,
+}
+
+struct RxState {
+    stats: CannelloniStats,
+    expected_seq: Option<u8>,
+    /// Events of the last datagram not handed out yet.
+    pending: VecDeque<TransportEvent>,
+}
+
+impl RxState {
+    fn track(&mut self, seq: u8) {
+        let stats = &mut self.stats;
+        stats.packets_received += 1;
+        if let Some(expected) = self.expected_seq {
+            let gap = seq.wrapping_sub(expected);
+            if gap >= REORDER_WINDOW {
+                // Counted as lost when it was skipped.
+                stats.packets_reordered += 1;
+                stats.packets_lost = stats.packets_lost.saturating_sub(1);
+                return;
+            }
+            stats.packets_lost += gap as u64;
+        }
+        self.expected_seq = Some(seq.wrapping_add(1));
+    }
+}
+
+/// `CanTransport` to a cannelloni peer, e.g. a `can_udp_gateway` on a robot.
+///
+/// The socket is connected to the peer, so datagrams from anyone else are
+/// ignored. Every frame goes out in its own datagram to keep latency down; frames
+/// are received in datagrams of any size. Filters are applied in software.
+pub struct CannelloniTransport {
+    channel: String,
+    remote: SocketAddr,
+    socket: UdpSocket,
+    tx_seq: AtomicU8,
+    rx: Mutex<RxState>,
+    filters: Mutex<Option<Vec<CanIdFilter>>>,
+}
+
+impl CannelloniTransport {
+    /// Must be called from within a tokio runtime.
+    pub fn bind(local: SocketAddr, remote: SocketAddr) -> Result<Self> {
+        let socket = std::net::UdpSocket::bind(local).with_context(|| format!("Failed to bind UDP socket to {}", local))?;
+        socket.connect(remote).with_context(|| format!("Failed to connect UDP socket to {}", remote))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            channel: format!("{},{}", local, remote),
+            remote,
+            socket: UdpSocket::from_std(socket)?,
+            tx_seq: AtomicU8::new(0),
+            rx: Mutex::new(RxState { stats: Default::default(), expected_seq: None, pending: VecDeque::new() }),
+            filters: Mutex::new(None),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote
+    }
+
+    pub fn stats(&self) -> CannelloniStats {
+        self.rx.lock().unwrap().stats
+    }
+
+    /// Sends an error frame as well as a data frame, for gateways forwarding a whole bus.
+    pub async fn send_event(&self, event: &TransportEvent) -> Result<()> {
+        let seq = self.tx_seq.fetch_add(1, Ordering::Relaxed);
+        let packet = Self::encode_packet(seq, std::slice::from_ref(event))?;
+        match self.socket.send(&packet).await {
+            Ok(_) => {}
+            // The refusal of an earlier datagram; this one went out.
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => self.rx.lock().unwrap().stats.peer_unreachable += 1,
+            Err(e) => return Err(anyhow!("UDP send to {} failed: {}", self.remote, e)),
+        }
+        let mut rx = self.rx.lock().unwrap();
+        rx.stats.packets_sent += 1;
+        rx.stats.frames_sent += 1;
+        Ok(())
+    }
+
+    pub fn encode_packet(seq: u8, events: &[TransportEvent]) -> Result<Vec<u8>> {
+        let count = u16::try_from(events.len()).map_err(|_| anyhow!("Too many frames for one packet: {}", events.len()))?;
+        let mut packet = Vec::with_capacity(HEADER_LEN + events.len() * 16);
+        packet.extend_from_slice(&[VERSION, OP_DATA, seq]);
+        packet.extend_from_slice(&count.to_be_bytes());
+        for event in events {
+            match event {
+                TransportEvent::Frame(raw) => {
+                    raw.validate()?;
+                    let id = raw.arbitration_id | if raw.is_extended_id { CAN_EFF_FLAG } else { 0 };
+                    packet.extend_from_slice(&id.to_be_bytes());
+                    if raw.is_fd {
+                        packet.push(raw.data.len() as u8 | CANFD_FRAME);
+                        packet.push(if raw.bitrate_switch { CANFD_BRS } else { 0 } | if raw.error_state_indicator { CANFD_ESI } else { 0 });
+                    } else {
+                        packet.push(raw.data.len() as u8);
+                    }
+                    packet.extend_from_slice(&raw.data);
+                }
+                TransportEvent::Error(frame) => {
+                    let (bits, data) = frame.encode();
+                    packet.extend_from_slice(&(bits | CAN_ERR_FLAG).to_be_bytes());
+                    packet.push(data.len() as u8);
+                    packet.extend_from_slice(&data);
+                }
+            }
+        }
+        Ok(packet)
+    }
+
+    /// Parses a data packet into its sequence number and frames. Remote frames
+    /// come out as empty data frames.
+    pub fn decode_packet(packet: &[u8]) -> Result<(u8, Vec<TransportEvent>)> {
+        let header = packet.get(..HEADER_LEN).ok_or_else(|| anyhow!("Packet of {} bytes is too short", packet.len()))?;
+        if header[0] != VERSION {
+            return Err(anyhow!("Unsupported cannelloni version {}", header[0]));
+        }
+        if header[1] != OP_DATA {
+            return Err(anyhow!("Unexpected cannelloni op code {}", header[1]));
+        }
+        let seq = header[2];
+        let count = u16::from_be_bytes([header[3], header[4]]);
+        let mut rest = &packet[HEADER_LEN..];
+        let mut take = |n: usize| -> Result<&[u8]> {
+            let taken = rest.get(..n).ok_or_else(|| anyhow!("Packet {} ends inside a frame", seq))?;
+            rest = &rest[n..];
+            Ok(taken)
+        };
+        let mut events = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let id = u32::from_be_bytes(take(4)?.try_into().unwrap());
+            let len = take(1)?[0];
+            let is_fd = len & CANFD_FRAME != 0;
+            let flags = if is_fd { take(1)?[0] } else { 0 };
+            let len = (len & !CANFD_FRAME) as usize;
+            if id & CAN_ERR_FLAG != 0 {
+                events.push(TransportEvent::Error(BusErrorFrame::decode(id & CAN_ERR_MASK_ALL, take(len)?)));
+                continue;
+            }
+            let max_len = if is_fd { CANFD_VALID_DLENS[CANFD_VALID_DLENS.len() - 1] } else { CAN_MAX_DLEN };
+            if len > max_len {
+                return Err(anyhow!("Frame of {} bytes in packet {}", len, seq));
+            }
+            let data = if id & CAN_RTR_FLAG != 0 { Vec::new() } else { take(len)?.to_vec() };
+            let is_extended_id = id & CAN_EFF_FLAG != 0;
+            events.push(TransportEvent::Frame(RawCanMessage {
+                arbitration_id: id & if is_extended_id { CAN_EFF_MASK } else { 0x7FF },
+                data,
+                is_extended_id,
+                is_fd,
+                bitrate_switch: flags & CANFD_BRS != 0,
+                error_state_indicator: flags & CANFD_ESI != 0,
+                timestamp: None,
+            }));
+        }
+        Ok((seq, events))
+    }
+
+    /// Takes the next event of the last datagram that passes the filters.
+    fn take_pending(&self) -> Option<TransportEvent> {
+        let filters = self.filters.lock().unwrap().clone();
+        let mut rx = self.rx.lock().unwrap();
+        while let Some(event) = rx.pending.pop_front() {
+            match &event {
+                TransportEvent::Frame(raw) if !filters.as_ref().is_none_or(|filters| filters.iter().any(|f| f.accepts_frame(raw))) => {}
+                _ => return Some(event),
+            }
+        }
+        None
+    }
+}
+
+impl CanTransport for CannelloniTransport {
+    /// Opens `local,remote`, or just `remote` to listen on the remote's port on all interfaces.
+    fn open(channel: &str) -> Result<Self> {
+        let resolve = |addr: &str| -> Result<SocketAddr> {
+            addr.to_socket_addrs()?.next().ok_or_else(|| anyhow!("{} does not resolve to an address", addr))
+        };
+        let (local, remote) = match channel.split_once(',') {
+            Some((local, remote)) => (resolve(local.trim())?, resolve(remote.trim())?),
+            None => {
+                let remote = resolve(channel)?;
+                let any: SocketAddr = if remote.is_ipv4() { (Ipv4Addr::UNSPECIFIED, remote.port()).into() } else { (Ipv6Addr::UNSPECIFIED, remote.port()).into() };
+                (any, remote)
+            }
+        };
+        Self::bind(local, remote)
+    }
+
+    fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    fn send<'a>(&'a self, msg: &'a RawCanMessage) -> TransportFuture<'a, ()> {
+        Box::pin(async move { self.send_event(&TransportEvent::Frame(msg.clone())).await })
+    }
+
+    fn recv(&self, timeout: Duration) -> TransportFuture<'_, Option<TransportEvent>> {
+        Box::pin(async move {
+            let deadline = Instant::now() + timeout;
+            let mut buf = [0u8; MAX_PACKET];
+            loop {
+                if let Some(event) = self.take_pending() {
+                    return Ok(Some(event));
+                }
+                let n = match time::timeout_at(deadline, self.socket.recv(&mut buf)).await {
+                    Err(_) => return Ok(None),
+                    Ok(Ok(n)) => n,
+                    Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                        self.rx.lock().unwrap().stats.peer_unreachable += 1;
+                        continue;
+                    }
+                    Ok(Err(e)) => return Err(anyhow!("UDP receive from {} failed: {}", self.remote, e)),
+                };
+                let mut rx = self.rx.lock().unwrap();
+                match Self::decode_packet(&buf[..n]) {
+                    Ok((seq, events)) => {
+                        rx.track(seq);
+                        rx.stats.frames_received += events.len() as u64;
+                        let now = RxTimestamp::now();
+                        rx.pending.extend(events.into_iter().map(|mut event| {
+                            if let TransportEvent::Frame(raw) = &mut event {
+                                raw.timestamp = Some(now);
+                            }
+                            event
+                        }));
+                    }
+                    Err(e) => {
+                        rx.stats.malformed += 1;
+                        log::debug!("Ignoring a packet from {}: {}", self.remote, e);
+                    }
+                }
+            }
+        })
+    }
+
+    fn set_filters(&self, filters: &[CanIdFilter]) -> Result<()> {
+        *self.filters.lock().unwrap() = Some(filters.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::can::bus_events::{BusErrorKind, ErrorCounters, CAN_ERR_CRTL_RX_WARNING};
+
+    fn error_frame() -> BusErrorFrame {
+        BusErrorFrame {
+            kinds: vec![BusErrorKind::ArbitrationLost { bit: Some(3) }, BusErrorKind::AckError],
+            controller: CAN_ERR_CRTL_RX_WARNING,
+            counters: Some(ErrorCounters { tx: 100, rx: 5 }),
+        }
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let events = vec![
+            TransportEvent::Frame(RawCanMessage::new(0x123, vec![1, 2, 3])),
+            TransportEvent::Frame(RawCanMessage { is_extended_id: true, ..RawCanMessage::new(0x1ABC_DEF0, vec![]) }),
+            TransportEvent::Frame(RawCanMessage { is_fd: true, bitrate_switch: true, error_state_indicator: true, ..RawCanMessage::new(0x7FF, vec![0xAA; 48]) }),
+            TransportEvent::Error(error_frame()),
+        ];
+        let packet = CannelloniTransport::encode_packet(0xFE, &events).unwrap();
+        assert_eq!(packet[..HEADER_LEN], [VERSION, OP_DATA, 0xFE, 0, 4]);
+        assert_eq!(packet[HEADER_LEN..HEADER_LEN + 8], [0, 0, 0x01, 0x23, 3, 1, 2, 3]);
+        assert_eq!(CannelloniTransport::decode_packet(&packet).unwrap(), (0xFE, events));
+    }
+
+    #[test]
+    fn bad_packets() {
+        let packet = CannelloniTransport::encode_packet(0, &[TransportEvent::Frame(RawCanMessage::new(1, vec![0; 8]))]).unwrap();
+        assert!(CannelloniTransport::decode_packet(&packet[..packet.len() - 1]).is_err());
+        assert!(CannelloniTransport::decode_packet(&packet[..HEADER_LEN - 1]).is_err());
+        let mut wrong_version = packet.clone();
+        wrong_version[0] = 1;
+        assert!(CannelloniTransport::decode_packet(&wrong_version).is_err());
+        let mut too_long = packet.clone();
+        too_long[HEADER_LEN + 4] = 9;
+        assert!(CannelloniTransport::decode_packet(&too_long).is_err());
+
+        let remote = [VERSION, OP_DATA, 0, 0, 1, 0x40, 0, 0x01, 0x23, 4];
+        assert_eq!(CannelloniTransport::decode_packet(&remote).unwrap().1, [TransportEvent::Frame(RawCanMessage::new(0x123, vec![]))]);
+    }
+
+    #[test]
+    fn sequence_tracking_across_the_wrap() {
+        let mut rx = RxState { stats: Default::default(), expected_seq: None, pending: VecDeque::new() };
+        for seq in [253, 254, 255, 1, 3] {
+            rx.track(seq);
+        }
+        // 0 and 2 were skipped.
+        assert_eq!((rx.stats.packets_lost, rx.stats.packets_reordered), (2, 0));
+        rx.track(0);
+        assert_eq!((rx.stats.packets_lost, rx.stats.packets_reordered), (1, 1));
+        rx.track(2);
+        assert_eq!((rx.stats.packets_lost, rx.stats.packets_reordered), (0, 2));
+        rx.track(4);
+        assert_eq!(rx.stats.packets_received, 8);
+        assert_eq!((rx.stats.packets_lost, rx.stats.packets_reordered), (0, 2));
+        assert_eq!(rx.expected_seq, Some(5));
+    }
+
+    fn free_local_addr() -> SocketAddr {
+        std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap().local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn localhost_pair() {
+        let (a_addr, b_addr) = (free_local_addr(), free_local_addr());
+        let a = CannelloniTransport::bind(a_addr, b_addr).unwrap();
+        let b = CannelloniTransport::open(&format!("{},{}", b_addr, a_addr)).unwrap();
+
+        let frame = RawCanMessage::new(0x123, vec![1, 2, 3]);
+        a.send(&frame).await.unwrap();
+        let Some(TransportEvent::Frame(received)) = b.recv(Duration::from_secs(1)).await.unwrap() else {
+            panic!("no frame received");
+        };
+        assert!(received.same_frame(&frame));
+        assert!(received.timestamp.is_some());
+
+        b.set_filters(&[CanIdFilter::exact(0x321)]).unwrap();
+        a.send(&frame).await.unwrap();
+        let fd = RawCanMessage { is_fd: true, ..RawCanMessage::new(0x321, vec![7; 12]) };
+        a.send(&fd).await.unwrap();
+        a.send_event(&TransportEvent::Error(error_frame())).await.unwrap();
+        let Some(TransportEvent::Frame(received)) = b.recv(Duration::from_secs(1)).await.unwrap() else {
+            panic!("no frame received");
+        };
+        assert!(received.same_frame(&fd));
+        assert_eq!(b.recv(Duration::from_secs(1)).await.unwrap(), Some(TransportEvent::Error(error_frame())));
+        assert_eq!(b.recv(Duration::from_millis(50)).await.unwrap(), None);
+
+        let (a_stats, b_stats) = (a.stats(), b.stats());
+        assert_eq!((a_stats.packets_sent, a_stats.frames_sent), (4, 4));
+        assert_eq!((b_stats.packets_received, b_stats.frames_received, b_stats.packets_lost), (4, 4, 0));
+    }
+}
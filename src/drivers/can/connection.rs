@@ -1,99 +1,129 @@
+use std::fmt;
+use std::future::Future;
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex as StdMutex};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex as StdMutex, Weak};
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
-use socketcan::{CanFrame, CanSocket, EmbeddedFrame, ExtendedId, StandardId};
 use tokio::sync::broadcast;
-use tokio::sync::mpsc;
-use tokio::sync::Mutex;
+use tokio::sync::watch;
 use tokio::task::JoinHandle;
 use tokio::time;
 
-use super::enums::{BusType, CanInterface};
-use super::messages::{CanMessageTrait, RawCanMessage};
+use super::bus_events::{BusErrorFrame, BusErrorKind, BusEvent, BusState, ErrorCounters};
+use super::bus_stats::{BusStats, BusStatsSnapshot, NodeIdFn};
+use super::dispatcher::{Dispatcher, OverflowPolicy, RouteReceiver, RouteStats};
+use super::enums::{BusType, CanInterface, ValueTypes};
+use super::messages::{CanIdFilter, CanMessageTrait, DecodeError, RawCanMessage};
+use super::odrive_msgs::{ParameterResponse, ReadParameterCommand, Value};
+use super::netlink::{self, InterfaceConfig};
+use super::periodic::{PeriodicHandle, PeriodicTx};
+use super::recorder::{Direction, FrameSink, RecordingTransport};
+use super::slcan::SlcanTransport;
+use super::socketcan_transport::SocketCanTransport;
+use super::subscription::Subscription;
+use super::tx_queue::{TxPriority, TxQueue};
+use super::transport::{CanTransport, TransportEvent};
+use super::virtual_bus::{VirtualBus, VirtualBusPort};
 
 use log;
 
- const BAUDRATE: u32 = 1_000_000;
+pub const BAUDRATE: u32 = 1_000_000;
 
-#[derive(Debug)]
-enum Command {
-    Send(CanFrame),
-    Shutdown,
+const RECV_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// How often a failed transport is reopened.
+const REOPEN_INTERVAL: Duration = Duration::from_millis(500);
+
+const BUS_EVENT_QUEUE_SIZE: usize = 64;
+
+/// Returned (inside `anyhow::Error`) when `CanSimple::request` gets no matching reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestTimeout {
+    pub node_id: u32,
+    pub cmd_id: u32,
+    pub attempts: u32,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for RequestTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no reply with cmd_id 0x{:02X} from node {} after {} attempt(s) of {:?}",
+            self.cmd_id, self.node_id, self.attempts, self.timeout
+        )
+    }
 }
 
+impl std::error::Error for RequestTimeout {}
+
+pub type MessageCallback<T> = Box<dyn Fn(T) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync + 'static>;
+
 pub trait DynamicCanListener {
-    fn on_message_received(&self, msg: &RawCanMessage);
-    fn on_error(&self, exc: anyhow::Error);
+    fn on_bus_event(&self, event: &BusEvent);
     fn stop(&self);
-    fn listen(&self, rx: broadcast::Receiver<RawCanMessage>) -> JoinHandle<Result<()>>;
+    fn listen(self: Arc<Self>) -> JoinHandle<Result<()>>;
 }
 
+/// Runs a callback for every `T` routed to it by the `CanSimple` it was registered on.
 pub struct CanSimpleListener<T: CanMessageTrait + Send + 'static> {
     _phantom: PhantomData<T>,
-    callback: Option<Box<dyn Fn(T) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync + 'static>>,
-    queue_tx: mpsc::Sender<RawCanMessage>,
-    queue_rx: Mutex<mpsc::Receiver<RawCanMessage>>,
-    bus_error: Mutex<Option<anyhow::Error>>,
-    is_stopped: AtomicBool,
+    callback: Option<MessageCallback<T>>,
+    route: RouteReceiver,
+    bus_error: StdMutex<Option<anyhow::Error>>,
 }
 
 impl<T: CanMessageTrait + Send + 'static> CanSimpleListener<T> {
-    pub fn new(_phantom: PhantomData<T>, callback: Option<Box<dyn Fn(T) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync + 'static>>) -> Self {
-        let (queue_tx, queue_rx) = mpsc::channel(32);
+    pub fn new(_phantom: PhantomData<T>, callback: Option<MessageCallback<T>>, route: RouteReceiver) -> Self {
         Self {
             _phantom,
             callback,
-            queue_tx,
-            queue_rx: Mutex::new(queue_rx),
-            bus_error: Mutex::new(None),
-            is_stopped: AtomicBool::new(false),
+            route,
+            bus_error: StdMutex::new(None),
         }
     }
 
-    pub async fn get_message(&self) -> T {
-        let mut rx = self.queue_rx.lock().await;
-        let raw = rx.recv().await.unwrap();
-        T::from_can_message(raw)
+    /// Waits for the next `T`. `None` once the listener is stopped or the bus is gone.
+    pub async fn get_message(&self) -> Option<T> {
+        self.route.recv_message().await
     }
 
     pub async fn wait_for_message(&self, duration: Duration) -> Option<T> {
-        let fut = self.get_message();
-        time::timeout(duration, fut).await.ok()
+        time::timeout(duration, self.get_message()).await.ok().flatten()
     }
-}
 
-impl<T: CanMessageTrait + Send + 'static> DynamicCanListener for CanSimpleListener<T> {
-    fn on_message_received(&self, msg: &RawCanMessage) {
-        if T::matches(msg) {
-            let _ = self.queue_tx.blocking_send(msg.clone());
-        }
+    /// Number of frames dropped because the callback could not keep up.
+    pub fn lagged(&self) -> u64 {
+        self.route.lagged()
     }
+    /// Number of frames that matched `T` but could not be decoded.
+    pub fn decode_errors(&self) -> u64 {
+        self.route.decode_errors()
+    }
+}
 
-    fn on_error(&self, exc: anyhow::Error) {
-        *self.bus_error.blocking_lock() = Some(exc);
+impl<T: CanMessageTrait + Send + Sync + 'static> DynamicCanListener for CanSimpleListener<T> {
+    fn on_bus_event(&self, event: &BusEvent) {
+        if let BusEvent::Closed { error } = event {
+            *self.bus_error.lock().unwrap() = Some(anyhow!("CAN bus closed: {}", error));
+        }
     }
 
     fn stop(&self) {
-        self.is_stopped.store(true, Ordering::Relaxed);
+        self.route.close();
     }
 
-    fn listen(&self, mut rx: broadcast::Receiver<RawCanMessage>) -> JoinHandle<Result<()>> {
-        let self_arc = Arc::new(self.clone()); // if Clone impl, but for simplicity assume
+    fn listen(self: Arc<Self>) -> JoinHandle<Result<()>> {
         tokio::spawn(async move {
-            while let None = *self_arc.bus_error.lock().await {
-                if self_arc.is_stopped.load(Ordering::Relaxed) {
-                    break;
-                }
-                if let Ok(msg) = time::timeout(Duration::from_millis(10), self_arc.get_message()).await {
-                    if let Some(cb) = &self_arc.callback {
-                        (cb)(msg).await;
-                    }
+            while let Some(msg) = self.get_message().await {
+                if let Some(cb) = &self.callback {
+                    (cb)(msg).await;
                 }
             }
-            if let Some(err) = self_arc.bus_error.lock().await.take() {
+            let bus_error = self.bus_error.lock().unwrap().take();
+            if let Some(err) = bus_error {
                 Err(err)
             } else {
                 Ok(())
@@ -102,118 +132,552 @@ impl<T: CanMessageTrait + Send + 'static> DynamicCanListener for CanSimpleListen
     }
 }
 
-pub struct CanSimple {
-    command_tx: mpsc::Sender<Command>,
-    broadcast_tx: broadcast::Sender<RawCanMessage>,
-    join_handle: JoinHandle<()>,
-    listeners: Arc<std::sync::Mutex<Vec<Arc<dyn DynamicCanListener + Send + Sync>>>>,
+type Listeners = Arc<StdMutex<Vec<Arc<dyn DynamicCanListener + Send + Sync>>>>;
+
+/// Controller state as last reported, and the fan-out of `BusEvent`s.
+#[derive(Clone)]
+struct BusMonitor {
+    status: Arc<StdMutex<(BusState, Option<ErrorCounters>)>>,
+    events: broadcast::Sender<BusEvent>,
+    listeners: Listeners,
 }
 
-impl CanSimple {
-    pub fn new(can_interface: CanInterface, bustype: BusType) -> Self {
-        let channel = can_interface.value();
-        let (command_tx, mut command_rx) = mpsc::channel(32);
-        let (broadcast_tx, _) = broadcast::channel(256);
-        let listeners = Arc::new(StdMutex::new(Vec::new()));
-        let join_handle = tokio::task::spawn_blocking({
-            let broadcast_tx = broadcast_tx.clone();
-            let listeners = listeners.clone();
-            move || {
-                let mut cs = CanSocket::open(channel).expect("Failed to open CAN socket");
-                // Flush bus
-                while cs.recv_timeout(Duration::ZERO).is_ok() {}
+impl BusMonitor {
+    fn emit(&self, event: BusEvent) {
+        for l in &*self.listeners.lock().unwrap() {
+            l.on_bus_event(&event);
+        }
+        let _ = self.events.send(event);
+    }
+
+    fn set_state(&self, to: BusState) {
+        let from = std::mem::replace(&mut self.status.lock().unwrap().0, to);
+        if from != to {
+            log::info!("CAN bus state {} -> {}", from, to);
+            self.emit(BusEvent::StateChanged { from, to });
+        }
+    }
+
+    fn on_error_frame(&self, frame: BusErrorFrame) {
+        if let Some(counters) = frame.counters {
+            self.status.lock().unwrap().1 = Some(counters);
+        } else if frame.has(&BusErrorKind::Restarted) {
+            // Recovering from bus-off clears both counters.
+            self.status.lock().unwrap().1 = Some(ErrorCounters::default());
+        }
+        let state = frame.state();
+        self.emit(BusEvent::Error(frame));
+        if let Some(state) = state {
+            self.set_state(state);
+        }
+    }
+}
+
+/// Userspace fallback for `CanSimple::send_periodic`, for transports that cannot keep time.
+struct IntervalTx {
+    msg: Arc<StdMutex<RawCanMessage>>,
+    task: JoinHandle<()>,
+}
+
+impl IntervalTx {
+    fn spawn(tx: Arc<TxQueue>, msg: RawCanMessage, priority: TxPriority, node_id: Option<u32>, period: Duration) -> Self {
+        let msg = Arc::new(StdMutex::new(msg));
+        let task = tokio::spawn({
+            let msg = msg.clone();
+            async move {
+                // A fixed-rate interval rather than sleeping after each send, so the
+                // period does not stretch by the time spent queueing the frame.
+                let mut interval = time::interval(period);
+                interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
                 loop {
-                    let frame_res = cs.recv_timeout(Duration::from_millis(10));
-                    match frame_res {
-                        Ok(frame) => {
-                            let raw = Self::frame_to_raw(&frame);
-                            let _ = broadcast_tx.send(raw);
-                        }
-                        Err(socketcan::Error::Timeout) => {},
-                        Err(e) => {
-                            let g = listeners.lock().unwrap();
-                            for l in &*g {
-                                l.on_error(anyhow!(e));
-                            }
+                    interval.tick().await;
+                    let raw = msg.lock().unwrap().clone();
+                    if let Err(e) = tx.push(raw, priority, node_id).await {
+                        if tx.is_closed() {
                             break;
                         }
-                    }
-                    while let Some(cmd) = command_rx.try_recv() {
-                        match cmd {
-                            Command::Send(f) => {
-                                if let Err(e) = cs.write(&f) {
-                                    log::error!("Error sending frame: {}", e);
-                                }
-                            }
-                            Command::Shutdown => return,
-                        }
+                        log::warn!("Skipped a periodic frame: {}", e);
                     }
                 }
             }
         });
+        Self { msg, task }
+    }
+}
+
+impl PeriodicTx for IntervalTx {
+    fn update(&self, msg: &RawCanMessage) -> Result<()> {
+        *self.msg.lock().unwrap() = msg.clone();
+        Ok(())
+    }
+
+    fn cancel(&self) -> Result<()> {
+        self.task.abort();
+        Ok(())
+    }
+}
+
+pub struct CanSimple {
+    transport: Arc<dyn CanTransport>,
+    tx: Arc<TxQueue>,
+    dispatcher: Dispatcher,
+    monitor: BusMonitor,
+    stats: Arc<BusStats>,
+    /// Periodic transmissions still running, cancelled on shutdown.
+    periodic: StdMutex<Vec<Weak<dyn PeriodicTx>>>,
+    join_handle: StdMutex<Option<JoinHandle<()>>>,
+    listeners: Listeners,
+}
+
+impl CanSimple {
+    /// Opens `can_interface` on the given bus type.
+    ///
+    /// `BusType::Virtual` attaches to the in-process `VirtualBus` named after the
+    /// interface, so every `CanSimple` created with the same interface shares one bus.
+    pub fn new(can_interface: CanInterface, bustype: BusType) -> Result<Self> {
+        let channel = can_interface.value();
+        Ok(match bustype {
+            BusType::SocketCan => Self::with_transport(SocketCanTransport::open(channel)?),
+            BusType::Virtual => Self::with_transport(VirtualBusPort::open(channel)?),
+            BusType::Slcan => Self::with_transport(SlcanTransport::open(channel)?),
+        })
+    }
+
+    /// Like `new`, but tees every frame sent and received into `sink`, e.g. a
+    /// `PcapngWriter` shared by all buses of a robot.
+    pub fn new_recorded(can_interface: CanInterface, bustype: BusType, sink: Arc<dyn FrameSink>) -> Result<Self> {
+        let channel = can_interface.value();
+        Ok(match bustype {
+            BusType::SocketCan => Self::with_transport(RecordingTransport::new(SocketCanTransport::open(channel)?, sink)),
+            BusType::Virtual => Self::with_transport(RecordingTransport::new(VirtualBusPort::open(channel)?, sink)),
+            BusType::Slcan => Self::with_transport(RecordingTransport::new(SlcanTransport::open(channel)?, sink)),
+        })
+    }
+
+    /// Opens the SocketCAN interface `can_interface` after making sure it is
+    /// configured as `config` asks and up, as deploy scripts do with `ip link`.
+    /// Fails with a clear error if that needs privileges the process lacks.
+    pub fn new_configured(can_interface: CanInterface, config: &InterfaceConfig) -> Result<Self> {
+        let channel = can_interface.value();
+        if netlink::ensure_up(channel, config)? {
+            log::info!("Brought up {}", channel);
+        }
+        // Interfaces without bit timing, such as vcan, report no bitrate.
+        let bitrate = match config.bitrate {
+            Some(bitrate) => bitrate,
+            None => netlink::query(channel)?.bitrate.unwrap_or(BAUDRATE),
+        };
+        Ok(Self::with_transport_at(SocketCanTransport::open(channel)?, bitrate))
+    }
+
+    /// Attaches a new port to `bus`.
+    pub fn with_virtual_bus(bus: &VirtualBus) -> Self {
+        Self::with_transport(bus.connect())
+    }
+
+    /// Runs on `transport`, assuming a `BAUDRATE` bus.
+    pub fn with_transport(transport: impl CanTransport + 'static) -> Self {
+        Self::with_transport_at(transport, BAUDRATE)
+    }
+
+    /// Runs on `transport` for a bus at `bitrate`, which the rate limits and the
+    /// load and utilization figures are relative to.
+    pub fn with_transport_at(transport: impl CanTransport + 'static, bitrate: u32) -> Self {
+        let transport: Arc<dyn CanTransport> = Arc::new(transport);
+        let tx = Arc::new(TxQueue::new(bitrate));
+        let dispatcher = Dispatcher::default();
+        // Only frames some route is interested in get past the transport.
+        let filter_transport = transport.clone();
+        dispatcher.set_id_filter_sink(Box::new(move |filters| {
+            if let Err(e) = filter_transport.set_filters(filters) {
+                log::warn!("Failed to set CAN filters on {}: {}", filter_transport.channel(), e);
+            }
+        }));
+        let listeners: Listeners = Arc::new(StdMutex::new(Vec::new()));
+        let monitor = BusMonitor {
+            status: Default::default(),
+            events: broadcast::channel(BUS_EVENT_QUEUE_SIZE).0,
+            listeners: listeners.clone(),
+        };
+        let stats = Arc::new(BusStats::new(bitrate));
+        let join_handle = tokio::spawn(Self::run(transport.clone(), tx.clone(), dispatcher.clone(), monitor.clone(), stats.clone()));
         Self {
-            command_tx,
-            broadcast_tx,
-            join_handle,
+            transport,
+            tx,
+            dispatcher,
+            monitor,
+            stats,
+            periodic: StdMutex::new(Vec::new()),
+            join_handle: StdMutex::new(Some(join_handle)),
             listeners,
         }
     }
 
-    pub fn register_callbacks<T: CanMessageTrait + Send + 'static>(&self, msg_cls_callbacks: Vec<(PhantomData<T>, Box<dyn Fn(T) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync + 'static>)>) {
+    pub fn channel(&self) -> &str {
+        self.transport.channel()
+    }
+
+    async fn run(
+        transport: Arc<dyn CanTransport>,
+        tx: Arc<TxQueue>,
+        dispatcher: Dispatcher,
+        monitor: BusMonitor,
+        stats: Arc<BusStats>,
+    ) {
+        let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+        let rx_loop = async {
+            'rx: loop {
+                let res = tokio::select! {
+                    _ = shutdown_rx.changed() => break,
+                    res = transport.recv(RECV_TIMEOUT) => res,
+                };
+                match res {
+                    Ok(Some(TransportEvent::Frame(raw))) => {
+                        tx.record_rx(&raw);
+                        stats.record(Direction::Rx, &raw, None);
+                        // A route under backpressure may hold us here, so keep watching for shutdown.
+                        tokio::select! {
+                            _ = shutdown_rx.changed() => break,
+                            _ = dispatcher.dispatch(&raw) => {}
+                        }
+                    }
+                    Ok(Some(TransportEvent::Error(frame))) => monitor.on_error_frame(frame),
+                    Ok(None) => {}
+                    Err(e) if !transport.can_reopen() => {
+                        log::error!("CAN bus {} failed: {}", transport.channel(), e);
+                        monitor.emit(BusEvent::Closed { error: e.to_string() });
+                        break;
+                    }
+                    Err(e) => {
+                        log::error!("CAN bus {} failed, reopening: {}", transport.channel(), e);
+                        monitor.emit(BusEvent::TransportLost { error: e.to_string() });
+                        loop {
+                            tokio::select! {
+                                _ = shutdown_rx.changed() => break 'rx,
+                                _ = time::sleep(REOPEN_INTERVAL) => {}
+                            }
+                            let reopened = tokio::select! {
+                                _ = shutdown_rx.changed() => break 'rx,
+                                reopened = transport.reopen() => reopened,
+                            };
+                            match reopened {
+                                Ok(()) => break,
+                                Err(e) => log::debug!("Reopening {} failed: {}", transport.channel(), e),
+                            }
+                        }
+                        log::info!("CAN bus {} reopened", transport.channel());
+                        monitor.set_state(BusState::ErrorActive);
+                        monitor.emit(BusEvent::TransportReopened);
+                    }
+                }
+            }
+            dispatcher.close();
+        };
+        let tx_loop = async {
+            while let Some((raw, node_id)) = tx.pop().await {
+                match transport.send(&raw).await {
+                    Ok(()) => stats.record(Direction::Tx, &raw, node_id),
+                    Err(e) => {
+                        stats.record_tx_failure();
+                        log::error!("Error sending frame: {}", e);
+                    }
+                }
+            }
+            let _ = shutdown_tx.send(true);
+        };
+        tokio::join!(rx_loop, tx_loop);
+    }
+
+    pub fn register_callbacks<T: CanMessageTrait + Send + Sync + 'static>(&self, msg_cls_callbacks: Vec<(PhantomData<T>, MessageCallback<T>)>) {
+        self.register_callbacks_with_policy(msg_cls_callbacks, OverflowPolicy::default());
+    }
+
+    /// Like `register_callbacks`, with `policy` deciding what happens when a callback falls behind.
+    pub fn register_callbacks_with_policy<T: CanMessageTrait + Send + Sync + 'static>(
+        &self,
+        msg_cls_callbacks: Vec<(PhantomData<T>, MessageCallback<T>)>,
+        policy: OverflowPolicy,
+    ) {
         let mut g = self.listeners.lock().unwrap();
         for (phantom, callback) in msg_cls_callbacks {
-            let callback = Box::new(move |m| Box::pin(callback(m)));
-            let listener = Arc::new(CanSimpleListener::new(PhantomData::<T>, Some(callback)));
+            let route = self.dispatcher.route_for::<T>(None, policy);
+            let listener = Arc::new(CanSimpleListener::new(phantom, Some(callback), route));
             g.push(listener);
         }
     }
 
+    /// Returns a stream of every `T` received from now on.
+    ///
+    /// Subscriptions can be taken while the bus is running; dropping the stream unsubscribes it.
+    pub fn subscribe<T: CanMessageTrait + 'static>(&self) -> Subscription<T> {
+        self.subscribe_with_policy(None, OverflowPolicy::default())
+    }
+
+    /// Like `subscribe`, but only yields messages from `node_id`.
+    pub fn subscribe_node<T: CanMessageTrait + 'static>(&self, node_id: u32) -> Subscription<T> {
+        self.subscribe_with_policy(Some(node_id), OverflowPolicy::default())
+    }
+
+    pub fn subscribe_with_policy<T: CanMessageTrait + 'static>(&self, node_id: Option<u32>, policy: OverflowPolicy) -> Subscription<T> {
+        Subscription::new(self.dispatcher.route_for::<T>(node_id, policy), node_id)
+    }
+
+    /// Fault confinement state as last reported by the controller.
+    pub fn bus_state(&self) -> BusState {
+        self.monitor.status.lock().unwrap().0
+    }
+
+    /// TX/RX error counters from the last error frame that carried them.
+    pub fn error_counters(&self) -> Option<ErrorCounters> {
+        self.monitor.status.lock().unwrap().1
+    }
+
+    /// Error frames, state changes and transport failures from now on.
+    pub fn bus_events(&self) -> broadcast::Receiver<BusEvent> {
+        self.monitor.events.subscribe()
+    }
+
+    /// The id filters currently installed on the transport.
+    pub fn id_filters(&self) -> Vec<CanIdFilter> {
+        self.dispatcher.id_filters()
+    }
+
+    /// Queue depth, lag and decode failure counters of every live listener, subscription and pending request.
+    pub fn dispatch_stats(&self) -> Vec<RouteStats> {
+        self.dispatcher.stats()
+    }
+
+    /// Traffic per arbitration id and node, utilization and drop counters.
+    ///
+    /// Frames the transport filters out are never seen, so with only some ids
+    /// subscribed to the utilization covers just those and what is sent. While a
+    /// bus load limit is set every frame is received and it covers the whole bus.
+    pub fn bus_stats(&self) -> BusStatsSnapshot {
+        let mut snapshot = self.stats.snapshot();
+        snapshot.tx_queue = self.tx.stats();
+        let routes = self.dispatcher.stats();
+        snapshot.rx_lagged = routes.iter().map(|route| route.lagged).sum();
+        snapshot.rx_decode_errors = routes.iter().map(|route| route.decode_errors).sum();
+        snapshot
+    }
+
+    /// Sets how received frames are attributed to nodes in `bus_stats`. Sent frames
+    /// count for the node they were sent to.
+    pub fn set_stats_node_id_fn(&self, node_id_fn: Option<NodeIdFn>) {
+        self.stats.set_node_id_fn(node_id_fn);
+    }
+
+    pub fn reset_bus_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// Priorities, rate limits and the full-queue policy of outgoing frames.
+    pub fn tx_queue(&self) -> &TxQueue {
+        &self.tx
+    }
+
+    /// Holds frames back while sending them would take more than `max_load` (0..=1)
+    /// of the bus, counting what is received as well as what is sent. While a limit
+    /// is set the transport's id filters are opened up, so that traffic nobody
+    /// subscribed to counts too.
+    pub fn set_max_bus_load(&self, max_load: Option<f64>) -> Result<()> {
+        self.tx.set_max_bus_load(max_load)?;
+        self.dispatcher.set_receive_all(max_load.is_some());
+        Ok(())
+    }
+
+    /// Fraction of the bitrate taken by the frames this connection sees: what it
+    /// sends and what the transport's id filters let through. That is the load of
+    /// the whole bus only while every frame is let through, as it is with a bus
+    /// load limit set. The same figure as the total utilization in `bus_stats`.
+    pub fn observed_load(&self) -> f64 {
+        self.stats.utilization()
+    }
+
+    /// Queues `msg` at its own `tx_priority`, counted against the rate limit of its node.
     pub async fn send(&self, msg: impl CanMessageTrait) -> Result<()> {
+        let priority = msg.tx_priority();
+        self.send_with_priority(msg, priority).await
+    }
+
+    pub async fn send_with_priority(&self, msg: impl CanMessageTrait, priority: TxPriority) -> Result<()> {
         let raw = msg.as_can_message();
-        let id = if raw.is_extended_id {
-            ExtendedId::new(raw.arbitration_id).ok_or(anyhow!("Invalid extended ID"))?
-        } else {
-            StandardId::new(raw.arbitration_id as u16).ok_or(anyhow!("Invalid standard ID"))?
+        raw.validate()?;
+        self.tx.push(raw, priority, Some(msg.node_id())).await
+    }
+
+    /// Queues a raw classic or FD frame. Frames that could not go on the wire as
+    /// is (see `RawCanMessage::validate`) are rejected here rather than in the bus task.
+    pub async fn send_raw(&self, raw: RawCanMessage) -> Result<()> {
+        self.send_raw_with_priority(raw, TxPriority::Normal).await
+    }
+
+    /// Like `send_raw`. Raw frames have no node, so only the bus limit applies to them.
+    pub async fn send_raw_with_priority(&self, raw: RawCanMessage, priority: TxPriority) -> Result<()> {
+        raw.validate()?;
+        self.tx.push(raw, priority, None).await
+    }
+
+    /// Sends `msg` now and every `period` after until the handle is dropped or the bus shut down.
+    ///
+    /// SocketCAN buses hand the frame to the kernel broadcast manager; other
+    /// transports, or kernels without CAN_BCM, send it from a userspace interval.
+    pub fn send_periodic(&self, msg: impl CanMessageTrait, period: Duration) -> Result<PeriodicHandle> {
+        self.start_periodic(msg.as_can_message(), msg.tx_priority(), Some(msg.node_id()), period)
+    }
+
+    pub fn send_periodic_raw(&self, raw: RawCanMessage, period: Duration) -> Result<PeriodicHandle> {
+        self.start_periodic(raw, TxPriority::Normal, None, period)
+    }
+
+    fn start_periodic(&self, raw: RawCanMessage, priority: TxPriority, node_id: Option<u32>, period: Duration) -> Result<PeriodicHandle> {
+        raw.validate()?;
+        if period.is_zero() {
+            return Err(anyhow!("Periodic frame 0x{:X} needs a non-zero period", raw.arbitration_id));
+        }
+        let task: Arc<dyn PeriodicTx> = match self.transport.start_periodic(&raw, period)? {
+            Some(task) => Arc::from(task),
+            None => Arc::new(IntervalTx::spawn(self.tx.clone(), raw.clone(), priority, node_id, period)),
         };
-        let frame = CanFrame::new(id, &raw.data, false, false).unwrap();
-        self.command_tx.send(Command::Send(frame)).await?;
-        Ok(())
+        let mut periodic = self.periodic.lock().unwrap();
+        periodic.retain(|task| task.strong_count() > 0);
+        periodic.push(Arc::downgrade(&task));
+        Ok(PeriodicHandle::new(task, &raw, period))
+    }
+
+    /// Sends `msg` and waits up to `timeout` for the matching `Resp`.
+    ///
+    /// A frame is a reply when `Resp::matches` accepts it, it decodes to the same node id
+    /// as `msg`, and it is not an echo of the request frame itself.
+    pub async fn request<Req, Resp>(&self, msg: Req, timeout: Duration) -> Result<Resp>
+    where
+        Req: CanMessageTrait,
+        Resp: CanMessageTrait + 'static,
+    {
+        self.request_with_retries(msg, timeout, 0).await
+    }
+
+    /// Like `request`, but resends `msg` up to `retries` more times before giving up.
+    pub async fn request_with_retries<Req, Resp>(&self, msg: Req, timeout: Duration, retries: u32) -> Result<Resp>
+    where
+        Req: CanMessageTrait,
+        Resp: CanMessageTrait + 'static,
+    {
+        self.await_reply(&msg, timeout, retries, Resp::try_from_received, |_| true).await
+    }
+
+    /// Reads an ODrive endpoint, decoding the value as `value_type`. Only a response
+    /// for the same endpoint counts as the reply, so reads of other endpoints in
+    /// flight on the node do not get mixed up.
+    pub async fn read_parameter(&self, node_id: u32, endpoint_id: u16, value_type: ValueTypes, timeout: Duration) -> Result<Value> {
+        let msg = ReadParameterCommand::new(node_id, endpoint_id);
+        let decode = |raw| ParameterResponse::decode_as(raw, value_type);
+        let resp = self.await_reply(&msg, timeout, 0, decode, |resp: &ParameterResponse| resp.endpoint_id == endpoint_id).await?;
+        Ok(resp.value)
+    }
+
+    /// Sends `msg` and waits for a frame that `decode` turns into a `Resp` from the
+    /// same node that `is_reply` accepts.
+    async fn await_reply<Req, Resp>(
+        &self,
+        msg: &Req,
+        timeout: Duration,
+        retries: u32,
+        decode: impl Fn(RawCanMessage) -> Result<Resp, DecodeError>,
+        is_reply: impl Fn(&Resp) -> bool,
+    ) -> Result<Resp>
+    where
+        Req: CanMessageTrait,
+        Resp: CanMessageTrait + 'static,
+    {
+        let node_id = msg.node_id();
+        let priority = msg.tx_priority();
+        let request = msg.as_can_message();
+        request.validate()?;
+        // Subscribe before sending so a fast reply cannot slip past.
+        let route = self.dispatcher.route_for::<Resp>(Some(node_id), OverflowPolicy::DropOldest);
+        for _ in 0..=retries {
+            self.tx.push(request.clone(), priority, Some(node_id)).await?;
+            let deadline = time::Instant::now() + timeout;
+            loop {
+                let raw = match time::timeout_at(deadline, route.recv()).await {
+                    Err(_) => break,
+                    Ok(Some(raw)) => raw,
+                    Ok(None) => return Err(anyhow!("CAN bus closed while awaiting reply")),
+                };
+                if raw.same_frame(&request) {
+                    continue;
+                }
+                if let Some(resp) = route.decode_with(raw, &decode) {
+                    if resp.node_id() == node_id && is_reply(&resp) {
+                        return Ok(resp);
+                    }
+                }
+            }
+        }
+        Err(RequestTimeout { node_id, cmd_id: Resp::cmd_id(), attempts: retries + 1, timeout }.into())
     }
 
-    pub async fn listen(&self) -> Result<() > {
+    /// Starts every registered listener and returns a future that resolves once they have all stopped.
+    pub fn listen(&self) -> impl Future<Output = Result<()>> + Send + 'static {
         let listeners = {
             let g = self.listeners.lock().unwrap();
             g.clone()
         };
-        let mut tasks = vec![];
-        for l in listeners {
-            let rx = self.broadcast_tx.subscribe();
-            tasks.push(l.listen(rx));
-        }
-        for task in tasks {
-            task.await??;
+        let tasks: Vec<_> = listeners.into_iter().map(|l| l.listen()).collect();
+        async move {
+            for task in tasks {
+                task.await??;
+            }
+            Ok(())
         }
-        Ok(())
     }
 
-    pub async fn shutdown(self) {
-        let g = self.listeners.lock().unwrap();
-        for l in &*g {
-            l.stop();
+    /// Stops the listeners and the bus task. Calling it again is a no-op.
+    pub async fn shutdown(&self) {
+        {
+            let g = self.listeners.lock().unwrap();
+            for l in &*g {
+                l.stop();
+            }
+        }
+        for task in self.periodic.lock().unwrap().drain(..).filter_map(|task| task.upgrade()) {
+            if let Err(e) = task.cancel() {
+                log::warn!("Failed to cancel periodic frame: {}", e);
+            }
+        }
+        self.tx.close();
+        let join_handle = self.join_handle.lock().unwrap().take();
+        if let Some(join_handle) = join_handle {
+            let _ = join_handle.await;
         }
-        let _ = self.command_tx.send(Command::Shutdown).await;
-        let _ = self.join_handle.await;
     }
+}
 
-    fn frame_to_raw(frame: &CanFrame) -> RawCanMessage {
-        let (arbitration_id, is_extended_id) = match frame.id() {
-            socketcan::Id::Standard(id) => (id.as_raw() as u32, false),
-            socketcan::Id::Extended(id) => (id.as_raw(), true),
-        };
-        RawCanMessage {
-            arbitration_id,
-            data: frame.data().to_vec(),
-            is_extended_id,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::can::odrive_msgs::HeartbeatMessage;
+
+    #[tokio::test]
+    async fn bus_load_limit_receives_every_frame() {
+        let bus = VirtualBus::new();
+        let can = CanSimple::with_virtual_bus(&bus);
+        let other = CanSimple::with_virtual_bus(&bus);
+        let _heartbeats = can.subscribe_node::<HeartbeatMessage>(1);
+        assert_eq!(can.id_filters(), HeartbeatMessage::can_filters(Some(1)));
+
+        assert!(can.set_max_bus_load(Some(1.5)).is_err());
+        can.set_max_bus_load(Some(0.5)).unwrap();
+        assert_eq!(can.id_filters(), [CanIdFilter::ACCEPT_ALL]);
+        other.send_raw(RawCanMessage::new(0x555, vec![0; 8])).await.unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(can.bus_stats().total.rx_frames, 1);
+
+        can.set_max_bus_load(None).unwrap();
+        assert_eq!(can.id_filters(), HeartbeatMessage::can_filters(Some(1)));
+        other.send_raw(RawCanMessage::new(0x555, vec![0; 8])).await.unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(can.bus_stats().total.rx_frames, 1);
     }
 }
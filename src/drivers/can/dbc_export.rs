@@ -0,0 +1,227 @@
+//! A DBC file describing the built-in ODrive, MyActuator V3 and X4-24 messages, for
+//! decoding a bus in cantools, SavvyCAN or Wireshark. The signals come from each
+//! message type's `DbcLayout`, which `#[derive(CanMessage)]` builds from the same
+//! attributes it encodes and decodes with.
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use anyhow::{anyhow, Result};
+
+use crate::drivers::can::dbc::{ByteOrder, Dbc, Message, Multiplex, Signal, ValueType};
+use crate::drivers::can::messages::CanMessageTrait;
+use crate::drivers::can::myactuator_v3_msgs as myactuator;
+use crate::drivers::can::myactuator_x424_msgs as x424;
+use crate::drivers::can::odrive_msgs as odrive;
+
+/// How a message type appears in a DBC file.
+pub trait DbcLayout: CanMessageTrait + Sized {
+    const DBC_NAME: &'static str;
+
+    /// Payload length in bytes.
+    const DBC_LEN: u8;
+
+    /// The payload's signals. The command byte of MyActuator messages and the type
+    /// bits of X4-24 ones are left out, as the export adds them as a multiplexor.
+    fn dbc_signals() -> Vec<Signal>;
+}
+
+/// Names for the raw values up to 255 that `decode` accepts, for `Signal::values`.
+pub fn value_names<R: From<u8>, T: Debug>(decode: impl Fn(R) -> Option<T>) -> Vec<(i64, String)> {
+    (0..=u8::MAX).filter_map(|v| decode(R::from(v)).map(|t| (v as i64, format!("{:?}", t)))).collect()
+}
+
+/// Node ids to describe, per protocol.
+#[derive(Debug, Clone, Default)]
+pub struct DbcNodes {
+    pub odrive: Vec<u32>,
+    pub myactuator: Vec<u32>,
+    pub x424: Vec<u32>,
+}
+
+struct Layout {
+    name: &'static str,
+    cmd_id: u32,
+    len: u8,
+    signals: Vec<Signal>,
+}
+
+fn layout<T: DbcLayout>() -> Layout {
+    Layout { name: T::DBC_NAME, cmd_id: T::cmd_id(), len: T::DBC_LEN, signals: T::dbc_signals() }
+}
+
+fn message(id: u32, name: String, dlc: u8, signals: Vec<Signal>) -> Message {
+    Message { id, extended: false, name, dlc, sender: String::new(), signals, comment: None }
+}
+
+fn odrive_layouts() -> Vec<Layout> {
+    vec![
+        layout::<odrive::VersionMessage>(),
+        layout::<odrive::HeartbeatMessage>(),
+        layout::<odrive::EStop>(),
+        layout::<odrive::ErrorMessage>(),
+        layout::<odrive::WriteParameterCommand>(),
+        layout::<odrive::ReadParameterCommand>(),
+        layout::<odrive::ParameterResponse>(),
+        layout::<odrive::SetAxisStateMessage>(),
+        layout::<odrive::EncoderEstimatesMessage>(),
+        layout::<odrive::SetControllerMode>(),
+        layout::<odrive::SetPositionMessage>(),
+        layout::<odrive::SetVelocityMessage>(),
+        layout::<odrive::SetTorqueMessage>(),
+        layout::<odrive::SetLimitsCommand>(),
+        layout::<odrive::SetTrajVelLimitMessage>(),
+        layout::<odrive::SetTrajAccelLimitsMessage>(),
+        layout::<odrive::SetTrajInertiaMessage>(),
+        layout::<odrive::IqMessage>(),
+        layout::<odrive::TemperatureMessage>(),
+        layout::<odrive::Reboot>(),
+        layout::<odrive::BusVoltageCurrentMessage>(),
+        layout::<odrive::ClearErrorsCommand>(),
+        layout::<odrive::SetAbsolutePositionMessage>(),
+        layout::<odrive::SetPosGainMessage>(),
+        layout::<odrive::SetVelGainsMessage>(),
+        layout::<odrive::TorquesMessage>(),
+        layout::<odrive::PowersMessage>(),
+        layout::<odrive::EnterDfuModeCommand>(),
+    ]
+}
+
+fn myactuator_layouts() -> Vec<Layout> {
+    vec![
+        layout::<myactuator::FunctionControlCommand>(),
+        layout::<myactuator::WriteMotorZeroPositionMessage>(),
+        layout::<myactuator::SystemOperatingModeAcquisitionCommand>(),
+        layout::<myactuator::SystemResetCommand>(),
+        layout::<myactuator::SystemBrakeReleaseCommand>(),
+        layout::<myactuator::SystemBrakeLockCommand>(),
+        layout::<myactuator::CANIDCommand>(),
+        layout::<myactuator::MotorShutdownCommand>(),
+        layout::<myactuator::MotorStopCommand>(),
+        layout::<myactuator::ReadMultiTurnAngleMessage>(),
+        layout::<myactuator::MyactuatorReadMotorStatus1Message>(),
+        layout::<myactuator::ReadMotorStatus2Message>(),
+        layout::<myactuator::TorqueControlCommand>(),
+        layout::<myactuator::SpeedControlCommand>(),
+        layout::<myactuator::PositionControlCommand>(),
+        layout::<myactuator::IncrementalPositionControlCommand>(),
+        layout::<myactuator::VersionAcquisitionCommand>(),
+    ]
+}
+
+/// Replies come first: the commands reuse types 1 to 3 on the same id, and only one
+/// message per type can be described.
+fn x424_layouts() -> Vec<Layout> {
+    vec![
+        layout::<x424::QAReturnMessage>(),
+        layout::<x424::QAReturnMessageType1>(),
+        layout::<x424::QAReturnMessageType2>(),
+        layout::<x424::QAReturnMessageType3>(),
+        layout::<x424::QAReturnMessageType4>(),
+        layout::<x424::QAReturnMessageType5>(),
+        layout::<x424::X424ServoPositionControlMessage>(),
+        layout::<x424::X424ServoSpeedControlMessage>(),
+        layout::<x424::X424CurrentControlMessage>(),
+    ]
+}
+
+/// One frame for several messages, told apart by `selector`'s raw value, which is
+/// each message's command id. Signals are prefixed with their message's name, and a
+/// message whose command id is already taken is only named in the frame comment.
+fn multiplexed(id: u32, name: String, selector: Signal, layouts: &[Layout]) -> Message {
+    let mut selector = Signal { multiplex: Multiplex::Multiplexor, ..selector };
+    let mut signals = Vec::new();
+    let mut skipped = Vec::new();
+    for layout in layouts {
+        if let Some((_, taken)) = selector.values.iter().find(|(value, _)| *value == layout.cmd_id as i64) {
+            skipped.push(format!("{} shares {} {} with {} and is not described.", layout.name, selector.name, layout.cmd_id, taken));
+            continue;
+        }
+        selector.values.push((layout.cmd_id as i64, layout.name.to_string()));
+        for signal in &layout.signals {
+            signals.push(Signal {
+                name: format!("{}_{}", layout.name, signal.name),
+                multiplex: Multiplex::Multiplexed(layout.cmd_id as u64),
+                ..signal.clone()
+            });
+        }
+    }
+    signals.insert(0, selector);
+    let dlc = layouts.iter().map(|l| l.len).max().unwrap_or(8);
+    Message { comment: (!skipped.is_empty()).then(|| skipped.join(" ")), ..message(id, name, dlc, signals) }
+}
+
+/// One message per node and command, at `node_id << 5 | cmd_id`.
+pub fn odrive_messages(node_ids: &[u32]) -> Result<Vec<Message>> {
+    let layouts = odrive_layouts();
+    let mut messages: Vec<Message> = Vec::new();
+    for &node_id in node_ids {
+        if node_id > 0x3F {
+            return Err(anyhow!("ODrive node id {} does not fit in 6 bits", node_id));
+        }
+        for layout in &layouts {
+            let id = node_id << 5 | layout.cmd_id;
+            match messages.iter_mut().find(|m| m.id == id) {
+                // Read and write parameter commands share 0x04; the write layout covers both.
+                Some(taken) => {
+                    let note = format!("{} shares this id and is not described.", layout.name);
+                    taken.comment = Some(taken.comment.take().map_or(note.clone(), |c| format!("{} {}", c, note)));
+                }
+                None => messages.push(message(id, format!("{}_{}", layout.name, node_id), layout.len, layout.signals.clone())),
+            }
+        }
+    }
+    Ok(messages)
+}
+
+/// Per node, a request frame at 0x140 + id and a reply frame at 0x240 + id, both
+/// multiplexed on the command byte. `CANIDCommand` is also broadcast on 0x300.
+pub fn myactuator_messages(node_ids: &[u32]) -> Result<Vec<Message>> {
+    let layouts = myactuator_layouts();
+    let command = Signal::new("command", 0, 8, ByteOrder::LittleEndian, ValueType::Unsigned);
+    let mut messages = Vec::new();
+    for &node_id in node_ids {
+        if node_id >= 0x20 {
+            return Err(anyhow!("MyActuator node id {} is outside 0 to 31", node_id));
+        }
+        messages.push(multiplexed(0x140 + node_id, format!("MyActuatorRequest_{}", node_id), command.clone(), &layouts));
+        messages.push(multiplexed(0x240 + node_id, format!("MyActuatorReply_{}", node_id), command.clone(), &layouts));
+    }
+    if !node_ids.is_empty() {
+        messages.push(multiplexed(0x300, "MyActuatorBroadcast".to_string(), command, &[layout::<myactuator::CANIDCommand>()]));
+    }
+    Ok(messages)
+}
+
+/// Per motor, a frame at its id multiplexed on the top three bits of the first byte,
+/// plus the set and query frame on 0x7FF that all motors share.
+pub fn x424_messages(node_ids: &[u32]) -> Result<Vec<Message>> {
+    let layouts = x424_layouts();
+    let message_type = Signal::new("type", 5, 3, ByteOrder::LittleEndian, ValueType::Unsigned);
+    let mut messages = Vec::new();
+    for &node_id in node_ids {
+        if node_id >= 0x7FF {
+            return Err(anyhow!("X4-24 node id {} is not below the broadcast id 0x7FF", node_id));
+        }
+        messages.push(multiplexed(node_id, format!("X424_{}", node_id), message_type.clone(), &layouts));
+    }
+    if !node_ids.is_empty() {
+        let set_and_query = layout::<x424::X424CanMessageSetAndQuery>();
+        messages.push(message(0x7FF, set_and_query.name.to_string(), set_and_query.len, set_and_query.signals));
+    }
+    Ok(messages)
+}
+
+/// Every built-in message for `nodes`. Fails if two messages would share an id,
+/// which happens when the protocols' id ranges overlap for the given nodes.
+pub fn builtin_dbc(nodes: &DbcNodes) -> Result<Dbc> {
+    let mut messages = odrive_messages(&nodes.odrive)?;
+    messages.extend(myactuator_messages(&nodes.myactuator)?);
+    messages.extend(x424_messages(&nodes.x424)?);
+    let mut names = HashMap::new();
+    for message in &messages {
+        if let Some(other) = names.insert(message.id, &message.name) {
+            return Err(anyhow!("Message id 0x{:X} is used by both {} and {}", message.id, other, message.name));
+        }
+    }
+    Ok(Dbc { messages, ..Default::default() })
+}
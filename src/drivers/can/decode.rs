@@ -0,0 +1,192 @@
+//! Decoding of arbitrary traffic into one message enum, for loggers, sniffers and
+//! simulators that do not know which message a frame carries.
+//!
+//! Each protocol enum tries its message types in declaration order and keeps the
+//! first whose `matches` accepts the frame and whose decode succeeds. Read and write
+//! parameter commands share ODrive command 0x04 and are told apart by their opcode
+//! byte. X4-24 servo commands reuse the types of QA replies 1 to 3 on the same id, so
+//! replies are tried first; the speed and current commands are shorter than their
+//! replies and still decode as commands, but a position command reads as a type 1 reply.
+use super::messages::{CanMessageTrait, RawCanMessage};
+use super::myactuator_v3_msgs as myactuator;
+use super::myactuator_x424_msgs as x424;
+use super::odrive_msgs as odrive;
+
+/// The id X4-24 set and query commands and their replies are broadcast on.
+const X424_BROADCAST_ID: u32 = 0x7FF;
+
+fn try_decode<T: CanMessageTrait>(raw: &RawCanMessage) -> Option<T> {
+    if !T::matches(raw) {
+        return None;
+    }
+    T::try_from_received(raw.clone()).ok()
+}
+
+macro_rules! message_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident($ty:ty),)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone)]
+        pub enum $name {
+            $($variant($ty),)*
+        }
+
+        impl $name {
+            /// The first message type, in the order listed, that matches and decodes `raw`.
+            pub fn decode(raw: &RawCanMessage) -> Option<Self> {
+                $(
+                    if let Some(msg) = try_decode::<$ty>(raw) {
+                        return Some(Self::$variant(msg));
+                    }
+                )*
+                None
+            }
+
+            /// The decoded message, e.g. for its node id, timestamp or frame.
+            pub fn message(&self) -> &dyn CanMessageTrait {
+                match self {
+                    $(Self::$variant(msg) => msg,)*
+                }
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$variant(_) => stringify!($variant),)*
+                }
+            }
+        }
+
+        $(
+            impl From<$ty> for $name {
+                fn from(msg: $ty) -> Self {
+                    Self::$variant(msg)
+                }
+            }
+        )*
+    };
+}
+
+message_enum! {
+    /// Every ODrive message, by command id.
+    OdriveMessage {
+        Version(odrive::VersionMessage),
+        Heartbeat(odrive::HeartbeatMessage),
+        EStop(odrive::EStop),
+        Error(odrive::ErrorMessage),
+        ReadParameter(odrive::ReadParameterCommand),
+        WriteParameter(odrive::WriteParameterCommand),
+        ParameterResponse(odrive::ParameterResponse),
+        SetAxisState(odrive::SetAxisStateMessage),
+        EncoderEstimates(odrive::EncoderEstimatesMessage),
+        SetControllerMode(odrive::SetControllerMode),
+        SetPosition(odrive::SetPositionMessage),
+        SetVelocity(odrive::SetVelocityMessage),
+        SetTorque(odrive::SetTorqueMessage),
+        SetLimits(odrive::SetLimitsCommand),
+        SetTrajVelLimit(odrive::SetTrajVelLimitMessage),
+        SetTrajAccelLimits(odrive::SetTrajAccelLimitsMessage),
+        SetTrajInertia(odrive::SetTrajInertiaMessage),
+        Iq(odrive::IqMessage),
+        Temperature(odrive::TemperatureMessage),
+        Reboot(odrive::Reboot),
+        BusVoltageCurrent(odrive::BusVoltageCurrentMessage),
+        ClearErrors(odrive::ClearErrorsCommand),
+        SetAbsolutePosition(odrive::SetAbsolutePositionMessage),
+        SetPosGain(odrive::SetPosGainMessage),
+        SetVelGains(odrive::SetVelGainsMessage),
+        Torques(odrive::TorquesMessage),
+        Powers(odrive::PowersMessage),
+        EnterDfuMode(odrive::EnterDfuModeCommand),
+    }
+}
+
+message_enum! {
+    /// Every MyActuator V3 message on a motor's request or reply id, by command byte.
+    MyActuatorV3Message {
+        FunctionControl(myactuator::FunctionControlCommand),
+        WriteMotorZeroPosition(myactuator::WriteMotorZeroPositionMessage),
+        SystemOperatingModeAcquisition(myactuator::SystemOperatingModeAcquisitionCommand),
+        SystemReset(myactuator::SystemResetCommand),
+        SystemBrakeRelease(myactuator::SystemBrakeReleaseCommand),
+        SystemBrakeLock(myactuator::SystemBrakeLockCommand),
+        CanId(myactuator::CANIDCommand),
+        MotorShutdown(myactuator::MotorShutdownCommand),
+        MotorStop(myactuator::MotorStopCommand),
+        ReadMultiTurnAngle(myactuator::ReadMultiTurnAngleMessage),
+        ReadMotorStatus1(myactuator::MyactuatorReadMotorStatus1Message),
+        ReadMotorStatus2(myactuator::ReadMotorStatus2Message),
+        TorqueControl(myactuator::TorqueControlCommand),
+        SpeedControl(myactuator::SpeedControlCommand),
+        PositionControl(myactuator::PositionControlCommand),
+        IncrementalPositionControl(myactuator::IncrementalPositionControlCommand),
+        VersionAcquisition(myactuator::VersionAcquisitionCommand),
+    }
+}
+
+message_enum! {
+    /// Every X4-24 message: set and query commands and replies on 0x7FF, then the
+    /// motor frames by type, replies before the commands that reuse their types.
+    X424Message {
+        SetCommunicationMode(x424::SetCommunicationModeMessage),
+        QueryCommunicationMode(x424::QueryCommunicationModeMessage),
+        QueryCanId(x424::QueryCANCommunicationIDMessage),
+        SetZeroPosition(x424::SetZeroPositionMessage),
+        SetMotorId(x424::SetMotorIDMessage),
+        ResetMotorId(x424::ResetMotorIDMessage),
+        QaReturn(x424::QAReturnMessage),
+        QaReturnType1(x424::QAReturnMessageType1),
+        QaReturnType2(x424::QAReturnMessageType2),
+        QaReturnType3(x424::QAReturnMessageType3),
+        QaReturnType4(x424::QAReturnMessageType4),
+        QaReturnType5(x424::QAReturnMessageType5),
+        ServoPosition(x424::X424ServoPositionControlMessage),
+        ServoSpeed(x424::X424ServoSpeedControlMessage),
+        Current(x424::X424CurrentControlMessage),
+    }
+}
+
+/// A frame decoded by `decode`, whichever protocol it belongs to.
+#[derive(Debug, Clone)]
+pub enum AnyMessage {
+    Odrive(OdriveMessage),
+    MyActuatorV3(MyActuatorV3Message),
+    X424(X424Message),
+}
+
+impl AnyMessage {
+    pub fn message(&self) -> &dyn CanMessageTrait {
+        match self {
+            AnyMessage::Odrive(msg) => msg.message(),
+            AnyMessage::MyActuatorV3(msg) => msg.message(),
+            AnyMessage::X424(msg) => msg.message(),
+        }
+    }
+
+    /// The message type's name within its protocol.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnyMessage::Odrive(msg) => msg.name(),
+            AnyMessage::MyActuatorV3(msg) => msg.name(),
+            AnyMessage::X424(msg) => msg.name(),
+        }
+    }
+}
+
+/// Decodes a frame of any built-in protocol. MyActuator V3 is tried first, as its ids
+/// and command bytes are the narrowest match, then ODrive, then X4-24, whose motor
+/// frames can sit on any id. On 0x7FF, X4-24 goes before ODrive, whose node 63 would
+/// otherwise take the set and query frames. All three use standard ids only.
+///
+/// The ids of the protocols overlap, so on a bus carrying one protocol, decoding with
+/// its own enum avoids frames being claimed by another.
+pub fn decode(raw: &RawCanMessage) -> Option<AnyMessage> {
+    if raw.is_extended_id {
+        return None;
+    }
+    let myactuator = || MyActuatorV3Message::decode(raw).map(AnyMessage::MyActuatorV3);
+    let odrive = || OdriveMessage::decode(raw).map(AnyMessage::Odrive);
+    let x424 = || X424Message::decode(raw).map(AnyMessage::X424);
+    if raw.arbitration_id == X424_BROADCAST_ID {
+        return x424().or_else(odrive);
+    }
+    myactuator().or_else(odrive).or_else(x424)
+}
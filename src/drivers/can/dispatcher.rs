@@ -0,0 +1,508 @@
+//! Routing of received frames to listeners, subscriptions and pending requests.
+//!
+//! Every consumer owns a route: a filter and a bounded queue. The receive loop of
+//! a `CanSimple` hands each frame to every route whose filter accepts it. What
+//! happens when a consumer falls behind is set per route by its `OverflowPolicy`.
+//! Frames a consumer cannot decode are counted on its route and skipped.
+use std::any::type_name;
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use super::messages::{CanIdFilter, CanMessageTrait, DecodeError, RawCanMessage};
+
+pub const ROUTE_QUEUE_SIZE: usize = 256;
+
+/// Above this many id filters the kernel is asked to accept everything instead.
+const MAX_ID_FILTERS: usize = 64;
+
+pub type FrameFilter = Box<dyn Fn(&RawCanMessage) -> bool + Send + Sync>;
+
+/// Receives the merged id filters of all routes whenever they change.
+pub type IdFilterSink = Box<dyn Fn(&[CanIdFilter]) + Send + Sync>;
+
+/// What a route does once `ROUTE_QUEUE_SIZE` frames are waiting for its consumer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued frame and count it as lagged. Never stalls the bus.
+    #[default]
+    DropOldest,
+    /// Hold the receive loop until the consumer makes room. No frame is lost, but
+    /// every other route waits too, and the kernel queue may overflow instead.
+    Backpressure,
+}
+
+/// Counters of a single route, see `Dispatcher::stats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteStats {
+    pub id: u64,
+    pub name: &'static str,
+    pub policy: OverflowPolicy,
+    pub queued: usize,
+    pub delivered: u64,
+    pub lagged: u64,
+    /// Frames the consumer received but could not decode.
+    pub decode_errors: u64,
+}
+
+#[derive(Default)]
+struct QueueState {
+    frames: VecDeque<RawCanMessage>,
+    rx_wakers: Vec<Waker>,
+    tx_waker: Option<Waker>,
+    closed: bool,
+}
+
+struct RouteQueue {
+    name: &'static str,
+    policy: OverflowPolicy,
+    state: Mutex<QueueState>,
+    delivered: AtomicU64,
+    lagged: AtomicU64,
+    decode_errors: AtomicU64,
+}
+
+impl RouteQueue {
+    fn poll_push(&self, cx: &mut Context<'_>, raw: &mut Option<RawCanMessage>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Poll::Ready(());
+        }
+        if state.frames.len() >= ROUTE_QUEUE_SIZE {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    state.frames.pop_front();
+                    if self.lagged.fetch_add(1, Ordering::Relaxed) == 0 {
+                        log::warn!("Route {} is lagging, dropping its oldest frames", self.name);
+                    }
+                }
+                OverflowPolicy::Backpressure => {
+                    state.tx_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+        if let Some(raw) = raw.take() {
+            state.frames.push_back(raw);
+            self.delivered.fetch_add(1, Ordering::Relaxed);
+        }
+        state.rx_wakers.drain(..).for_each(Waker::wake);
+        Poll::Ready(())
+    }
+
+    fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<Option<RawCanMessage>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(raw) = state.frames.pop_front() {
+            if let Some(waker) = state.tx_waker.take() {
+                waker.wake();
+            }
+            return Poll::Ready(Some(raw));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        if !state.rx_wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.rx_wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    fn decode_failed(&self, error: &DecodeError) {
+        if self.decode_errors.fetch_add(1, Ordering::Relaxed) == 0 {
+            log::warn!("Route {} got a frame it cannot decode: {}", self.name, error);
+        } else {
+            log::debug!("Route {} got a frame it cannot decode: {}", self.name, error);
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.rx_wakers.drain(..).for_each(Waker::wake);
+        if let Some(waker) = state.tx_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn stats(&self, id: u64) -> RouteStats {
+        RouteStats {
+            id,
+            name: self.name,
+            policy: self.policy,
+            queued: self.state.lock().unwrap().frames.len(),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            lagged: self.lagged.load(Ordering::Relaxed),
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Route {
+    id: u64,
+    filter: FrameFilter,
+    id_filters: Vec<CanIdFilter>,
+    queue: Arc<RouteQueue>,
+}
+
+#[derive(Default)]
+struct DispatcherState {
+    next_id: u64,
+    routes: Vec<Route>,
+    closed: bool,
+    id_filters: Vec<CanIdFilter>,
+    id_filter_sink: Option<IdFilterSink>,
+    /// Hand the sink `ACCEPT_ALL` whatever the routes need.
+    receive_all: bool,
+}
+
+impl DispatcherState {
+    /// Recomputes the union of the routes' id filters and hands it to the sink if it changed.
+    ///
+    /// Runs under the dispatcher lock, so the sink sees updates in order and a new
+    /// route's filters are installed before `route` returns.
+    fn update_id_filters(&mut self) {
+        let mut filters: Vec<CanIdFilter> = self.routes.iter().flat_map(|r| r.id_filters.iter().copied()).collect();
+        filters.sort();
+        filters.dedup();
+        if self.receive_all || filters.contains(&CanIdFilter::ACCEPT_ALL) || filters.len() > MAX_ID_FILTERS {
+            filters = vec![CanIdFilter::ACCEPT_ALL];
+        }
+        if filters != self.id_filters {
+            self.id_filters = filters;
+            if let Some(sink) = &self.id_filter_sink {
+                sink(&self.id_filters);
+            }
+        }
+    }
+}
+
+/// Fans received frames out to routes. Cheap to clone; clones share the routes.
+#[derive(Clone, Default)]
+pub struct Dispatcher {
+    state: Arc<Mutex<DispatcherState>>,
+}
+
+impl Dispatcher {
+    /// Adds a route for every frame `filter` accepts. `name` only shows up in logs and stats.
+    ///
+    /// `id_filters` must let through every frame `filter` accepts; they are merged
+    /// across routes and handed to the sink set by `set_id_filter_sink`.
+    pub fn route(&self, name: &'static str, filter: FrameFilter, id_filters: Vec<CanIdFilter>, policy: OverflowPolicy) -> RouteReceiver {
+        let queue = Arc::new(RouteQueue {
+            name,
+            policy,
+            state: Mutex::new(QueueState::default()),
+            delivered: AtomicU64::new(0),
+            lagged: AtomicU64::new(0),
+            decode_errors: AtomicU64::new(0),
+        });
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        if state.closed {
+            queue.close();
+        } else {
+            state.routes.push(Route { id, filter, id_filters, queue: queue.clone() });
+            state.update_id_filters();
+        }
+        RouteReceiver { id, queue, dispatcher: self.clone() }
+    }
+
+    /// Adds a route for every frame `T::matches` accepts, limited to the ids of `node_id`
+    /// if given, so other nodes' frames never take up the route's queue.
+    pub fn route_for<T: CanMessageTrait + 'static>(&self, node_id: Option<u32>, policy: OverflowPolicy) -> RouteReceiver {
+        let id_filters = T::can_filters(node_id);
+        let filter: FrameFilter = match node_id {
+            Some(_) => {
+                let node_filters = id_filters.clone();
+                Box::new(move |raw| T::matches(raw) && node_filters.iter().any(|f| f.accepts_frame(raw)))
+            }
+            None => Box::new(|raw| T::matches(raw)),
+        };
+        self.route(type_name::<T>(), filter, id_filters, policy)
+    }
+
+    /// The id filters needed by the current routes. Empty when no route wants any frame.
+    pub fn id_filters(&self) -> Vec<CanIdFilter> {
+        self.state.lock().unwrap().id_filters.clone()
+    }
+
+    /// Calls `sink` with the current id filters now and again whenever they change.
+    pub fn set_id_filter_sink(&self, sink: IdFilterSink) {
+        let mut state = self.state.lock().unwrap();
+        sink(&state.id_filters);
+        state.id_filter_sink = Some(sink);
+    }
+
+    /// Asks for every frame on the bus, e.g. to measure all of its traffic, rather
+    /// than just the ids the routes need. Routes still only get the frames they match.
+    pub fn set_receive_all(&self, receive_all: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.receive_all = receive_all;
+        state.update_id_filters();
+    }
+
+    /// Queues `raw` on every matching route, waiting on the ones under `Backpressure`.
+    pub async fn dispatch(&self, raw: &RawCanMessage) {
+        let queues: Vec<Arc<RouteQueue>> = {
+            let state = self.state.lock().unwrap();
+            state.routes.iter().filter(|r| (r.filter)(raw)).map(|r| r.queue.clone()).collect()
+        };
+        for queue in queues {
+            let mut raw = Some(raw.clone());
+            poll_fn(|cx| queue.poll_push(cx, &mut raw)).await;
+        }
+    }
+
+    /// Closes every route; receivers drain what is queued and then end.
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.id_filter_sink = None;
+        for route in state.routes.drain(..) {
+            route.queue.close();
+        }
+    }
+
+    pub fn stats(&self) -> Vec<RouteStats> {
+        let state = self.state.lock().unwrap();
+        state.routes.iter().map(|r| r.queue.stats(r.id)).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn remove(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        let before = state.routes.len();
+        state.routes.retain(|r| r.id != id);
+        if state.routes.len() != before {
+            state.update_id_filters();
+        }
+    }
+}
+
+/// The consuming end of a route. Dropping it removes the route.
+pub struct RouteReceiver {
+    id: u64,
+    queue: Arc<RouteQueue>,
+    dispatcher: Dispatcher,
+}
+
+impl RouteReceiver {
+    /// Waits for the next frame. `None` once the route is closed and drained.
+    pub async fn recv(&self) -> Option<RawCanMessage> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<RawCanMessage>> {
+        self.queue.poll_pop(cx)
+    }
+
+    /// Waits for the next frame that decodes as a `T`, counting and skipping the ones that do not.
+    pub async fn recv_message<T: CanMessageTrait>(&self) -> Option<T> {
+        poll_fn(|cx| self.poll_recv_message(cx)).await
+    }
+
+    pub fn poll_recv_message<T: CanMessageTrait>(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        loop {
+            match self.poll_recv(cx) {
+                Poll::Ready(Some(raw)) => {
+                    if let Some(msg) = self.decode(raw) {
+                        return Poll::Ready(Some(msg));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Decodes `raw` as a `T`, counting a failure against this route.
+    pub fn decode<T: CanMessageTrait>(&self, raw: RawCanMessage) -> Option<T> {
+        self.decode_with(raw, T::try_from_received)
+    }
+
+    /// Like `decode`, for messages that need more than the frame to decode.
+    pub fn decode_with<T>(&self, raw: RawCanMessage, decode: impl FnOnce(RawCanMessage) -> Result<T, DecodeError>) -> Option<T> {
+        match decode(raw) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                self.queue.decode_failed(&e);
+                None
+            }
+        }
+    }
+
+    /// Number of frames this route received but could not decode.
+    pub fn decode_errors(&self) -> u64 {
+        self.queue.decode_errors.load(Ordering::Relaxed)
+    }
+
+    /// Number of frames discarded because this route fell behind.
+    pub fn lagged(&self) -> u64 {
+        self.queue.lagged.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> RouteStats {
+        self.queue.stats(self.id)
+    }
+
+    /// Stops the route; pending `recv` calls return what is queued and then `None`.
+    pub fn close(&self) {
+        self.dispatcher.remove(self.id);
+        self.queue.close();
+    }
+}
+
+impl Drop for RouteReceiver {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::drivers::can::odrive_msgs::HeartbeatMessage;
+    use crate::drivers::can::transport::{CanTransport, TransportEvent};
+    use crate::drivers::can::virtual_bus::{VirtualBus, VirtualBusPort};
+
+    /// A dispatcher fed from a port that installs the routes' id filters, as a
+    /// `CanSimple` would, and a port to send from.
+    fn setup() -> (Dispatcher, Arc<VirtualBusPort>, VirtualBusPort) {
+        let bus = VirtualBus::new();
+        let port = Arc::new(bus.connect());
+        let dispatcher = Dispatcher::default();
+        dispatcher.set_id_filter_sink(Box::new({
+            let port = port.clone();
+            move |filters| port.set_filters(filters).unwrap()
+        }));
+        (dispatcher, port, bus.connect())
+    }
+
+    /// Dispatches what `port` receives until it has been quiet for a second.
+    async fn pump(port: &VirtualBusPort, dispatcher: &Dispatcher) {
+        while let Some(TransportEvent::Frame(raw)) = port.recv(Duration::from_secs(1)).await.unwrap() {
+            dispatcher.dispatch(&raw).await;
+        }
+    }
+
+    /// The ids queued on `route`, without waiting for more.
+    fn queued(route: &RouteReceiver) -> Vec<u32> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut ids = Vec::new();
+        while let Poll::Ready(Some(raw)) = route.poll_recv(&mut cx) {
+            ids.push(raw.arbitration_id);
+        }
+        ids
+    }
+
+    fn frame(id: u32) -> RawCanMessage {
+        RawCanMessage::new(id, vec![0; 8])
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn routes_get_the_frames_they_match() {
+        let (dispatcher, port, sender) = setup();
+        let odd = dispatcher.route("odd", Box::new(|raw| raw.arbitration_id % 2 == 1), vec![CanIdFilter::exact(0x3), CanIdFilter::exact(0x7)], OverflowPolicy::DropOldest);
+        let node_2 = dispatcher.route_for::<HeartbeatMessage>(Some(2), OverflowPolicy::DropOldest);
+        let any_node = dispatcher.route_for::<HeartbeatMessage>(None, OverflowPolicy::DropOldest);
+
+        // 0x5 is odd but outside every id filter, so the port never sees it.
+        for id in [0x3, 0x5, 0x7, 1 << 5 | 0x01, 2 << 5 | 0x01, 2 << 5 | 0x02] {
+            sender.send(&frame(id)).await.unwrap();
+        }
+        sender.send(&RawCanMessage { is_extended_id: true, ..frame(2 << 5 | 0x01) }).await.unwrap();
+        pump(&port, &dispatcher).await;
+
+        assert_eq!(queued(&odd), [0x3, 0x7, 1 << 5 | 0x01, 2 << 5 | 0x01]);
+        assert_eq!(queued(&node_2), [2 << 5 | 0x01]);
+        assert_eq!(queued(&any_node), [1 << 5 | 0x01, 2 << 5 | 0x01]);
+        assert_eq!(dispatcher.stats().iter().map(|s| s.delivered).collect::<Vec<_>>(), [4, 1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn id_filters_are_merged() {
+        let (dispatcher, port, sender) = setup();
+        let installed = Arc::new(Mutex::new(Vec::new()));
+        dispatcher.set_id_filter_sink(Box::new({
+            let (port, installed) = (port.clone(), installed.clone());
+            move |filters| {
+                installed.lock().unwrap().push(filters.to_vec());
+                port.set_filters(filters).unwrap();
+            }
+        }));
+        assert_eq!(dispatcher.id_filters(), []);
+
+        let heartbeats = dispatcher.route_for::<HeartbeatMessage>(Some(2), OverflowPolicy::DropOldest);
+        let again = dispatcher.route_for::<HeartbeatMessage>(Some(2), OverflowPolicy::DropOldest);
+        let expected = HeartbeatMessage::can_filters(Some(2));
+        assert_eq!(dispatcher.id_filters(), expected);
+
+        // Past MAX_ID_FILTERS the kernel is asked for everything, until the route goes away.
+        let many = (0..=MAX_ID_FILTERS as u32).map(|i| CanIdFilter::exact(0x400 + i)).collect();
+        let wide = dispatcher.route("wide", Box::new(|_| false), many, OverflowPolicy::DropOldest);
+        assert_eq!(dispatcher.id_filters(), [CanIdFilter::ACCEPT_ALL]);
+        sender.send(&frame(0x7FF)).await.unwrap();
+        assert!(port.recv(Duration::from_millis(10)).await.unwrap().is_some());
+        drop(wide);
+        assert_eq!(dispatcher.id_filters(), expected);
+        sender.send(&frame(0x7FF)).await.unwrap();
+        assert!(port.recv(Duration::from_millis(10)).await.unwrap().is_none());
+
+        dispatcher.set_receive_all(true);
+        assert_eq!(dispatcher.id_filters(), [CanIdFilter::ACCEPT_ALL]);
+        dispatcher.set_receive_all(false);
+        drop((heartbeats, again));
+        assert_eq!(dispatcher.id_filters(), []);
+
+        // The sink only hears about changes; the duplicate route changed nothing.
+        let all = vec![CanIdFilter::ACCEPT_ALL];
+        assert_eq!(*installed.lock().unwrap(), [vec![], expected.clone(), all.clone(), expected.clone(), all, expected, vec![]]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_oldest_lags() {
+        let (dispatcher, port, sender) = setup();
+        let route = dispatcher.route("slow", Box::new(|_| true), vec![CanIdFilter::ACCEPT_ALL], OverflowPolicy::DropOldest);
+        for id in 0..ROUTE_QUEUE_SIZE as u32 + 10 {
+            sender.send(&frame(id)).await.unwrap();
+        }
+        pump(&port, &dispatcher).await;
+        assert_eq!(queued(&route), (10..ROUTE_QUEUE_SIZE as u32 + 10).collect::<Vec<_>>());
+        assert_eq!((route.lagged(), route.stats().delivered), (10, ROUTE_QUEUE_SIZE as u64 + 10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backpressure_holds_the_receive_loop() {
+        let (dispatcher, port, sender) = setup();
+        let route = dispatcher.route("slow", Box::new(|_| true), vec![CanIdFilter::ACCEPT_ALL], OverflowPolicy::Backpressure);
+        let pumping = tokio::spawn({
+            let dispatcher = dispatcher.clone();
+            async move { pump(&port, &dispatcher).await }
+        });
+        for id in 0..ROUTE_QUEUE_SIZE as u32 + 10 {
+            sender.send(&frame(id)).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(!pumping.is_finished());
+        assert_eq!((route.stats().queued, route.lagged()), (ROUTE_QUEUE_SIZE, 0));
+
+        // Nothing is lost once the consumer catches up.
+        for id in 0..ROUTE_QUEUE_SIZE as u32 + 10 {
+            assert_eq!(route.recv().await.unwrap().arbitration_id, id);
+        }
+        pumping.await.unwrap();
+        assert_eq!(route.stats().delivered, ROUTE_QUEUE_SIZE as u64 + 10);
+    }
+}
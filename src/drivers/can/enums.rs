@@ -1,9 +1,13 @@
+use super::messages::DecodeError;
+
 /// Common enums for the CAN bus protocols
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BusType {
     SocketCan,
     Virtual,
+    /// A LAWICEL adapter; the interface names its tty, e.g. `/dev/ttyACM0`.
+    Slcan,
 }
 
 impl BusType {
@@ -11,6 +15,7 @@ impl BusType {
         match self {
             BusType::SocketCan => "socketcan",
             BusType::Virtual => "virtual",
+            BusType::Slcan => "slcan",
         }
     }
 }
@@ -21,18 +26,27 @@ pub enum CanInterface {
     Odrive,
     Myactuator,
     Virtual,
+    /// Any interface by name, e.g. `can1` or `vcan0`.
+    Named(String),
 }
 
 impl CanInterface {
-    pub fn value(&self) -> &'static str {
+    pub fn value(&self) -> &str {
         match self {
             CanInterface::Odrive => "can0",
             CanInterface::Myactuator => "can0",
             CanInterface::Virtual => "vcan",
+            CanInterface::Named(name) => name,
         }
     }
 }
 
+impl From<&str> for CanInterface {
+    fn from(name: &str) -> Self {
+        CanInterface::Named(name.to_string())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum X424MotorError {
     /// Specifies the error codes for the X4-24 motor.
@@ -58,16 +72,16 @@ impl X424MotorError {
         }
     }
 
-    pub fn from_value(value: u8) -> Self {
+    pub fn from_value(value: u8) -> Option<Self> {
         match value {
-            0 => Self::NoError,
-            1 => Self::MotorOverheating,
-            2 => Self::MotorOvercurrent,
-            3 => Self::MotorVoltageTooLow,
-            4 => Self::MotorEncoderError,
-            6 => Self::MotorBrakeVoltageTooHigh,
-            7 => Self::DrvDriveError,
-            _ => Self::NoError,
+            0 => Some(Self::NoError),
+            1 => Some(Self::MotorOverheating),
+            2 => Some(Self::MotorOvercurrent),
+            3 => Some(Self::MotorVoltageTooLow),
+            4 => Some(Self::MotorEncoderError),
+            6 => Some(Self::MotorBrakeVoltageTooHigh),
+            7 => Some(Self::DrvDriveError),
+            _ => None,
         }
     }
 }
@@ -186,9 +200,12 @@ pub enum AxisState {
     AnticoggingCalibration = 14,
 }
 
-impl From<u8> for AxisState {
-    fn from(value: u8) -> Self {
-        match value {
+impl TryFrom<u8> for AxisState {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, DecodeError> {
+        Ok(match value {
+            0 => AxisState::Undefined,
             1 => AxisState::Idle,
             2 => AxisState::StartupSequence,
             3 => AxisState::FullCalibrationSequence,
@@ -202,8 +219,19 @@ impl From<u8> for AxisState {
             12 => AxisState::EncoderHallPolarityCalibration,
             13 => AxisState::EncoderHallPhaseCalibration,
             14 => AxisState::AnticoggingCalibration,
-            _ => AxisState::Undefined,
-        }
+            _ => return Err(DecodeError::UnknownEnumValue { name: "AxisState", value: value as u32 }),
+        })
+    }
+}
+
+/// Set_Axis_State carries the state as a u32, the heartbeat as a u8.
+impl TryFrom<u32> for AxisState {
+    type Error = DecodeError;
+
+    fn try_from(value: u32) -> Result<Self, DecodeError> {
+        u8::try_from(value)
+            .map_err(|_| DecodeError::UnknownEnumValue { name: "AxisState", value })
+            .and_then(AxisState::try_from)
     }
 }
 
@@ -215,15 +243,17 @@ pub enum ControlMode {
     PositionControl = 3,
 }
 
-impl From<u32> for ControlMode {
-    fn from(value: u32) -> Self {
-        match value {
+impl TryFrom<u32> for ControlMode {
+    type Error = DecodeError;
+
+    fn try_from(value: u32) -> Result<Self, DecodeError> {
+        Ok(match value {
             0 => ControlMode::VoltageControl,
             1 => ControlMode::TorqueControl,
             2 => ControlMode::VelocityControl,
             3 => ControlMode::PositionControl,
-            _ => ControlMode::VoltageControl,
-        }
+            _ => return Err(DecodeError::UnknownEnumValue { name: "ControlMode", value }),
+        })
     }
 }
 
@@ -239,9 +269,11 @@ pub enum InputMode {
     Tuning = 8,
 }
 
-impl From<u32> for InputMode {
-    fn from(value: u32) -> Self {
-        match value {
+impl TryFrom<u32> for InputMode {
+    type Error = DecodeError;
+
+    fn try_from(value: u32) -> Result<Self, DecodeError> {
+        Ok(match value {
             0 => InputMode::Inactive,
             1 => InputMode::Passthrough,
             2 => InputMode::VelRamp,
@@ -250,8 +282,8 @@ impl From<u32> for InputMode {
             6 => InputMode::TorqueRamp,
             7 => InputMode::Mirror,
             8 => InputMode::Tuning,
-            _ => InputMode::Inactive,
-        }
+            _ => return Err(DecodeError::UnknownEnumValue { name: "InputMode", value }),
+        })
     }
 }
 
@@ -331,9 +363,11 @@ pub enum ProcedureResult {
     NotConverging = 15,
 }
 
-impl From<u8> for ProcedureResult {
-    fn from(value: u8) -> Self {
-        match value {
+impl TryFrom<u8> for ProcedureResult {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, DecodeError> {
+        Ok(match value {
             0 => ProcedureResult::Success,
             1 => ProcedureResult::Busy,
             2 => ProcedureResult::Cancelled,
@@ -350,8 +384,8 @@ impl From<u8> for ProcedureResult {
             13 => ProcedureResult::InvalidState,
             14 => ProcedureResult::NotCalibrated,
             15 => ProcedureResult::NotConverging,
-            _ => ProcedureResult::Success,
-        }
+            _ => return Err(DecodeError::UnknownEnumValue { name: "ProcedureResult", value: value as u32 }),
+        })
     }
 }
 
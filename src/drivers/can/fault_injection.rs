@@ -0,0 +1,587 @@
+//! Fault injection for robustness testing.
+//!
+//! `FaultInjectingTransport` wraps another transport and, following `FaultRule`s
+//! picked per arbitration id and direction, drops, delays, duplicates, reorders
+//! or corrupts the frames passing through it. It can also raise error frames and
+//! bus-off periods on a schedule. Randomness comes from a seeded generator, so a
+//! failing run can be repeated exactly as long as the traffic is the same.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::time::Instant;
+
+use super::bus_events::{BusErrorFrame, BusErrorKind, BusState, ErrorCounters};
+use super::messages::{CanIdFilter, RawCanMessage, RxTimestamp};
+use super::periodic::PeriodicTx;
+use super::recorder::Direction;
+use super::transport::{CanTransport, TransportEvent, TransportFuture};
+
+/// How long a frame held back for reordering waits for another to overtake it.
+const REORDER_HOLD: Duration = Duration::from_millis(50);
+
+/// What may happen to a frame. Probabilities are in 0..=1 and are drawn in the
+/// order of the fields: a dropped frame is not also delayed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FaultPolicy {
+    pub drop: f64,
+    pub delay: f64,
+    /// How long a delayed frame is held, drawn uniformly.
+    pub delay_range: (Duration, Duration),
+    pub duplicate: f64,
+    /// The chance the frame is held back until the next one has gone past it.
+    pub reorder: f64,
+    /// The chance one random bit of the payload is flipped.
+    pub bit_flip: f64,
+}
+
+/// Applies `policy` to frames in `direction`, or both, whose id passes `filter`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultRule {
+    pub direction: Option<Direction>,
+    pub filter: CanIdFilter,
+    pub policy: FaultPolicy,
+}
+
+impl FaultRule {
+    /// Applies to every frame in both directions.
+    pub fn all(policy: FaultPolicy) -> Self {
+        Self { direction: None, filter: CanIdFilter::ACCEPT_ALL, policy }
+    }
+
+    fn matches(&self, direction: Direction, raw: &RawCanMessage) -> bool {
+        self.direction.is_none_or(|d| d == direction) && self.filter.accepts_frame(raw)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduledFault {
+    /// Hands `recv` this error frame.
+    Error(BusErrorFrame),
+    /// Reports bus-off, loses every frame for `duration`, then reports a restart.
+    BusOff { duration: Duration },
+}
+
+/// `fault` at `at` after the transport was created, and every `every` after that.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultSchedule {
+    pub at: Duration,
+    pub every: Option<Duration>,
+    pub fault: ScheduledFault,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FaultStats {
+    pub dropped: u64,
+    pub delayed: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    pub corrupted: u64,
+    /// Error frames raised by the schedule.
+    pub injected_errors: u64,
+    /// Frames lost while bus-off.
+    pub lost_bus_off: u64,
+}
+
+/// SplitMix64: small, fast and good enough to decide faults.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+
+    fn duration(&mut self, (low, high): (Duration, Duration)) -> Duration {
+        low + high.saturating_sub(low).mul_f64(self.next_f64())
+    }
+}
+
+/// What the policy decided for one frame.
+#[derive(Default)]
+struct Verdict {
+    drop: bool,
+    delay: Option<Duration>,
+    duplicate: bool,
+    reorder: bool,
+}
+
+struct PendingSchedule {
+    next: Instant,
+    every: Option<Duration>,
+    fault: ScheduledFault,
+}
+
+struct FaultState {
+    rng: Rng,
+    rules: Vec<FaultRule>,
+    schedule: Vec<PendingSchedule>,
+    bus_off_until: Option<Instant>,
+    stats: FaultStats,
+    /// Received events ready to hand out.
+    ready: VecDeque<TransportEvent>,
+    /// Received frames being delayed, with when they are due.
+    delayed: Vec<(Instant, RawCanMessage)>,
+    /// Frames to send being delayed, with when they are due.
+    delayed_tx: Vec<(Instant, RawCanMessage)>,
+    held_rx: Option<(Instant, RawCanMessage)>,
+    held_tx: Option<(Instant, RawCanMessage)>,
+}
+
+impl FaultState {
+    /// Decides the fate of `raw`, corrupting it in place if the policy says so.
+    fn judge(&mut self, direction: Direction, raw: &mut RawCanMessage) -> Verdict {
+        let Some(policy) = self.rules.iter().find(|rule| rule.matches(direction, raw)).map(|rule| rule.policy.clone()) else {
+            return Verdict::default();
+        };
+        let rng = &mut self.rng;
+        if rng.chance(policy.drop) {
+            self.stats.dropped += 1;
+            return Verdict { drop: true, ..Default::default() };
+        }
+        let mut verdict = Verdict::default();
+        if rng.chance(policy.delay) {
+            self.stats.delayed += 1;
+            verdict.delay = Some(rng.duration(policy.delay_range));
+        }
+        if rng.chance(policy.duplicate) {
+            self.stats.duplicated += 1;
+            verdict.duplicate = true;
+        }
+        if rng.chance(policy.reorder) {
+            self.stats.reordered += 1;
+            verdict.reorder = true;
+        }
+        if rng.chance(policy.bit_flip) && !raw.data.is_empty() {
+            let bit = (rng.next_u64() % (raw.data.len() as u64 * 8)) as usize;
+            raw.data[bit / 8] ^= 1 << (bit % 8);
+            self.stats.corrupted += 1;
+        }
+        verdict
+    }
+
+    fn is_bus_off(&self, now: Instant) -> bool {
+        self.bus_off_until.is_some_and(|until| now < until)
+    }
+
+    /// Moves whatever has come due into `ready`, and says when something next will.
+    fn release(&mut self, now: Instant) -> Option<Instant> {
+        let mut wake: Option<Instant> = None;
+        let mut soonest = |at: Instant| wake = Some(wake.map_or(at, |w| w.min(at)));
+
+        let mut fired = Vec::new();
+        self.schedule.retain_mut(|entry| {
+            if entry.next > now {
+                soonest(entry.next);
+                return true;
+            }
+            fired.push(entry.fault.clone());
+            match entry.every {
+                Some(every) if !every.is_zero() => {
+                    entry.next += every;
+                    soonest(entry.next);
+                    true
+                }
+                _ => false,
+            }
+        });
+        for fault in fired {
+            match fault {
+                ScheduledFault::Error(frame) => self.ready.push_back(TransportEvent::Error(frame)),
+                ScheduledFault::BusOff { duration } => {
+                    let counters = ErrorCounters { tx: 255, rx: 0 };
+                    self.ready.push_back(TransportEvent::Error(BusErrorFrame::state_change(BusState::BusOff, counters)));
+                    self.bus_off_until = Some(now + duration);
+                    soonest(now + duration);
+                }
+            }
+            self.stats.injected_errors += 1;
+        }
+
+        match self.bus_off_until {
+            Some(until) if until <= now => {
+                self.bus_off_until = None;
+                self.ready.push_back(TransportEvent::Error(BusErrorFrame::single(BusErrorKind::Restarted)));
+                self.stats.injected_errors += 1;
+            }
+            Some(until) => soonest(until),
+            None => {}
+        }
+
+        let (due, waiting): (Vec<_>, Vec<_>) = self.delayed.drain(..).partition(|(at, _)| *at <= now);
+        self.delayed = waiting;
+        for (_, mut raw) in due {
+            raw.timestamp = Some(RxTimestamp::now());
+            self.ready.push_back(TransportEvent::Frame(raw));
+        }
+        self.delayed.iter().for_each(|(at, _)| soonest(*at));
+
+        match self.held_rx.take() {
+            Some((since, raw)) if since + REORDER_HOLD <= now => self.ready.push_back(TransportEvent::Frame(raw)),
+            Some((since, raw)) => {
+                soonest(since + REORDER_HOLD);
+                self.held_rx = Some((since, raw));
+            }
+            None => {}
+        }
+        wake
+    }
+
+    /// Files a received frame according to the policy.
+    fn on_rx(&mut self, mut raw: RawCanMessage, now: Instant) {
+        if self.is_bus_off(now) {
+            self.stats.lost_bus_off += 1;
+            return;
+        }
+        let verdict = self.judge(Direction::Rx, &mut raw);
+        if verdict.drop {
+            return;
+        }
+        let copies = if verdict.duplicate { 2 } else { 1 };
+        for _ in 0..copies {
+            if let Some(delay) = verdict.delay {
+                self.delayed.push((now + delay, raw.clone()));
+            } else if verdict.reorder && self.held_rx.is_none() {
+                self.held_rx = Some((now, raw.clone()));
+            } else {
+                self.ready.push_back(TransportEvent::Frame(raw.clone()));
+                // The frame held back goes after the one that overtook it.
+                if let Some((_, held)) = self.held_rx.take() {
+                    self.ready.push_back(TransportEvent::Frame(held));
+                }
+            }
+        }
+    }
+}
+
+struct Shared {
+    started: Instant,
+    state: Mutex<FaultState>,
+}
+
+pub struct FaultInjectingTransport<T> {
+    inner: T,
+    shared: Arc<Shared>,
+}
+
+impl<T: CanTransport> FaultInjectingTransport<T> {
+    /// Passes everything through until rules are added; `seed` fixes every random choice.
+    pub fn new(inner: T, seed: u64) -> Self {
+        let state = FaultState {
+            rng: Rng(seed),
+            rules: Vec::new(),
+            schedule: Vec::new(),
+            bus_off_until: None,
+            stats: FaultStats::default(),
+            ready: VecDeque::new(),
+            delayed: Vec::new(),
+            delayed_tx: Vec::new(),
+            held_rx: None,
+            held_tx: None,
+        };
+        Self { inner, shared: Arc::new(Shared { started: Instant::now(), state: Mutex::new(state) }) }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// A handle to change the faults while a `CanSimple` owns the transport.
+    pub fn control(&self) -> FaultControl {
+        FaultControl { shared: self.shared.clone() }
+    }
+
+    /// Sends the delayed frames that have come due, and a frame held back for
+    /// reordering once nothing has overtaken it in time, or at once if `force`.
+    async fn flush_tx(&self, force: bool) -> Result<()> {
+        let due = {
+            let mut state = self.shared.state.lock().unwrap();
+            let now = Instant::now();
+            let (mut due, waiting): (Vec<_>, Vec<_>) = state.delayed_tx.drain(..).partition(|(at, _)| *at <= now);
+            state.delayed_tx = waiting;
+            due.sort_by_key(|(at, _)| *at);
+            let mut due: Vec<RawCanMessage> = due.into_iter().map(|(_, raw)| raw).collect();
+            match state.held_tx.take() {
+                Some((since, raw)) if force || since + REORDER_HOLD <= now => due.push(raw),
+                held => state.held_tx = held,
+            }
+            // Frames that come due while bus-off never make it onto the bus.
+            if state.is_bus_off(now) {
+                state.stats.lost_bus_off += due.len() as u64;
+                due.clear();
+            }
+            due
+        };
+        for raw in &due {
+            self.inner.send(raw).await?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: CanTransport> CanTransport for FaultInjectingTransport<T> {
+    fn open(channel: &str) -> Result<Self> {
+        Err(anyhow!("Fault injection on {} needs a seed, use FaultInjectingTransport::new", channel))
+    }
+
+    fn channel(&self) -> &str {
+        self.inner.channel()
+    }
+
+    /// Delayed frames are held and sent by `recv` once due, so they do not hold up the frames behind them.
+    fn send<'a>(&'a self, msg: &'a RawCanMessage) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            let mut raw = msg.clone();
+            let verdict = {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.is_bus_off(Instant::now()) {
+                    state.stats.lost_bus_off += 1;
+                    return Err(anyhow!("{} is bus-off", self.inner.channel()));
+                }
+                let verdict = state.judge(Direction::Tx, &mut raw);
+                if verdict.drop {
+                    return Ok(());
+                }
+                if let Some(delay) = verdict.delay {
+                    let copies = if verdict.duplicate { 2 } else { 1 };
+                    let due = Instant::now() + delay;
+                    state.delayed_tx.extend(std::iter::repeat_n((due, raw), copies));
+                    return Ok(());
+                }
+                if verdict.reorder && state.held_tx.is_none() {
+                    state.held_tx = Some((Instant::now(), raw));
+                    return Ok(());
+                }
+                verdict
+            };
+            self.inner.send(&raw).await?;
+            if verdict.duplicate {
+                self.inner.send(&raw).await?;
+            }
+            self.flush_tx(true).await
+        })
+    }
+
+    fn recv(&self, timeout: Duration) -> TransportFuture<'_, Option<TransportEvent>> {
+        Box::pin(async move {
+            let deadline = Instant::now() + timeout;
+            loop {
+                self.flush_tx(false).await?;
+                let wake = {
+                    let mut state = self.shared.state.lock().unwrap();
+                    let wake = state.release(Instant::now());
+                    if let Some(event) = state.ready.pop_front() {
+                        return Ok(Some(event));
+                    }
+                    let held_tx = state.held_tx.as_ref().map(|(since, _)| *since + REORDER_HOLD);
+                    let delayed_tx = state.delayed_tx.iter().map(|(at, _)| *at).min();
+                    [wake, held_tx, delayed_tx].into_iter().flatten().fold(deadline, Instant::min)
+                };
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(None);
+                }
+                match self.inner.recv(wake.saturating_duration_since(now)).await? {
+                    Some(TransportEvent::Frame(raw)) => self.shared.state.lock().unwrap().on_rx(raw, Instant::now()),
+                    Some(event) => return Ok(Some(event)),
+                    None => {}
+                }
+            }
+        })
+    }
+
+    fn set_filters(&self, filters: &[CanIdFilter]) -> Result<()> {
+        self.inner.set_filters(filters)
+    }
+
+    fn start_periodic(&self, _msg: &RawCanMessage, _period: Duration) -> Result<Option<Box<dyn PeriodicTx>>> {
+        // Frames the transport sends on its own would never meet the faults.
+        Ok(None)
+    }
+
+    fn can_reopen(&self) -> bool {
+        self.inner.can_reopen()
+    }
+
+    fn reopen(&self) -> TransportFuture<'_, ()> {
+        self.inner.reopen()
+    }
+}
+
+/// Changes the faults of a `FaultInjectingTransport` and reads its counters.
+#[derive(Clone)]
+pub struct FaultControl {
+    shared: Arc<Shared>,
+}
+
+impl FaultControl {
+    /// Adds a rule after the existing ones. A frame gets the policy of the first rule it matches.
+    pub fn add_rule(&self, rule: FaultRule) {
+        self.shared.state.lock().unwrap().rules.push(rule);
+    }
+
+    pub fn clear_rules(&self) {
+        self.shared.state.lock().unwrap().rules.clear();
+    }
+
+    pub fn schedule(&self, schedule: FaultSchedule) {
+        let next = self.shared.started + schedule.at;
+        let entry = PendingSchedule { next, every: schedule.every, fault: schedule.fault };
+        self.shared.state.lock().unwrap().schedule.push(entry);
+    }
+
+    /// Drops pending scheduled faults. A bus-off already under way runs its course.
+    pub fn clear_schedule(&self) {
+        self.shared.state.lock().unwrap().schedule.clear();
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.shared.state.lock().unwrap().stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::can::virtual_bus::{VirtualBus, VirtualBusPort};
+
+    /// A faulty port and a clean one on the same bus.
+    fn pair(seed: u64) -> (FaultInjectingTransport<VirtualBusPort>, VirtualBusPort) {
+        let bus = VirtualBus::new();
+        (FaultInjectingTransport::new(bus.connect(), seed), bus.connect())
+    }
+
+    fn frame(id: u32) -> RawCanMessage {
+        RawCanMessage::new(id, vec![id as u8; 8])
+    }
+
+    /// Everything `port` receives until it has been quiet for a second.
+    async fn drain(port: &impl CanTransport) -> Vec<(u32, Vec<u8>)> {
+        let mut frames = Vec::new();
+        while let Some(event) = port.recv(Duration::from_secs(1)).await.unwrap() {
+            if let TransportEvent::Frame(raw) = event {
+                frames.push((raw.arbitration_id, raw.data));
+            }
+        }
+        frames
+    }
+
+    fn ids(frames: &[(u32, Vec<u8>)]) -> Vec<u32> {
+        frames.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_seed_decides_every_fault() {
+        async fn run(seed: u64) -> (Vec<(u32, Vec<u8>)>, FaultStats) {
+            let (faulty, clean) = pair(seed);
+            let policy = FaultPolicy { drop: 0.2, duplicate: 0.2, bit_flip: 0.3, ..Default::default() };
+            faulty.control().add_rule(FaultRule::all(policy));
+            for id in 0..100 {
+                clean.send(&frame(id)).await.unwrap();
+            }
+            (drain(&faulty).await, faulty.control().stats())
+        }
+
+        let (frames, stats) = run(7).await;
+        assert!(stats.dropped > 0 && stats.duplicated > 0 && stats.corrupted > 0, "{:?}", stats);
+        assert_eq!(frames.len() as u64, 100 - stats.dropped + stats.duplicated);
+        assert_eq!(run(7).await, (frames.clone(), stats));
+        assert_ne!(run(8).await.0, frames);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rules_pick_frames_by_direction_and_id() {
+        let (faulty, clean) = pair(1);
+        let drop = FaultPolicy { drop: 1.0, ..Default::default() };
+        let control = faulty.control();
+        control.add_rule(FaultRule { direction: Some(Direction::Rx), filter: CanIdFilter::exact(0x100), policy: drop.clone() });
+        control.add_rule(FaultRule { direction: Some(Direction::Tx), filter: CanIdFilter::exact(0x200), policy: drop });
+
+        for id in [0x100, 0x200, 0x300] {
+            clean.send(&frame(id)).await.unwrap();
+        }
+        assert_eq!(ids(&drain(&faulty).await), [0x200, 0x300]);
+        for id in [0x100, 0x200, 0x300] {
+            faulty.send(&frame(id)).await.unwrap();
+        }
+        assert_eq!(ids(&drain(&clean).await), [0x100, 0x300]);
+        assert_eq!(control.stats().dropped, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn held_frames_are_released() {
+        let (faulty, clean) = pair(1);
+        let control = faulty.control();
+        let reorder = FaultPolicy { reorder: 1.0, ..Default::default() };
+        let delay = FaultPolicy { delay: 1.0, delay_range: (Duration::from_millis(100), Duration::from_millis(100)), ..Default::default() };
+        control.add_rule(FaultRule { direction: None, filter: CanIdFilter::exact(0x1), policy: reorder });
+        control.add_rule(FaultRule { direction: None, filter: CanIdFilter::exact(0x2), policy: delay });
+
+        // Received: overtaken by the next frame, or let go after the hold.
+        clean.send(&frame(0x1)).await.unwrap();
+        clean.send(&frame(0x3)).await.unwrap();
+        assert_eq!(ids(&drain(&faulty).await), [0x3, 0x1]);
+        let start = Instant::now();
+        clean.send(&frame(0x1)).await.unwrap();
+        assert!(matches!(faulty.recv(Duration::from_secs(1)).await.unwrap(), Some(TransportEvent::Frame(raw)) if raw.arbitration_id == 0x1));
+        assert_eq!(start.elapsed(), REORDER_HOLD);
+
+        let start = Instant::now();
+        clean.send(&frame(0x2)).await.unwrap();
+        clean.send(&frame(0x3)).await.unwrap();
+        assert_eq!(ids(&drain(&faulty).await), [0x3, 0x2]);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // Sent: the same, with `recv` letting the held frames go.
+        faulty.send(&frame(0x1)).await.unwrap();
+        faulty.send(&frame(0x3)).await.unwrap();
+        assert_eq!(ids(&drain(&clean).await), [0x3, 0x1]);
+        faulty.send(&frame(0x1)).await.unwrap();
+        assert!(faulty.recv(Duration::from_secs(1)).await.unwrap().is_none());
+        assert_eq!(ids(&drain(&clean).await), [0x1]);
+
+        // A delayed frame does not hold up the ones behind it.
+        let start = Instant::now();
+        faulty.send(&frame(0x2)).await.unwrap();
+        faulty.send(&frame(0x3)).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(ids(&drain(&clean).await), [0x3]);
+        assert!(faulty.recv(Duration::from_millis(150)).await.unwrap().is_none());
+        assert_eq!(ids(&drain(&clean).await), [0x2]);
+        assert_eq!((control.stats().reordered, control.stats().delayed), (4, 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bus_off_fails_sends_until_the_restart() {
+        let (faulty, clean) = pair(1);
+        let control = faulty.control();
+        let duration = Duration::from_millis(100);
+        control.schedule(FaultSchedule { at: Duration::from_millis(10), every: None, fault: ScheduledFault::BusOff { duration } });
+
+        let Some(TransportEvent::Error(bus_off)) = faulty.recv(Duration::from_secs(1)).await.unwrap() else { panic!("no bus-off") };
+        assert_eq!(bus_off.state(), Some(BusState::BusOff));
+        assert!(faulty.send(&frame(0x1)).await.is_err());
+        clean.send(&frame(0x2)).await.unwrap();
+
+        let Some(TransportEvent::Error(restart)) = faulty.recv(Duration::from_secs(1)).await.unwrap() else { panic!("no restart") };
+        assert!(restart.has(&BusErrorKind::Restarted));
+        assert_eq!(control.stats().lost_bus_off, 2);
+        assert_eq!(control.stats().injected_errors, 2);
+
+        faulty.send(&frame(0x3)).await.unwrap();
+        assert_eq!(ids(&drain(&clean).await), [0x3]);
+    }
+}
@@ -1,10 +1,191 @@
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
+use anyhow::{anyhow, Result};
+
+use super::tx_queue::TxPriority;
+
+pub use havendrive_derive::CanMessage;
+
+pub const CAN_MAX_DLEN: usize = 8;
+pub const CANFD_MAX_DLEN: usize = 64;
+
+/// Payload sizes a CAN FD frame can encode in its DLC.
+pub const CANFD_VALID_DLENS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Which clock took a receive timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimestampSource {
+    /// The adapter's own clock. Only comparable with other hardware stamps from the same adapter.
+    Hardware,
+    /// The kernel, as the frame came in, on the realtime clock.
+    Kernel,
+    /// The receiving process, on the realtime clock. Includes scheduling delay.
+    Userspace,
+}
+
+/// When a frame was received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RxTimestamp {
+    /// Time since the Unix epoch, or since the adapter's epoch for `Hardware` stamps.
+    pub time: Duration,
+    pub source: TimestampSource,
+}
+
+impl RxTimestamp {
+    pub fn now() -> Self {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self { time, source: TimestampSource::Userspace }
+    }
+
+    /// Time elapsed between `earlier` and `self`, if both were taken on the same kind of clock.
+    pub fn duration_since(&self, earlier: &RxTimestamp) -> Option<Duration> {
+        let hardware = |t: &RxTimestamp| t.source == TimestampSource::Hardware;
+        if hardware(self) != hardware(earlier) {
+            return None;
+        }
+        Some(self.time.saturating_sub(earlier.time))
+    }
+}
+
+/// Why a frame could not be decoded as a particular message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The arbitration id does not carry this message.
+    WrongId { arbitration_id: u32 },
+    /// The payload is shorter than the message layout.
+    ShortPayload { expected: usize, actual: usize },
+    /// A field holds a value its enum has no variant for.
+    UnknownEnumValue { name: &'static str, value: u32 },
+    /// The command byte in the payload belongs to another message.
+    BadCommandByte { expected: u8, actual: u8 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::WrongId { arbitration_id } => write!(f, "arbitration id 0x{:03X} does not carry this message", arbitration_id),
+            DecodeError::ShortPayload { expected, actual } => write!(f, "payload has {} bytes, expected at least {}", actual, expected),
+            DecodeError::UnknownEnumValue { name, value } => write!(f, "unknown {} value {}", name, value),
+            DecodeError::BadCommandByte { expected, actual } => write!(f, "command byte 0x{:02X}, expected 0x{:02X}", actual, expected),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl DecodeError {
+    /// Fails with `BadCommandByte` unless `actual` is `expected`.
+    pub fn check_command(expected: u8, actual: u8) -> Result<(), DecodeError> {
+        if actual != expected {
+            return Err(DecodeError::BadCommandByte { expected, actual });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct RawCanMessage {
     pub arbitration_id: u32,
     pub data: Vec<u8>,
     pub is_extended_id: bool,
+    /// A CAN FD frame, carrying up to `CANFD_MAX_DLEN` bytes.
+    pub is_fd: bool,
+    /// FD only: the data phase is sent at the data bitrate.
+    pub bitrate_switch: bool,
+    /// FD only: the transmitting node is error passive.
+    pub error_state_indicator: bool,
+    /// Set on received frames, `None` on frames built for sending.
+    pub timestamp: Option<RxTimestamp>,
+}
+
+impl RawCanMessage {
+    /// A classic frame with an 11-bit id.
+    pub fn new(arbitration_id: u32, data: Vec<u8>) -> Self {
+        Self { arbitration_id, data, ..Default::default() }
+    }
+
+    /// An FD frame with an 11-bit id, sent with bitrate switching if `bitrate_switch` is set.
+    pub fn new_fd(arbitration_id: u32, data: Vec<u8>, bitrate_switch: bool) -> Self {
+        Self { arbitration_id, data, is_fd: true, bitrate_switch, ..Default::default() }
+    }
+
+    /// Whether `other` is the same frame on the wire, whenever either was received.
+    pub fn same_frame(&self, other: &RawCanMessage) -> bool {
+        self.arbitration_id == other.arbitration_id
+            && self.is_extended_id == other.is_extended_id
+            && self.is_fd == other.is_fd
+            && self.data == other.data
+    }
+
+    /// Fails with `ShortPayload` if the payload has fewer than `len` bytes.
+    pub fn require_len(&self, len: usize) -> Result<(), DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::ShortPayload { expected: len, actual: self.data.len() });
+        }
+        Ok(())
+    }
+
+    /// The `N` payload bytes starting at `offset`.
+    pub fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N], DecodeError> {
+        self.data
+            .get(offset..offset + N)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(DecodeError::ShortPayload { expected: offset + N, actual: self.data.len() })
+    }
+
+    /// Checks that the frame can go on the wire as is: the id fits its format, the
+    /// payload fits the frame type, and FD flags only appear on FD frames.
+    pub fn validate(&self) -> Result<()> {
+        let max_id = if self.is_extended_id { 0x1FFF_FFFF } else { 0x7FF };
+        if self.arbitration_id > max_id {
+            return Err(anyhow!("Arbitration id 0x{:X} does not fit in {} bits", self.arbitration_id, if self.is_extended_id { 29 } else { 11 }));
+        }
+        let len = self.data.len();
+        if self.is_fd {
+            if !CANFD_VALID_DLENS.contains(&len) {
+                return Err(anyhow!("Invalid CAN FD data length {}, expected one of {:?}", len, CANFD_VALID_DLENS));
+            }
+        } else {
+            if len > CAN_MAX_DLEN {
+                return Err(anyhow!("Classic CAN frame carries at most {} bytes, got {}", CAN_MAX_DLEN, len));
+            }
+            if self.bitrate_switch || self.error_state_indicator {
+                return Err(anyhow!("BRS/ESI flags are only valid on CAN FD frames"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An 11-bit id/mask pair with kernel `can_filter` semantics: a frame passes when
+/// `frame_id & mask == id & mask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CanIdFilter {
+    pub id: u32,
+    pub mask: u32,
+}
+
+impl CanIdFilter {
+    pub const ACCEPT_ALL: Self = Self { id: 0, mask: 0 };
+
+    pub fn new(id: u32, mask: u32) -> Self {
+        Self { id, mask }
+    }
+
+    pub fn exact(id: u32) -> Self {
+        Self { id, mask: 0x7FF }
+    }
+
+    pub fn accepts(&self, arbitration_id: u32) -> bool {
+        arbitration_id & self.mask == self.id & self.mask
+    }
+
+    /// Whether `raw` passes, as the kernel would filter it: extended frames only pass `ACCEPT_ALL`.
+    pub fn accepts_frame(&self, raw: &RawCanMessage) -> bool {
+        *self == Self::ACCEPT_ALL || (!raw.is_extended_id && self.accepts(raw.arbitration_id))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -21,9 +202,26 @@ impl OdriveArbitrationId {
         }
     }
 
+    /// The id of `msg`, if it carries `cmd_id`.
+    pub fn for_cmd(msg: &RawCanMessage, cmd_id: u32) -> Result<Self, DecodeError> {
+        let arb = Self::from_can_message(msg);
+        if arb.cmd_id != cmd_id || msg.is_extended_id {
+            return Err(DecodeError::WrongId { arbitration_id: msg.arbitration_id });
+        }
+        Ok(arb)
+    }
+
     pub fn value(&self) -> u32 {
         (self.node_id << 5) | self.cmd_id
     }
+
+    /// Ids that can carry `cmd_id`, from any node or from `node_id` only.
+    pub fn can_filters(cmd_id: u32, node_id: Option<u32>) -> Vec<CanIdFilter> {
+        match node_id {
+            Some(node_id) => vec![CanIdFilter::exact((node_id << 5) | cmd_id)],
+            None => vec![CanIdFilter::new(cmd_id, 0b11111)],
+        }
+    }
 }
 
 impl Hash for OdriveArbitrationId {
@@ -41,27 +239,36 @@ pub struct MyActuatorArbitrationId {
 }
 
 impl MyActuatorArbitrationId {
-    pub fn from_can_message(msg: &RawCanMessage) -> Result<Self, &'static str> {
-        if (0x140..0x160).contains(&msg.arbitration_id) {
-            Ok(Self {
-                node_id: msg.arbitration_id - 0x140,
-                cmd_id: if !msg.data.is_empty() { msg.data[0] as u32 } else { return Err("No data for cmd_id"); },
-                custom_value: None,
-            })
+    pub fn from_can_message(msg: &RawCanMessage) -> Result<Self, DecodeError> {
+        let node_id = if (0x140..0x160).contains(&msg.arbitration_id) {
+            msg.arbitration_id - 0x140
         } else if (0x240..0x260).contains(&msg.arbitration_id) {
-            Ok(Self {
-                node_id: msg.arbitration_id - 0x240,
-                cmd_id: if !msg.data.is_empty() { msg.data[0] as u32 } else { return Err("No data for cmd_id"); },
-                custom_value: None,
-            })
+            msg.arbitration_id - 0x240
         } else {
-            Err("Invalid MyActuator arbitration ID")
-        }
+            return Err(DecodeError::WrongId { arbitration_id: msg.arbitration_id });
+        };
+        msg.require_len(1)?;
+        Ok(Self { node_id, cmd_id: msg.data[0] as u32, custom_value: None })
+    }
+
+    /// The id of `msg`, if its command byte is `cmd_id`.
+    pub fn for_cmd(msg: &RawCanMessage, cmd_id: u32) -> Result<Self, DecodeError> {
+        let arb = Self::from_can_message(msg)?;
+        DecodeError::check_command(cmd_id as u8, arb.cmd_id as u8)?;
+        Ok(arb)
     }
 
     pub fn value(&self) -> u32 {
         self.custom_value.unwrap_or(0x140 + self.node_id)
     }
+
+    /// The request (0x140 + id) and reply (0x240 + id) ranges, optionally for one node.
+    pub fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> {
+        match node_id {
+            Some(node_id) => vec![CanIdFilter::exact(0x140 + node_id), CanIdFilter::exact(0x240 + node_id)],
+            None => vec![CanIdFilter::new(0x140, 0x7E0), CanIdFilter::new(0x240, 0x7E0)],
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -81,12 +288,33 @@ impl X424ArbitrationId {
     pub fn value(&self) -> u32 {
         self.node_id
     }
+
+    /// X4-24 frames use the motor id as the arbitration id, so only a known node narrows the filter.
+    pub fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> {
+        match node_id {
+            Some(node_id) => vec![CanIdFilter::exact(node_id)],
+            None => vec![CanIdFilter::ACCEPT_ALL],
+        }
+    }
 }
 
 pub enum ArbitrationId {
     Odrive(OdriveArbitrationId),
     MyActuator(MyActuatorArbitrationId),
     X424(X424ArbitrationId),
+    /// An id with no node or command structure, such as one from a DBC file.
+    Raw(u32),
+}
+
+impl ArbitrationId {
+    pub fn value(&self) -> u32 {
+        match self {
+            ArbitrationId::Odrive(arb) => arb.value(),
+            ArbitrationId::MyActuator(arb) => arb.value(),
+            ArbitrationId::X424(arb) => arb.value(),
+            ArbitrationId::Raw(id) => *id,
+        }
+    }
 }
 
 pub trait CanMessageTrait {
@@ -96,13 +324,43 @@ pub trait CanMessageTrait {
 
     fn matches(msg: &RawCanMessage) -> bool where Self: Sized;
 
-    fn from_can_message(msg: RawCanMessage) -> Self where Self: Sized;
+    /// Kernel filters that let through every frame `matches` could accept, optionally
+    /// narrowed to `node_id`. May be wider than `matches`, never narrower.
+    fn can_filters(_node_id: Option<u32>) -> Vec<CanIdFilter> where Self: Sized {
+        vec![CanIdFilter::ACCEPT_ALL]
+    }
+
+    /// Decodes `msg`, failing if it does not carry this message or its payload does not fit.
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> where Self: Sized;
+
+    /// Where `CanSimple::send` queues this message.
+    fn tx_priority(&self) -> TxPriority { TxPriority::Normal }
+
+    /// Decodes a received frame, keeping its receive timestamp.
+    fn try_from_received(msg: RawCanMessage) -> Result<Self, DecodeError> where Self: Sized {
+        let timestamp = msg.timestamp;
+        let mut s = Self::try_from_can_message(msg)?;
+        s.set_timestamp(timestamp);
+        Ok(s)
+    }
+
+    /// When the frame this message was decoded from was received.
+    fn timestamp(&self) -> Option<RxTimestamp> { None }
+
+    fn set_timestamp(&mut self, _timestamp: Option<RxTimestamp>) {}
 
-    fn as_can_message(&self) -> RawCanMessage;
+    fn as_can_message(&self) -> RawCanMessage {
+        RawCanMessage {
+            arbitration_id: self.gen_arbitration_id().value(),
+            data: self.gen_can_msg_data(),
+            is_extended_id: false,
+            ..Default::default()
+        }
+    }
 
     fn gen_arbitration_id(&self) -> ArbitrationId;
 
     fn gen_can_msg_data(&self) -> Vec<u8>;
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage);
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError>;
 }
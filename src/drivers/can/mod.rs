@@ -1,7 +1,35 @@
 #[cfg(target_os = "linux")]
+pub mod bcm;
+pub mod bus_events;
+#[cfg(target_os = "linux")]
+pub mod bus_manager;
+pub mod bus_stats;
+pub mod candump;
+pub mod cannelloni;
+#[cfg(target_os = "linux")]
 pub mod connection;
+pub mod dbc_export;
+pub mod decode;
+pub mod dispatcher;
 pub mod enums;
+pub mod fault_injection;
 pub mod messages;
 pub mod myactuator_v3_msgs;
 pub mod myactuator_x424_msgs;
+#[cfg(target_os = "linux")]
+pub mod netlink;
 pub mod odrive_msgs;
+pub mod pcapng;
+pub mod periodic;
+pub mod recorder;
+pub mod replay;
+#[cfg(target_os = "linux")]
+pub mod slcan;
+#[cfg(target_os = "linux")]
+pub mod socketcan_transport;
+pub mod subscription;
+pub mod transport;
+pub mod tx_queue;
+pub mod virtual_bus;
+
+pub use havendrive_dbc as dbc;
@@ -1,5 +1,7 @@
+use crate::drivers::can::dbc::{ByteOrder, Signal, ValueType};
+use crate::drivers::can::dbc_export::{value_names, DbcLayout};
 use crate::drivers::can::enums::{MyActuatorFunctionControlIndex, MyActuatorV3OperatingMode};
-use crate::drivers::can::messages::{ArbitrationId, CanMessageTrait, MyActuatorArbitrationId, RawCanMessage};
+use crate::drivers::can::messages::{ArbitrationId, CanIdFilter, CanMessage, CanMessageTrait, DecodeError, MyActuatorArbitrationId, RawCanMessage, RxTimestamp};
 use chrono::NaiveDate;
 
 // Helper function for clipping
@@ -11,6 +13,7 @@ fn clip(val: i32, min_val: i32, max_val: i32) -> i32 {
 pub struct MyActuatorCanMessage {
     pub node_id: u32,
     pub arbitration_id: MyActuatorArbitrationId,
+    pub timestamp: Option<RxTimestamp>,
 }
 
 impl MyActuatorCanMessage {
@@ -20,7 +23,7 @@ impl MyActuatorCanMessage {
             cmd_id,
             custom_value: None,
         };
-        Self { node_id, arbitration_id }
+        Self { node_id, arbitration_id, timestamp: None }
     }
 }
 
@@ -29,15 +32,21 @@ impl CanMessageTrait for MyActuatorCanMessage {
 
     fn node_id(&self) -> u32 { self.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool where Self: Sized {
         (0x140..0x160).contains(&msg.arbitration_id) || (0x240..0x260).contains(&msg.arbitration_id)
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self where Self: Sized {
-        let arb_id = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { MyActuatorArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> where Self: Sized {
+        let arb_id = MyActuatorArbitrationId::from_can_message(&msg)?;
         let mut s = Self::new(arb_id.node_id, arb_id.cmd_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
     fn as_can_message(&self) -> RawCanMessage {
@@ -45,6 +54,7 @@ impl CanMessageTrait for MyActuatorCanMessage {
             arbitration_id: self.arbitration_id.value(),
             data: self.gen_can_msg_data(),
             is_extended_id: false,
+            ..Default::default()
         }
     }
 
@@ -56,15 +66,20 @@ impl CanMessageTrait for MyActuatorCanMessage {
         vec![0; 8]
     }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) -> Result<(), DecodeError> { Ok(()) }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x9A)]
 pub struct MyactuatorReadMotorStatus1Message {
     base: MyActuatorCanMessage,
+    #[can(offset = 1, unit = "degC")]
     pub temperature: i8,
+    #[can(offset = 3)]
     pub brake_released: bool,
+    #[can(offset = 4, raw = "u16", scale = 0.1, unit = "V")]
     pub voltage: f32,
+    #[can(offset = 6)]
     pub error_state: u16,
 }
 
@@ -80,47 +95,17 @@ impl MyactuatorReadMotorStatus1Message {
     }
 }
 
-impl CanMessageTrait for MyactuatorReadMotorStatus1Message {
-    fn cmd_id() -> u32 { 0x9A }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let mut s = Self::new(0); // temp node_id
-        s.parse_can_msg_data(&msg);
-        s.base = MyActuatorCanMessage::new(s.node_id(), Self::cmd_id());
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        self.temperature = msg.data[1] as i8;
-        self.brake_released = msg.data[3] != 0;
-        let voltage_raw = ((msg.data[5] as u16) << 8) | msg.data[4] as u16;
-        self.voltage = voltage_raw as f32 * 0.1;
-        self.error_state = ((msg.data[7] as u16) << 8) | msg.data[6] as u16;
-        // Set node_id from arb
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x9C)]
 pub struct ReadMotorStatus2Message {
     base: MyActuatorCanMessage,
+    #[can(offset = 1, unit = "degC")]
     pub temperature: i8,
+    #[can(offset = 2, raw = "i16", scale = 0.01, unit = "A")]
     pub torque_current: f32,
+    #[can(offset = 4, unit = "dps")]
     pub speed: i16,
+    #[can(offset = 6, unit = "deg")]
     pub angle: i16,
 }
 
@@ -136,47 +121,8 @@ impl ReadMotorStatus2Message {
     }
 }
 
-impl CanMessageTrait for ReadMotorStatus2Message {
-    fn cmd_id() -> u32 { 0x9C }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let mut s = Self::new(0);
-        s.parse_can_msg_data(&msg);
-        s.base = MyActuatorCanMessage::new(s.node_id(), Self::cmd_id());
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        self.temperature = msg.data[1] as i8;
-        let mut current_raw = ((msg.data[3] as i32) << 8) | msg.data[2] as i32;
-        if current_raw > 32767 { current_raw -= 65536; }
-        self.torque_current = current_raw as f32 * 0.01;
-        let mut speed_raw = ((msg.data[5] as i32) << 8) | msg.data[4] as i32;
-        if speed_raw > 32767 { speed_raw -= 65536; }
-        self.speed = speed_raw as i16;
-        let mut angle_raw = ((msg.data[7] as i32) << 8) | msg.data[6] as i32;
-        if angle_raw > 32767 { angle_raw -= 65536; }
-        self.angle = angle_raw as i16;
-        // Set node_id
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x64)]
 pub struct WriteMotorZeroPositionMessage {
     base: MyActuatorCanMessage,
 }
@@ -187,32 +133,11 @@ impl WriteMotorZeroPositionMessage {
     }
 }
 
-impl CanMessageTrait for WriteMotorZeroPositionMessage {
-    fn cmd_id() -> u32 { 0x64 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        Self { base: MyActuatorCanMessage::new(arb.node_id, Self::cmd_id()) }
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0xA1)]
 pub struct TorqueControlCommand {
     base: MyActuatorCanMessage,
+    #[can(offset = 4, raw = "i16", scale = 0.01, unit = "A")]
     pub torque_current: f32,
 }
 
@@ -222,40 +147,6 @@ impl TorqueControlCommand {
     }
 }
 
-impl CanMessageTrait for TorqueControlCommand {
-    fn cmd_id() -> u32 { 0xA1 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        let mut s = Self::new(arb.node_id, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let torque_raw = (self.torque_current * 100.0) as i16;
-        vec![Self::cmd_id() as u8, 0, 0, 0, (torque_raw & 0xFF) as u8, ((torque_raw >> 8) & 0xFF) as u8, 0, 0]
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 6 { return; }
-        let torque_raw = ((msg.data[5] as i16) << 8) | msg.data[4] as i16;
-        self.torque_current = torque_raw as f32 * 0.01;
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct FunctionControlCommand {
     base: MyActuatorCanMessage,
@@ -274,25 +165,29 @@ impl CanMessageTrait for FunctionControlCommand {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
         MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { MyActuatorArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        let arb = MyActuatorArbitrationId::for_cmd(&msg, Self::cmd_id())?;
         let mut s = Self::new(arb.node_id, MyActuatorFunctionControlIndex::ClearMultiTurnValue, 0);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
         vec![
             Self::cmd_id() as u8,
-            self.function.value() as u8,
+            self.function.value(),
             0,
             0,
             (self.function_value & 0xFF) as u8,
@@ -302,18 +197,37 @@ impl CanMessageTrait for FunctionControlCommand {
         ]
     }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        self.function = MyActuatorFunctionControlIndex::from_value(msg.data[1]).unwrap_or(MyActuatorFunctionControlIndex::ClearMultiTurnValue);
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(8)?;
+        self.function = MyActuatorFunctionControlIndex::from_value(msg.data[1])
+            .ok_or(DecodeError::UnknownEnumValue { name: "MyActuatorFunctionControlIndex", value: msg.data[1] as u32 })?;
         self.function_value = ((msg.data[7] as i32) << 24) | ((msg.data[6] as i32) << 16) | ((msg.data[5] as i32) << 8) | (msg.data[4] as i32);
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
+        self.base.node_id = MyActuatorArbitrationId::from_can_message(msg)?.node_id;
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
+impl DbcLayout for FunctionControlCommand {
+    const DBC_NAME: &'static str = "FunctionControlCommand";
+
+    const DBC_LEN: u8 = 8;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal {
+                values: value_names(MyActuatorFunctionControlIndex::from_value),
+                ..Signal::new("function", 8, 8, ByteOrder::LittleEndian, ValueType::Unsigned)
+            },
+            Signal::new("function_value", 32, 32, ByteOrder::LittleEndian, ValueType::Signed),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0xA2)]
 pub struct SpeedControlCommand {
     base: MyActuatorCanMessage,
+    #[can(offset = 4, raw = "i32", scale = 0.01, unit = "dps")]
     pub speed: f32,
 }
 
@@ -323,51 +237,13 @@ impl SpeedControlCommand {
     }
 }
 
-impl CanMessageTrait for SpeedControlCommand {
-    fn cmd_id() -> u32 { 0xA2 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        let mut s = Self::new(arb.node_id, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let speed_raw = (self.speed * 100.0) as i32;
-        vec![
-            Self::cmd_id() as u8,
-            0, 0, 0,
-            (speed_raw & 0xFF) as u8,
-            ((speed_raw >> 8) & 0xFF) as u8,
-            ((speed_raw >> 16) & 0xFF) as u8,
-            ((speed_raw >> 24) & 0xFF) as u8,
-        ]
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        let speed_raw = ((msg.data[7] as i32) << 24) | ((msg.data[6] as i32) << 16) | ((msg.data[5] as i32) << 8) | msg.data[4] as i32;
-        self.speed = speed_raw as f32 / 100.0;
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0xA4)]
 pub struct PositionControlCommand {
     base: MyActuatorCanMessage,
+    #[can(offset = 4, raw = "i32", scale = 0.01, unit = "deg")]
     pub position: f32,
+    #[can(offset = 2, unit = "dps")]
     pub max_speed: u16,
 }
 
@@ -377,54 +253,13 @@ impl PositionControlCommand {
     }
 }
 
-impl CanMessageTrait for PositionControlCommand {
-    fn cmd_id() -> u32 { 0xA4 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        let mut s = Self::new(arb.node_id, 0.0, 0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let position_raw = (self.position * 100.0) as i32;
-        vec![
-            Self::cmd_id() as u8,
-            0,
-            (self.max_speed & 0xFF) as u8,
-            ((self.max_speed >> 8) & 0xFF) as u8,
-            (position_raw & 0xFF) as u8,
-            ((position_raw >> 8) & 0xFF) as u8,
-            ((position_raw >> 16) & 0xFF) as u8,
-            ((position_raw >> 24) & 0xFF) as u8,
-        ]
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        self.max_speed = ((msg.data[3] as u16) << 8) | msg.data[2] as u16;
-        let position_raw = ((msg.data[7] as i32) << 24) | ((msg.data[6] as i32) << 16) | ((msg.data[5] as i32) << 8) | msg.data[4] as i32;
-        self.position = position_raw as f32 / 100.0;
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0xA8)]
 pub struct IncrementalPositionControlCommand {
     base: MyActuatorCanMessage,
+    #[can(offset = 2, unit = "dps")]
     pub max_speed: u16,
+    #[can(offset = 4, raw = "i32", scale = 0.01, unit = "deg")]
     pub position_increment: f32,
 }
 
@@ -434,51 +269,8 @@ impl IncrementalPositionControlCommand {
     }
 }
 
-impl CanMessageTrait for IncrementalPositionControlCommand {
-    fn cmd_id() -> u32 { 0xA8 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        let mut s = Self::new(arb.node_id, 0, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let position_raw = (self.position_increment * 100.0) as i32;
-        vec![
-            Self::cmd_id() as u8,
-            0,
-            (self.max_speed & 0xFF) as u8,
-            ((self.max_speed >> 8) & 0xFF) as u8,
-            (position_raw & 0xFF) as u8,
-            ((position_raw >> 8) & 0xFF) as u8,
-            ((position_raw >> 16) & 0xFF) as u8,
-            ((position_raw >> 24) & 0xFF) as u8,
-        ]
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        self.max_speed = ((msg.data[3] as u16) << 8) | msg.data[2] as u16;
-        let position_raw = ((msg.data[7] as i32) << 24) | ((msg.data[6] as i32) << 16) | ((msg.data[5] as i32) << 8) | msg.data[4] as i32;
-        self.position_increment = position_raw as f32 / 100.0;
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x80, priority = "Emergency")]
 pub struct MotorShutdownCommand {
     base: MyActuatorCanMessage,
 }
@@ -489,30 +281,8 @@ impl MotorShutdownCommand {
     }
 }
 
-impl CanMessageTrait for MotorShutdownCommand {
-    fn cmd_id() -> u32 { 0x80 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        Self { base: MyActuatorCanMessage::new(arb.node_id, Self::cmd_id()) }
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x81, priority = "Emergency")]
 pub struct MotorStopCommand {
     base: MyActuatorCanMessage,
 }
@@ -523,32 +293,11 @@ impl MotorStopCommand {
     }
 }
 
-impl CanMessageTrait for MotorStopCommand {
-    fn cmd_id() -> u32 { 0x81 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        Self { base: MyActuatorCanMessage::new(arb.node_id, Self::cmd_id()) }
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x92)]
 pub struct ReadMultiTurnAngleMessage {
     base: MyActuatorCanMessage,
+    #[can(offset = 4, raw = "i32", scale = 0.01, unit = "deg")]
     pub angle: f32,
 }
 
@@ -558,39 +307,8 @@ impl ReadMultiTurnAngleMessage {
     }
 }
 
-impl CanMessageTrait for ReadMultiTurnAngleMessage {
-    fn cmd_id() -> u32 { 0x92 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        let mut angle_raw = ((msg.data[7] as i64) << 24) | ((msg.data[6] as i64) << 16) | ((msg.data[5] as i64) << 8) | msg.data[4] as i64;
-        if angle_raw > 0x7FFFFFFF { angle_raw -= 0x100000000i64; }
-        self.angle = angle_raw as f32 * 0.01;
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x77)]
 pub struct SystemBrakeReleaseCommand {
     base: MyActuatorCanMessage,
 }
@@ -601,30 +319,8 @@ impl SystemBrakeReleaseCommand {
     }
 }
 
-impl CanMessageTrait for SystemBrakeReleaseCommand {
-    fn cmd_id() -> u32 { 0x77 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        Self { base: MyActuatorCanMessage::new(arb.node_id, Self::cmd_id()) }
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x78, priority = "Emergency")]
 pub struct SystemBrakeLockCommand {
     base: MyActuatorCanMessage,
 }
@@ -635,29 +331,6 @@ impl SystemBrakeLockCommand {
     }
 }
 
-impl CanMessageTrait for SystemBrakeLockCommand {
-    fn cmd_id() -> u32 { 0x78 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        Self { base: MyActuatorCanMessage::new(arb.node_id, Self::cmd_id()) }
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
-}
-
 #[derive(Debug, Clone)]
 pub struct SystemOperatingModeAcquisitionCommand {
     base: MyActuatorCanMessage,
@@ -675,32 +348,51 @@ impl CanMessageTrait for SystemOperatingModeAcquisitionCommand {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
         MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { MyActuatorArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        let arb = MyActuatorArbitrationId::for_cmd(&msg, Self::cmd_id())?;
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        self.operating_mode = MyActuatorV3OperatingMode::from_value(msg.data[7]).unwrap_or(MyActuatorV3OperatingMode::PositionLoopControl);
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(8)?;
+        self.operating_mode = MyActuatorV3OperatingMode::from_value(msg.data[7])
+            .ok_or(DecodeError::UnknownEnumValue { name: "MyActuatorV3OperatingMode", value: msg.data[7] as u32 })?;
+        self.base.node_id = MyActuatorArbitrationId::from_can_message(msg)?.node_id;
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
+impl DbcLayout for SystemOperatingModeAcquisitionCommand {
+    const DBC_NAME: &'static str = "SystemOperatingModeAcquisitionCommand";
+
+    const DBC_LEN: u8 = 8;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![Signal {
+            values: value_names(MyActuatorV3OperatingMode::from_value),
+            ..Signal::new("operating_mode", 56, 8, ByteOrder::LittleEndian, ValueType::Unsigned)
+        }]
+    }
+}
+
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0x76)]
 pub struct SystemResetCommand {
     base: MyActuatorCanMessage,
 }
@@ -711,32 +403,12 @@ impl SystemResetCommand {
     }
 }
 
-impl CanMessageTrait for SystemResetCommand {
-    fn cmd_id() -> u32 { 0x76 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        Self { base: MyActuatorCanMessage::new(arb.node_id, Self::cmd_id()) }
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "myactuator", cmd_id = 0xB2)]
 pub struct VersionAcquisitionCommand {
     base: MyActuatorCanMessage,
+    /// The firmware date as decimal digits, e.g. 20220206.
+    #[can(offset = 4)]
     pub version_date: u32,
 }
 
@@ -745,38 +417,10 @@ impl VersionAcquisitionCommand {
         Self { base: MyActuatorCanMessage::new(node_id, Self::cmd_id()), version_date: 0 }
     }
 
-    pub fn version_datetime(&self) -> NaiveDate {
-        NaiveDate::parse_from_str(&self.version_date.to_string(), "%Y%m%d").unwrap()
-    }
-}
-
-impl CanMessageTrait for VersionAcquisitionCommand {
-    fn cmd_id() -> u32 { 0xB2 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![Self::cmd_id() as u8, 0, 0, 0, 0, 0, 0, 0] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
-        self.version_date = ((msg.data[7] as u32) << 24) | ((msg.data[6] as u32) << 16) | ((msg.data[5] as u32) << 8) | msg.data[4] as u32;
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
+    /// The firmware date, or None if `version_date` is not a valid date.
+    pub fn version_datetime(&self) -> Option<NaiveDate> {
+        let date = self.version_date;
+        NaiveDate::from_ymd_opt((date / 10_000) as i32, date / 100 % 100, date % 100)
     }
 }
 
@@ -804,19 +448,23 @@ impl CanMessageTrait for CANIDCommand {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
         MyActuatorCanMessage::matches(msg) && !msg.data.is_empty() && msg.data[0] == Self::cmd_id() as u8
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = MyActuatorArbitrationId::from_can_message(&msg).unwrap();
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { MyActuatorArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        let arb = MyActuatorArbitrationId::for_cmd(&msg, Self::cmd_id())?;
         let mut s = Self::new(arb.node_id, ReadWriteFlag::Write, 0);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId {
         let mut arb = self.base.arbitration_id.clone();
         arb.custom_value = Some(0x300);
@@ -829,11 +477,43 @@ impl CanMessageTrait for CANIDCommand {
         vec![Self::cmd_id() as u8, 0, flag_byte, 0, 0, 0, 0, clipped_id]
     }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() < 8 { return; }
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(8)?;
         self.read_write_flag = if msg.data[2] != 0 { ReadWriteFlag::Read } else { ReadWriteFlag::Write };
         self.can_id = msg.data[7] as u32;
-        let arb = MyActuatorArbitrationId::from_can_message(msg).unwrap();
-        self.base.node_id = arb.node_id;
+        self.base.node_id = MyActuatorArbitrationId::from_can_message(msg)?.node_id;
+        Ok(())
+    }
+}
+
+impl DbcLayout for CANIDCommand {
+    const DBC_NAME: &'static str = "CANIDCommand";
+
+    const DBC_LEN: u8 = 8;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal {
+                values: vec![(0, "Write".to_string()), (1, "Read".to_string())],
+                ..Signal::new("read_write_flag", 16, 8, ByteOrder::LittleEndian, ValueType::Unsigned)
+            },
+            Signal::new("can_id", 56, 8, ByteOrder::LittleEndian, ValueType::Unsigned),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_dates() {
+        let mut version = VersionAcquisitionCommand::new(1);
+        version.version_date = 20220206;
+        assert_eq!(version.version_datetime(), NaiveDate::from_ymd_opt(2022, 2, 6));
+        for malformed in [0, 20221306, 20220230, 2022026] {
+            version.version_date = malformed;
+            assert_eq!(version.version_datetime(), None, "{}", malformed);
+        }
     }
 }
@@ -1,20 +1,44 @@
-use byteorder::{BigEndian, WriteBytesExt};
-use std::io::Cursor;
-use std::convert::TryInto;
-
-use crate::drivers::can::messages::{ArbitrationId, CanMessageTrait, RawCanMessage, X424ArbitrationId};
+use crate::drivers::can::messages::{ArbitrationId, CanIdFilter, CanMessage, CanMessageTrait, DecodeError, RawCanMessage, RxTimestamp, X424ArbitrationId};
+use crate::drivers::can::dbc::{ByteOrder, Signal, ValueType};
+use crate::drivers::can::dbc_export::{value_names, DbcLayout};
 use crate::drivers::can::enums::X424MotorError;
 
+/// Set and query replies come back on the broadcast id, like the commands.
+fn check_broadcast_id(msg: &RawCanMessage) -> Result<(), DecodeError> {
+    if msg.arbitration_id != 0x7FF {
+        return Err(DecodeError::WrongId { arbitration_id: msg.arbitration_id });
+    }
+    Ok(())
+}
+
+/// Servo commands and QA replies carry their type in the top three bits of the first byte.
+fn check_qa_type(msg: &RawCanMessage, cmd_id: u32) -> Result<(), DecodeError> {
+    msg.require_len(1)?;
+    DecodeError::check_command(cmd_id as u8, msg.data[0] >> 5)
+}
+
+/// Set and query commands carry their code in the fourth byte, after the target id and a zero.
+fn matches_set_and_query(msg: &RawCanMessage, code: u8) -> bool {
+    msg.arbitration_id == 0x7FF && msg.data.get(2) == Some(&0x00) && msg.data.get(3) == Some(&code)
+}
+
+/// Replies to both queries have 0x01 in the third byte; the CAN id reply starts with 0xFF,
+/// which no motor id does.
+fn matches_query_reply(msg: &RawCanMessage, can_id_reply: bool) -> bool {
+    msg.arbitration_id == 0x7FF && msg.data.get(2) == Some(&0x01) && (msg.data[0] == 0xFF) == can_id_reply
+}
+
 #[derive(Debug, Clone)]
 pub struct X424CanMessage {
     pub node_id: u32,
     pub arbitration_id: X424ArbitrationId,
+    pub timestamp: Option<RxTimestamp>,
 }
 
 impl X424CanMessage {
     pub fn new(node_id: u32, cmd_id: u32) -> Self {
         let arbitration_id = X424ArbitrationId { node_id, cmd_id };
-        Self { node_id, arbitration_id }
+        Self { node_id, arbitration_id, timestamp: None }
     }
 }
 
@@ -23,15 +47,21 @@ impl CanMessageTrait for X424CanMessage {
 
     fn node_id(&self) -> u32 { self.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
-        msg.data.get(0).map_or(false, |&d| d == Self::cmd_id() as u8)
+        msg.data.first().is_some_and(|&d| d == Self::cmd_id() as u8)
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id, arb.cmd_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
     fn as_can_message(&self) -> RawCanMessage {
@@ -39,6 +69,7 @@ impl CanMessageTrait for X424CanMessage {
             arbitration_id: self.arbitration_id.value(),
             data: self.gen_can_msg_data(),
             is_extended_id: false,
+            ..Default::default()
         }
     }
 
@@ -48,7 +79,7 @@ impl CanMessageTrait for X424CanMessage {
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) -> Result<(), DecodeError> { Ok(()) }
 }
 
 #[derive(Debug, Clone)]
@@ -67,15 +98,22 @@ impl CanMessageTrait for X424CanMessageSetAndQuery {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
-        msg.arbitration_id == 0x7FF && msg.data.get(3).map_or(false, |&d| d == Self::cmd_id() as u8)
+        msg.arbitration_id == 0x7FF && msg.data.get(3).is_some_and(|&d| d == Self::cmd_id() as u8)
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(_node_id: Option<u32>) -> Vec<CanIdFilter> { vec![CanIdFilter::exact(0x7FF)] }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_broadcast_id(&msg)?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id, arb.cmd_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
     fn as_can_message(&self) -> RawCanMessage {
@@ -83,14 +121,48 @@ impl CanMessageTrait for X424CanMessageSetAndQuery {
             arbitration_id: 0x7FF,
             data: self.gen_can_msg_data(),
             is_extended_id: false,
+            ..Default::default()
         }
     }
 
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
+    fn gen_arbitration_id(&self) -> ArbitrationId {
+        // Set and query commands are always broadcast on 0x7FF; the target id is in the payload.
+        ArbitrationId::X424(X424ArbitrationId { node_id: 0x7FF, cmd_id: self.base.arbitration_id.cmd_id })
+    }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) -> Result<(), DecodeError> { Ok(()) }
+}
+
+impl DbcLayout for X424CanMessageSetAndQuery {
+    const DBC_NAME: &'static str = "X424SetAndQuery";
+
+    const DBC_LEN: u8 = 8;
+
+    /// What the set and query messages have in common.
+    fn dbc_signals() -> Vec<Signal> {
+        let codes = vec![
+            (SetZeroPositionMessage::cmd_id() as i64, "SetZeroPosition".to_string()),
+            (SetMotorIDMessage::cmd_id() as i64, "SetMotorID".to_string()),
+            (ResetMotorIDMessage::cmd_id() as i64, "ResetMotorID".to_string()),
+            (QueryCommunicationModeMessage::cmd_id() as i64, "QueryCommunicationMode".to_string()),
+            (QueryCANCommunicationIDMessage::cmd_id() as i64, "QueryCANCommunicationID".to_string()),
+        ];
+        vec![
+            Signal::new("motor_id", 7, 16, ByteOrder::BigEndian, ValueType::Unsigned),
+            Signal {
+                values: vec![(0, "Request".to_string()), (1, "Reply".to_string())],
+                ..Signal::new("reply", 16, 8, ByteOrder::LittleEndian, ValueType::Unsigned)
+            },
+            Signal {
+                values: codes,
+                comment: Some("In SetCommunicationMode the mode instead: 1 auto, 2 QA.".to_string()),
+                ..Signal::new("code", 24, 8, ByteOrder::LittleEndian, ValueType::Unsigned)
+            },
+            Signal::new("new_motor_id", 39, 16, ByteOrder::BigEndian, ValueType::Unsigned),
+        ]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -110,19 +182,22 @@ impl CanMessageTrait for QueryCommunicationModeMessage {
 
     fn node_id(&self) -> u32 { self.base.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool {
-        msg.arbitration_id == 0x7FF && msg.data.get(2).map_or(false, |&d| d == 0x01)
-    }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool { matches_query_reply(msg, false) }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(_node_id: Option<u32>) -> Vec<CanIdFilter> { vec![CanIdFilter::exact(0x7FF)] }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_broadcast_id(&msg)?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
@@ -131,10 +206,10 @@ impl CanMessageTrait for QueryCommunicationModeMessage {
         vec![high_id, low_id, 0x00, Self::cmd_id() as u8]
     }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 {
-            self.mode = if msg.data[3] == 0x01 { "auto".to_string() } else { "qa".to_string() };
-        }
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(4)?;
+        self.mode = if msg.data[3] == 0x01 { "auto".to_string() } else { "qa".to_string() };
+        Ok(())
     }
 }
 
@@ -154,30 +229,35 @@ impl CanMessageTrait for QueryCANCommunicationIDMessage {
 
     fn node_id(&self) -> u32 { self.base.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool {
-        msg.arbitration_id == 0x7FF && msg.data.get(2).map_or(false, |&d| d == 0x01)
-    }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.base.timestamp }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool { matches_query_reply(msg, true) }
+
+    fn can_filters(_node_id: Option<u32>) -> Vec<CanIdFilter> { vec![CanIdFilter::exact(0x7FF)] }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_broadcast_id(&msg)?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
         vec![0xFF, 0xFF, 0x00, Self::cmd_id() as u8]
     }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 5 && msg.data[0] == 0xFF && msg.data[2] == 0x01 {
-            let data = &msg.data[3..5];
-            self.base.base.node_id = u16::from_be_bytes(data.try_into().unwrap()) as u32;
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(5)?;
+        DecodeError::check_command(0x01, msg.data[2])?;
+        if msg.data[0] == 0xFF {
+            self.base.base.node_id = u16::from_be_bytes(msg.bytes(3)?) as u32;
         }
+        Ok(())
     }
 }
 
@@ -198,17 +278,24 @@ impl CanMessageTrait for SetCommunicationModeMessage {
 
     fn node_id(&self) -> u32 { self.base.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { X424CanMessageSetAndQuery::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.base.timestamp = timestamp; }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn matches(msg: &RawCanMessage) -> bool {
+        matches_set_and_query(msg, 0x01) || matches_set_and_query(msg, 0x02)
+    }
+
+    fn can_filters(_node_id: Option<u32>) -> Vec<CanIdFilter> { vec![CanIdFilter::exact(0x7FF)] }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_broadcast_id(&msg)?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id, String::new());
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
@@ -218,11 +305,11 @@ impl CanMessageTrait for SetCommunicationModeMessage {
         vec![high_id, low_id, Self::cmd_id() as u8, mode_val]
     }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
         self.base.base.node_id = msg.arbitration_id;
-        if msg.data.len() >= 4 {
-            self.mode = if msg.data[3] == 0x01 { "auto".to_string() } else { "qa".to_string() };
-        }
+        msg.require_len(4)?;
+        self.mode = if msg.data[3] == 0x01 { "auto".to_string() } else { "qa".to_string() };
+        Ok(())
     }
 }
 
@@ -242,15 +329,20 @@ impl CanMessageTrait for SetZeroPositionMessage {
 
     fn node_id(&self) -> u32 { self.base.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { X424CanMessageSetAndQuery::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool { matches_set_and_query(msg, Self::cmd_id() as u8) }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(_node_id: Option<u32>) -> Vec<CanIdFilter> { vec![CanIdFilter::exact(0x7FF)] }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_broadcast_id(&msg)?;
         let arb = X424ArbitrationId::from_can_message(&msg);
-        Self::new(arb.node_id)
+        Ok(Self::new(arb.node_id))
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
@@ -259,7 +351,7 @@ impl CanMessageTrait for SetZeroPositionMessage {
         vec![high_id, low_id, 0x00, Self::cmd_id() as u8]
     }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) -> Result<(), DecodeError> { Ok(()) }
 }
 
 #[derive(Debug, Clone)]
@@ -280,15 +372,20 @@ impl CanMessageTrait for SetMotorIDMessage {
 
     fn node_id(&self) -> u32 { self.base.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { X424CanMessageSetAndQuery::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.base.timestamp }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool { matches_set_and_query(msg, Self::cmd_id() as u8) }
+
+    fn can_filters(_node_id: Option<u32>) -> Vec<CanIdFilter> { vec![CanIdFilter::exact(0x7FF)] }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_broadcast_id(&msg)?;
         let arb = X424ArbitrationId::from_can_message(&msg);
-        Self::new(arb.node_id, 0, 0)
+        Ok(Self::new(arb.node_id, 0, 0))
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
@@ -299,7 +396,7 @@ impl CanMessageTrait for SetMotorIDMessage {
         vec![cur_high, cur_low, 0x00, Self::cmd_id() as u8, new_high, new_low]
     }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) -> Result<(), DecodeError> { Ok(()) }
 }
 
 #[derive(Debug, Clone)]
@@ -318,22 +415,27 @@ impl CanMessageTrait for ResetMotorIDMessage {
 
     fn node_id(&self) -> u32 { self.base.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { X424CanMessageSetAndQuery::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.base.timestamp = timestamp; }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn matches(msg: &RawCanMessage) -> bool { matches_set_and_query(msg, Self::cmd_id() as u8) }
+
+    fn can_filters(_node_id: Option<u32>) -> Vec<CanIdFilter> { vec![CanIdFilter::exact(0x7FF)] }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_broadcast_id(&msg)?;
         let arb = X424ArbitrationId::from_can_message(&msg);
-        Self::new(arb.node_id)
+        Ok(Self::new(arb.node_id))
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
         vec![0x7F, 0x7F, 0x00, Self::cmd_id() as u8, 0x7F, 0x7F]
     }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) -> Result<(), DecodeError> { Ok(()) }
 }
 
 #[derive(Debug, Clone)]
@@ -356,17 +458,24 @@ impl CanMessageTrait for X424ServoPositionControlMessage {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { X424CanMessage::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool {
+        msg.data.first().is_some_and(|&d| (d >> 5) as u32 == Self::cmd_id())
+    }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_qa_type(&msg, Self::cmd_id())?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id, 0.0, 0.0, 0.0, 0);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
@@ -376,14 +485,36 @@ impl CanMessageTrait for X424ServoPositionControlMessage {
         let position_bytes = self.position.to_le_bytes();
         let position_int = u32::from_le_bytes(position_bytes);
         result |= ((Self::cmd_id() & 0x07) as u64) << 61;
-        result |= ((position_int & 0xFFFFFFFF) as u64) << 29;
+        result |= (position_int as u64) << 29;
         result |= ((speed_value) as u64) << 14;
         result |= ((current_value) as u64) << 2;
         result |= (self.message_type & 0x03) as u64;
         result.to_be_bytes().to_vec()
     }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        let word = u64::from_be_bytes(msg.bytes(0)?);
+        self.position = f32::from_bits((word >> 29) as u32);
+        self.speed = ((word >> 14) & 0x7FFF) as f32;
+        self.current_limit = ((word >> 2) & 0xFFF) as f32 / 10.0;
+        self.message_type = (word & 0x03) as u32;
+        Ok(())
+    }
+}
+
+impl DbcLayout for X424ServoPositionControlMessage {
+    const DBC_NAME: &'static str = "X424ServoPositionControlMessage";
+
+    const DBC_LEN: u8 = 8;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal::new("position", 4, 32, ByteOrder::BigEndian, ValueType::Float32),
+            Signal::new("speed", 36, 15, ByteOrder::BigEndian, ValueType::Unsigned),
+            Signal { factor: 0.1, unit: "A".to_string(), ..Signal::new("current_limit", 53, 12, ByteOrder::BigEndian, ValueType::Unsigned) },
+            Signal::new("message_type", 57, 2, ByteOrder::BigEndian, ValueType::Unsigned),
+        ]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -405,17 +536,24 @@ impl CanMessageTrait for X424ServoSpeedControlMessage {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { X424CanMessage::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool {
+        msg.data.first().is_some_and(|&d| (d >> 5) as u32 == Self::cmd_id())
+    }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_qa_type(&msg, Self::cmd_id())?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id, 0.0, 0.0, 0);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
@@ -424,17 +562,36 @@ impl CanMessageTrait for X424ServoSpeedControlMessage {
         let speed_bytes = self.speed.to_le_bytes();
         let speed_int = u32::from_le_bytes(speed_bytes);
         result |= ((Self::cmd_id() & 0x07) as u64) << 53;
-        result |= (0u64 & 0x07) << 50;
+        // Bits 50..=52 are reserved and stay zero.
         result |= ((self.message_type & 0x03) as u64) << 48;
-        result |= ((speed_int & 0xFFFFFFFF) as u64) << 16;
+        result |= (speed_int as u64) << 16;
         result |= current_value as u64;
-        let mut bytes = vec![0u8; 7];
-        let mut cursor = Cursor::new(&mut bytes);
-        cursor.write_u64::<BigEndian>(result).unwrap();
-        bytes
+        result.to_be_bytes()[1..].to_vec()
+    }
+
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        let mut bytes = [0u8; 8];
+        bytes[1..].copy_from_slice(&msg.bytes::<7>(0)?);
+        let word = u64::from_be_bytes(bytes);
+        self.message_type = ((word >> 48) & 0x03) as u32;
+        self.speed = f32::from_bits((word >> 16) as u32);
+        self.current_limit = (word & 0xFFFF) as f32 / 10.0;
+        Ok(())
     }
+}
+
+impl DbcLayout for X424ServoSpeedControlMessage {
+    const DBC_NAME: &'static str = "X424ServoSpeedControlMessage";
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    const DBC_LEN: u8 = 7;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal::new("message_type", 1, 2, ByteOrder::BigEndian, ValueType::Unsigned),
+            Signal::new("speed", 15, 32, ByteOrder::BigEndian, ValueType::Float32),
+            Signal { factor: 0.1, unit: "A".to_string(), ..Signal::new("current_limit", 47, 16, ByteOrder::BigEndian, ValueType::Unsigned) },
+        ]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -456,17 +613,24 @@ impl CanMessageTrait for X424CurrentControlMessage {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { X424CanMessage::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool {
+        msg.data.first().is_some_and(|&d| (d >> 5) as u32 == Self::cmd_id())
+    }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_qa_type(&msg, Self::cmd_id())?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id, 0.0, 0, 0);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
@@ -476,14 +640,35 @@ impl CanMessageTrait for X424CurrentControlMessage {
             current_int = (current_int.abs() ^ 0xFFFF) + 1;
         }
         current_int &= 0xFFFF;
-        result |= ((Self::cmd_id() & 0x07) as u32) << 21;
-        result |= ((self.control_type & 0x07) as u32) << 18;
-        result |= ((self.message_type & 0x03) as u32) << 16;
+        result |= (Self::cmd_id() & 0x07) << 21;
+        result |= (self.control_type & 0x07) << 18;
+        result |= (self.message_type & 0x03) << 16;
         result |= (current_int as u32) & 0xFFFF;
         result.to_be_bytes()[1..4].to_vec()
     }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        let [b0, b1, b2] = msg.bytes(0)?;
+        let word = u32::from_be_bytes([0, b0, b1, b2]);
+        self.control_type = (word >> 18) & 0x07;
+        self.message_type = (word >> 16) & 0x03;
+        self.current = (word as u16 as i16) as f32 / 100.0;
+        Ok(())
+    }
+}
+
+impl DbcLayout for X424CurrentControlMessage {
+    const DBC_NAME: &'static str = "X424CurrentControlMessage";
+
+    const DBC_LEN: u8 = 3;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal::new("control_type", 4, 3, ByteOrder::BigEndian, ValueType::Unsigned),
+            Signal::new("message_type", 1, 2, ByteOrder::BigEndian, ValueType::Unsigned),
+            Signal { factor: 0.01, unit: "A".to_string(), ..Signal::new("current", 15, 16, ByteOrder::BigEndian, ValueType::Signed) },
+        ]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -503,85 +688,81 @@ impl CanMessageTrait for QAReturnMessage {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
         if msg.data.is_empty() { return false; }
         ((msg.data[0] >> 5) & 0x07) as u32 == Self::cmd_id()
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_qa_type(&msg, Self::cmd_id())?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if !msg.data.is_empty() {
-            self.motor_error = X424MotorError::from_value(msg.data[0] & 0x1F);
-        }
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(1)?;
+        let code = msg.data[0] & 0x1F;
+        self.motor_error = X424MotorError::from_value(code).ok_or(DecodeError::UnknownEnumValue { name: "X424MotorError", value: code as u32 })?;
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
+impl DbcLayout for QAReturnMessage {
+    const DBC_NAME: &'static str = "QAReturnMessage";
+
+    const DBC_LEN: u8 = 1;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![Signal { values: value_names(X424MotorError::from_value), ..Signal::new("motor_error", 0, 5, ByteOrder::LittleEndian, ValueType::Unsigned) }]
+    }
+}
+
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "x424", cmd_id = 0x01, endian = "big")]
 pub struct QAReturnMessageType1 {
-    base: QAReturnMessage,
+    base: X424CanMessage,
+    #[can(offset = 0, raw = "u64", bits = "40..56", scale = 25.0 / 65536.0, bias = -12.5, unit = "rad")]
     pub position: f32,
+    #[can(offset = 0, raw = "u64", bits = "28..40", scale = 36.0 / 4095.0, bias = -18.0, unit = "rad/s")]
     pub speed: f32,
+    #[can(offset = 0, raw = "u64", bits = "16..28", scale = 60.0 / 4095.0, bias = -30.0, unit = "A")]
     pub current: f32,
+    #[can(offset = 0, raw = "u64", bits = "8..16", scale = 0.5, bias = -25.0, unit = "degC")]
     pub motor_temp: f32,
+    #[can(offset = 0, raw = "u64", bits = "0..8", scale = 0.5, bias = -25.0, unit = "degC")]
     pub mos_temp: f32,
 }
 
 impl QAReturnMessageType1 {
     pub fn new(node_id: u32) -> Self {
-        Self { base: QAReturnMessage::new(node_id), position: 0.0, speed: 0.0, current: 0.0, motor_temp: 0.0, mos_temp: 0.0 }
+        Self { base: X424CanMessage::new(node_id, Self::cmd_id()), position: 0.0, speed: 0.0, current: 0.0, motor_temp: 0.0, mos_temp: 0.0 }
     }
 }
 
-impl CanMessageTrait for QAReturnMessageType1 {
-    fn cmd_id() -> u32 { 0x01 }
-
-    fn node_id(&self) -> u32 { self.base.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool {
-        if msg.data.is_empty() { return false; }
-        ((msg.data[0] >> 5) & 0x07) as u32 == Self::cmd_id()
-    }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = X424ArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() == 8 {
-            let data_int = u64::from_be_bytes((&msg.data[..]).try_into().unwrap());
-            let position_raw = ((data_int >> 40) & 0xFFFF) as u32;
-            self.position = (position_raw as f32 / 65536.0 * 25.0) - 12.5;
-            let speed_raw = ((data_int >> 28) & 0xFFF) as u32;
-            self.speed = (speed_raw as f32 / 4095.0 * 36.0) - 18.0;
-            let current_raw = ((data_int >> 16) & 0xFFF) as u32;
-            self.current = (current_raw as f32 / 4095.0 * 60.0) - 30.0;
-            let temp_raw = ((data_int >> 8) & 0xFF) as u32;
-            self.motor_temp = (temp_raw as f32 - 50.0) / 2.0;
-            let mos_temp_raw = (data_int & 0xFF) as u32;
-            self.mos_temp = (mos_temp_raw as f32 - 50.0) / 2.0;
-        }
-    }
+/// Types 2 and 3 differ only in whether the float is the position or the speed.
+fn qa_status_signals(first: &str) -> Vec<Signal> {
+    vec![
+        Signal::new(first, 8, 32, ByteOrder::LittleEndian, ValueType::Float32),
+        Signal {
+            factor: 0.01,
+            unit: "A".to_string(),
+            comment: Some("Decoded as its magnitude.".to_string()),
+            ..Signal::new("current", 47, 16, ByteOrder::BigEndian, ValueType::Signed)
+        },
+        Signal { factor: 0.5, offset: -25.0, unit: "degC".to_string(), ..Signal::new("motor_temp", 56, 8, ByteOrder::LittleEndian, ValueType::Unsigned) },
+    ]
 }
 
 #[derive(Debug, Clone)]
@@ -603,35 +784,50 @@ impl CanMessageTrait for QAReturnMessageType2 {
 
     fn node_id(&self) -> u32 { self.base.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
         if msg.data.is_empty() { return false; }
         ((msg.data[0] >> 5) & 0x07) as u32 == Self::cmd_id()
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_qa_type(&msg, Self::cmd_id())?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.position = f32::from_le_bytes([msg.data[1], msg.data[2], msg.data[3], msg.data[4]]);
-            let mut current_raw = i16::from_be_bytes([msg.data[5], msg.data[6]]);
-            if current_raw < 0 {
-                current_raw = -current_raw;
-            }
-            self.current = current_raw as f32 / 100.0;
-            let temp_raw = msg.data[7];
-            self.motor_temp = (temp_raw as f32 - 50.0) / 2.0;
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(8)?;
+        self.position = f32::from_le_bytes([msg.data[1], msg.data[2], msg.data[3], msg.data[4]]);
+        let mut current_raw = i16::from_be_bytes([msg.data[5], msg.data[6]]);
+        if current_raw < 0 {
+            current_raw = -current_raw;
         }
+        self.current = current_raw as f32 / 100.0;
+        let temp_raw = msg.data[7];
+        self.motor_temp = (temp_raw as f32 - 50.0) / 2.0;
+        Ok(())
+    }
+}
+
+impl DbcLayout for QAReturnMessageType2 {
+    const DBC_NAME: &'static str = "QAReturnMessageType2";
+
+    const DBC_LEN: u8 = 8;
+
+    fn dbc_signals() -> Vec<Signal> {
+        qa_status_signals("position")
     }
 }
 
@@ -654,35 +850,50 @@ impl CanMessageTrait for QAReturnMessageType3 {
 
     fn node_id(&self) -> u32 { self.base.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
         if msg.data.is_empty() { return false; }
         ((msg.data[0] >> 5) & 0x07) as u32 == Self::cmd_id()
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_qa_type(&msg, Self::cmd_id())?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.speed = f32::from_le_bytes([msg.data[1], msg.data[2], msg.data[3], msg.data[4]]);
-            let mut current_raw = i16::from_be_bytes([msg.data[5], msg.data[6]]);
-            if current_raw < 0 {
-                current_raw = -current_raw;
-            }
-            self.current = current_raw as f32 / 100.0;
-            let temp_raw = msg.data[7];
-            self.motor_temp = (temp_raw as f32 - 50.0) / 2.0;
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(8)?;
+        self.speed = f32::from_le_bytes([msg.data[1], msg.data[2], msg.data[3], msg.data[4]]);
+        let mut current_raw = i16::from_be_bytes([msg.data[5], msg.data[6]]);
+        if current_raw < 0 {
+            current_raw = -current_raw;
         }
+        self.current = current_raw as f32 / 100.0;
+        let temp_raw = msg.data[7];
+        self.motor_temp = (temp_raw as f32 - 50.0) / 2.0;
+        Ok(())
+    }
+}
+
+impl DbcLayout for QAReturnMessageType3 {
+    const DBC_NAME: &'static str = "QAReturnMessageType3";
+
+    const DBC_LEN: u8 = 8;
+
+    fn dbc_signals() -> Vec<Signal> {
+        qa_status_signals("speed")
     }
 }
 
@@ -704,29 +915,47 @@ impl CanMessageTrait for QAReturnMessageType4 {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
         if msg.data.is_empty() { return false; }
         ((msg.data[0] >> 5) & 0x07) as u32 == Self::cmd_id()
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_qa_type(&msg, Self::cmd_id())?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 3 {
-            self.config_code = msg.data[1];
-            self.config_status = msg.data[2] == 1;
-        }
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(3)?;
+        self.config_code = msg.data[1];
+        self.config_status = msg.data[2] == 1;
+        Ok(())
+    }
+}
+
+impl DbcLayout for QAReturnMessageType4 {
+    const DBC_NAME: &'static str = "QAReturnMessageType4";
+
+    const DBC_LEN: u8 = 3;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal::new("config_code", 8, 8, ByteOrder::LittleEndian, ValueType::Unsigned),
+            Signal::new("config_status", 16, 8, ByteOrder::LittleEndian, ValueType::Unsigned),
+        ]
     }
 }
 
@@ -752,39 +981,60 @@ impl CanMessageTrait for QAReturnMessageType5 {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
         if msg.data.is_empty() { return false; }
         ((msg.data[0] >> 5) & 0x07) as u32 == Self::cmd_id()
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { X424ArbitrationId::can_filters(node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        check_qa_type(&msg, Self::cmd_id())?;
         let arb = X424ArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 3 {
-            self.query_code = msg.data[1];
-            if self.query_code >= 1 && self.query_code <= 4 && msg.data.len() >= 6 {
-                let value = f32::from_le_bytes([msg.data[2], msg.data[3], msg.data[4], msg.data[5]]);
-                match self.query_code {
-                    1 => self.position = value,
-                    2 => self.speed = value,
-                    3 => self.current = value,
-                    4 => self.power = value,
-                    _ => {},
-                }
-            } else if self.query_code >= 5 && self.query_code <= 9 && msg.data.len() >= 4 {
-                self.uint16_value = u16::from_be_bytes([msg.data[2], msg.data[3]]);
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(3)?;
+        self.query_code = msg.data[1];
+        if self.query_code >= 1 && self.query_code <= 4 {
+            let value = f32::from_le_bytes(msg.bytes(2)?);
+            match self.query_code {
+                1 => self.position = value,
+                2 => self.speed = value,
+                3 => self.current = value,
+                4 => self.power = value,
+                _ => {},
             }
+        } else if self.query_code >= 5 && self.query_code <= 9 {
+            self.uint16_value = u16::from_be_bytes(msg.bytes(2)?);
         }
+        Ok(())
+    }
+}
+
+impl DbcLayout for QAReturnMessageType5 {
+    const DBC_NAME: &'static str = "QAReturnMessageType5";
+
+    const DBC_LEN: u8 = 6;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal::new("query_code", 8, 8, ByteOrder::LittleEndian, ValueType::Unsigned),
+            Signal {
+                comment: Some("Position, speed, current or power for query codes 1 to 4. For codes 5 to 9, a big-endian u16 in the first two bytes.".to_string()),
+                ..Signal::new("value", 16, 32, ByteOrder::LittleEndian, ValueType::Float32)
+            },
+        ]
     }
 }
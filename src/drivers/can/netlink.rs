@@ -0,0 +1,197 @@
+//! Query and configure SocketCAN interfaces over rtnetlink, as
+//! `ip link set can0 type can bitrate 1000000` and `ip link set can0 up` do.
+//!
+//! Reading an interface is unprivileged. Changing it needs root or
+//! `CAP_NET_ADMIN`, and the bit timing, control modes and restart delay can only
+//! be changed while the interface is down.
+use std::fmt::Debug;
+
+use anyhow::{anyhow, Result};
+use neli::err::{NlError, WrappedError};
+use socketcan::nl::{CanCtrlModes, CanState};
+use socketcan::{CanCtrlMode, CanInterface};
+
+use super::bus_events::BusState;
+use super::connection::BAUDRATE;
+
+/// Desired settings of a CAN interface. `None` leaves a setting as it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterfaceConfig {
+    /// Nominal bitrate in bit/s.
+    pub bitrate: Option<u32>,
+    /// Sample point as a fraction of the bit, e.g. `0.875`. Left to the driver if `None`.
+    pub sample_point: Option<f32>,
+    /// Delay before the controller restarts itself after bus-off, 0 to stay off the bus.
+    pub restart_ms: Option<u32>,
+    pub listen_only: Option<bool>,
+    pub loopback: Option<bool>,
+}
+
+impl Default for InterfaceConfig {
+    fn default() -> Self {
+        Self { bitrate: Some(BAUDRATE), sample_point: None, restart_ms: None, listen_only: None, loopback: None }
+    }
+}
+
+/// The current settings and state of a CAN interface.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceStatus {
+    pub name: String,
+    pub index: u32,
+    pub is_up: bool,
+    /// `None` for interfaces without bit timing, such as `vcan`.
+    pub bitrate: Option<u32>,
+    pub sample_point: Option<f32>,
+    pub restart_ms: Option<u32>,
+    pub listen_only: bool,
+    pub loopback: bool,
+    /// `None` while the controller is stopped or if the driver does not report it.
+    pub state: Option<BusState>,
+}
+
+impl InterfaceStatus {
+    /// Whether every setting `config` asks for is already in place.
+    pub fn matches(&self, config: &InterfaceConfig) -> bool {
+        let timing_matches = self.bitrate.is_none()
+            || (config.bitrate.is_none_or(|bitrate| self.bitrate == Some(bitrate))
+                && config
+                    .sample_point
+                    .is_none_or(|sp| self.sample_point.map(sample_point_permille) == Some(sample_point_permille(sp))));
+        timing_matches
+            && config.restart_ms.is_none_or(|ms| self.restart_ms.is_none_or(|current| current == ms))
+            && config.listen_only.is_none_or(|on| on == self.listen_only)
+            && config.loopback.is_none_or(|on| on == self.loopback)
+    }
+}
+
+/// Reads the settings and state of `ifname`.
+pub fn query(ifname: &str) -> Result<InterfaceStatus> {
+    let iface = open(ifname)?;
+    let details = iface.details().map_err(|e| nl_error(ifname, "query", e))?;
+    let can = details.can;
+    let ctrl_mode = can.ctrl_mode.unwrap_or_default();
+    Ok(InterfaceStatus {
+        name: details.name.unwrap_or_else(|| ifname.to_string()),
+        index: details.index,
+        is_up: details.is_up,
+        bitrate: can.bit_timing.map(|timing| timing.bitrate),
+        sample_point: can.bit_timing.map(|timing| timing.sample_point as f32 / 1000.0),
+        restart_ms: can.restart_ms,
+        listen_only: ctrl_mode.has_mode(CanCtrlMode::ListenOnly),
+        loopback: ctrl_mode.has_mode(CanCtrlMode::Loopback),
+        state: can.state.and_then(bus_state),
+    })
+}
+
+pub fn set_up(ifname: &str) -> Result<()> {
+    open(ifname)?.bring_up().map_err(|e| nl_error(ifname, "bring up", e))
+}
+
+pub fn set_down(ifname: &str) -> Result<()> {
+    open(ifname)?.bring_down().map_err(|e| nl_error(ifname, "bring down", e))
+}
+
+/// Restarts a controller that is bus-off and has automatic restart disabled.
+pub fn restart(ifname: &str) -> Result<()> {
+    open(ifname)?.restart().map_err(|e| nl_error(ifname, "restart", e))
+}
+
+/// Applies `config` to `ifname`, taking it down for the change if it is up and
+/// bringing it back up afterwards. Bit timing is skipped on interfaces that have none.
+pub fn configure(ifname: &str, config: &InterfaceConfig) -> Result<()> {
+    let status = query(ifname)?;
+    if status.is_up {
+        set_down(ifname)?;
+    }
+    let result = apply(ifname, &status, config);
+    if status.is_up {
+        set_up(ifname)?;
+    }
+    result
+}
+
+/// Makes sure `ifname` is configured as `config` asks and up, touching it only
+/// if something differs. Returns whether anything was changed.
+pub fn ensure_up(ifname: &str, config: &InterfaceConfig) -> Result<bool> {
+    let status = query(ifname)?;
+    let configured = status.matches(config);
+    if configured && status.is_up {
+        return Ok(false);
+    }
+    if !configured {
+        log::info!("Configuring {}: {:?}", ifname, config);
+        if status.is_up {
+            set_down(ifname)?;
+        }
+        apply(ifname, &status, config)?;
+    }
+    set_up(ifname)?;
+    Ok(true)
+}
+
+/// Applies `config` to an interface that is down.
+fn apply(ifname: &str, status: &InterfaceStatus, config: &InterfaceConfig) -> Result<()> {
+    let iface = open(ifname)?;
+    if status.bitrate.is_some() {
+        if let Some(bitrate) = config.bitrate {
+            iface
+                .set_bitrate(bitrate, config.sample_point.map(sample_point_permille))
+                .map_err(|e| nl_error(ifname, "set the bitrate of", e))?;
+        }
+    } else if config.bitrate.is_some() || config.sample_point.is_some() {
+        log::debug!("{} has no bit timing, leaving it as is", ifname);
+    }
+    if let Some(restart_ms) = config.restart_ms {
+        if status.restart_ms.is_some() {
+            iface.set_restart_ms(restart_ms).map_err(|e| nl_error(ifname, "set the restart delay of", e))?;
+        }
+    }
+    let mut modes: Option<CanCtrlModes> = None;
+    if let Some(on) = config.listen_only {
+        modes.get_or_insert_with(CanCtrlModes::default).add(CanCtrlMode::ListenOnly, on);
+    }
+    if let Some(on) = config.loopback {
+        modes.get_or_insert_with(CanCtrlModes::default).add(CanCtrlMode::Loopback, on);
+    }
+    if let Some(modes) = modes {
+        iface.set_ctrlmodes(modes).map_err(|e| nl_error(ifname, "set the control modes of", e))?;
+    }
+    Ok(())
+}
+
+fn open(ifname: &str) -> Result<CanInterface> {
+    CanInterface::open(ifname).map_err(|e| anyhow!("No network interface named {}: {}", ifname, e))
+}
+
+/// The kernel counts the sample point in tenths of a percent.
+fn sample_point_permille(sample_point: f32) -> u32 {
+    (sample_point * 1000.0).round() as u32
+}
+
+fn bus_state(state: CanState) -> Option<BusState> {
+    match state {
+        CanState::ErrorActive => Some(BusState::ErrorActive),
+        CanState::ErrorWarning => Some(BusState::ErrorWarning),
+        CanState::ErrorPassive => Some(BusState::ErrorPassive),
+        CanState::BusOff => Some(BusState::BusOff),
+        CanState::Stopped | CanState::Sleeping => None,
+    }
+}
+
+fn nl_error<T: Debug, P: Debug>(ifname: &str, action: &str, e: NlError<T, P>) -> anyhow::Error {
+    let errno = match &e {
+        NlError::Nlmsgerr(err) => Some(-err.error),
+        NlError::Wrapped(WrappedError::IOError(err)) => err.raw_os_error(),
+        _ => None,
+    };
+    match errno {
+        Some(libc::EPERM) | Some(libc::EACCES) => anyhow!(
+            "Not permitted to {} {}: this needs root or CAP_NET_ADMIN, e.g. `sudo setcap cap_net_admin+ep <binary>`",
+            action,
+            ifname
+        ),
+        Some(libc::EBUSY) => anyhow!("Cannot {} {} while it is up", action, ifname),
+        Some(libc::EOPNOTSUPP) => anyhow!("Cannot {} {}: its driver does not support it", action, ifname),
+        _ => anyhow!("Failed to {} {}: {}", action, ifname, e),
+    }
+}
@@ -1,9 +1,7 @@
-use byteorder::{LittleEndian, ReadBytesExt};
-use std::io::Cursor;
-use std::convert::TryInto;
-
-use crate::drivers::can::messages::{ArbitrationId, CanMessageTrait, OdriveArbitrationId, RawCanMessage};
+use crate::drivers::can::messages::{ArbitrationId, CanIdFilter, CanMessage, CanMessageTrait, DecodeError, OdriveArbitrationId, RawCanMessage, RxTimestamp};
 use crate::drivers::can::enums::{AxisState, ControlMode, InputMode, ODriveError, ProcedureResult, ValueTypes};
+use crate::drivers::can::dbc::{ByteOrder, Signal, ValueType};
+use crate::drivers::can::dbc_export::DbcLayout;
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -19,16 +17,24 @@ pub enum Value {
     Float(f32),
 }
 
+/// The first `N` bytes of `data`.
+fn le_bytes<const N: usize>(data: &[u8]) -> Result<[u8; N], DecodeError> {
+    data.get(..N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(DecodeError::ShortPayload { expected: N, actual: data.len() })
+}
+
 #[derive(Debug, Clone)]
 pub struct OdriveCanMessage {
     pub node_id: u32,
     pub arbitration_id: OdriveArbitrationId,
+    pub timestamp: Option<RxTimestamp>,
 }
 
 impl OdriveCanMessage {
     pub fn new(node_id: u32, cmd_id: u32) -> Self {
         let arbitration_id = OdriveArbitrationId { node_id, cmd_id };
-        Self { node_id, arbitration_id }
+        Self { node_id, arbitration_id, timestamp: None }
     }
 }
 
@@ -37,16 +43,21 @@ impl CanMessageTrait for OdriveCanMessage {
 
     fn node_id(&self) -> u32 { self.node_id }
 
+    fn timestamp(&self) -> Option<RxTimestamp> { self.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.timestamp = timestamp; }
+
     fn matches(msg: &RawCanMessage) -> bool {
-        let arb = OdriveArbitrationId::from_can_message(msg);
-        Self::cmd_id() == arb.cmd_id
+        OdriveArbitrationId::for_cmd(msg, Self::cmd_id()).is_ok()
     }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { OdriveArbitrationId::can_filters(Self::cmd_id(), node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
         let arb = OdriveArbitrationId::from_can_message(&msg);
         let mut s = Self::new(arb.node_id, arb.cmd_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
     fn as_can_message(&self) -> RawCanMessage {
@@ -54,6 +65,7 @@ impl CanMessageTrait for OdriveCanMessage {
             arbitration_id: self.arbitration_id.value(),
             data: self.gen_can_msg_data(),
             is_extended_id: false,
+            ..Default::default()
         }
     }
 
@@ -63,15 +75,18 @@ impl CanMessageTrait for OdriveCanMessage {
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) -> Result<(), DecodeError> { Ok(()) }
 }
 
 // Cyclic Messages
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x17)]
 pub struct BusVoltageCurrentMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "V")]
     pub voltage: f32,
+    #[can(offset = 4, unit = "A")]
     pub current: f32,
 }
 
@@ -81,40 +96,15 @@ impl BusVoltageCurrentMessage {
     }
 }
 
-impl CanMessageTrait for BusVoltageCurrentMessage {
-    fn cmd_id() -> u32 { 0x17 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.voltage = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.current = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
 // Add the remaining cyclic messages
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x09)]
 pub struct EncoderEstimatesMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "rev")]
     pub pos_estimate: f32,
+    #[can(offset = 4, unit = "rev/s")]
     pub vel_estimate: f32,
 }
 
@@ -124,34 +114,6 @@ impl EncoderEstimatesMessage {
     }
 }
 
-impl CanMessageTrait for EncoderEstimatesMessage {
-    fn cmd_id() -> u32 { 0x09 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.pos_estimate = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.vel_estimate = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct ErrorMessage {
     base: OdriveCanMessage,
@@ -170,38 +132,55 @@ impl CanMessageTrait for ErrorMessage {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool { OdriveArbitrationId::for_cmd(msg, Self::cmd_id()).is_ok() }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { OdriveArbitrationId::can_filters(Self::cmd_id(), node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        let arb = OdriveArbitrationId::for_cmd(&msg, Self::cmd_id())?;
         let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            let mut cursor = Cursor::new(&msg.data);
-            let active_errors_int = cursor.read_u32::<LittleEndian>().unwrap();
-            self.active_errors = ODriveError::from_bits(active_errors_int);
-            let disarm_reason_int = cursor.read_u32::<LittleEndian>().unwrap();
-            self.disarm_reason = ODriveError::from_bits(disarm_reason_int);
-        }
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(8)?;
+        self.active_errors = ODriveError::from_bits(u32::from_le_bytes(msg.bytes(0)?));
+        self.disarm_reason = ODriveError::from_bits(u32::from_le_bytes(msg.bytes(4)?));
+        Ok(())
+    }
+}
+
+impl DbcLayout for ErrorMessage {
+    const DBC_NAME: &'static str = "ErrorMessage";
+
+    const DBC_LEN: u8 = 8;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal { comment: Some("ODriveError bits".to_string()), ..Signal::new("active_errors", 0, 32, ByteOrder::LittleEndian, ValueType::Unsigned) },
+            Signal { comment: Some("ODriveError bits".to_string()), ..Signal::new("disarm_reason", 32, 32, ByteOrder::LittleEndian, ValueType::Unsigned) },
+        ]
     }
 }
 
 // HeartbeatMessage already implemented
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x14)]
 pub struct IqMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "A")]
     pub setpoint: f32,
+    #[can(offset = 4, unit = "A")]
     pub measured: f32,
 }
 
@@ -211,38 +190,13 @@ impl IqMessage {
     }
 }
 
-impl CanMessageTrait for IqMessage {
-    fn cmd_id() -> u32 { 0x14 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.setpoint = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.measured = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x1D)]
 pub struct PowersMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "W")]
     pub electrical_power: f32,
+    #[can(offset = 4, unit = "W")]
     pub mechanical_power: f32,
 }
 
@@ -252,38 +206,13 @@ impl PowersMessage {
     }
 }
 
-impl CanMessageTrait for PowersMessage {
-    fn cmd_id() -> u32 { 0x1D }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.electrical_power = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.mechanical_power = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x15)]
 pub struct TemperatureMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "degC")]
     pub fet_temperature: f32,
+    #[can(offset = 4, unit = "degC")]
     pub motor_temperature: f32,
 }
 
@@ -293,38 +222,13 @@ impl TemperatureMessage {
     }
 }
 
-impl CanMessageTrait for TemperatureMessage {
-    fn cmd_id() -> u32 { 0x15 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.fet_temperature = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.motor_temperature = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x1C)]
 pub struct TorquesMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "Nm")]
     pub target: f32,
+    #[can(offset = 4, unit = "Nm")]
     pub estimate: f32,
 }
 
@@ -334,42 +238,21 @@ impl TorquesMessage {
     }
 }
 
-impl CanMessageTrait for TorquesMessage {
-    fn cmd_id() -> u32 { 0x1C }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.target = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.estimate = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x00, len = 8)]
 pub struct VersionMessage {
     base: OdriveCanMessage,
+    #[can(offset = 1)]
     pub hw_major: u8,
+    #[can(offset = 2)]
     pub hw_minor: u8,
+    #[can(offset = 3)]
     pub hw_variant: u8,
+    #[can(offset = 4)]
     pub fw_major: u8,
+    #[can(offset = 5)]
     pub fw_minor: u8,
+    #[can(offset = 6)]
     pub fw_revision: u8,
 }
 
@@ -387,48 +270,19 @@ impl VersionMessage {
     }
 }
 
-impl CanMessageTrait for VersionMessage {
-    fn cmd_id() -> u32 { 0x00 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            let _protocol = msg.data[0];
-            self.hw_major = msg.data[1];
-            self.hw_minor = msg.data[2];
-            self.hw_variant = msg.data[3];
-            self.fw_major = msg.data[4];
-            self.fw_minor = msg.data[5];
-            self.fw_revision = msg.data[6];
-            let _unreleased = msg.data[7];
-        }
-    }
-}
-
 // HeartbeatMessage already implemented
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x01, len = 8)]
 pub struct HeartbeatMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0)]
     pub axis_error: u32,
+    #[can(offset = 4, raw = "u8", enum)]
     pub axis_state: AxisState,
+    #[can(offset = 5, raw = "u8", enum)]
     pub procedure_result: ProcedureResult,
+    #[can(offset = 6)]
     pub trajectory_done: bool,
 }
 
@@ -438,42 +292,13 @@ impl HeartbeatMessage {
     }
 }
 
-impl CanMessageTrait for HeartbeatMessage {
-    fn cmd_id() -> u32 { 0x01 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 7 {
-            let mut cursor = Cursor::new(&msg.data);
-            self.axis_error = cursor.read_u32::<LittleEndian>().unwrap();
-            self.axis_state = AxisState::from(cursor.read_u8().unwrap());
-            self.procedure_result = ProcedureResult::from(cursor.read_u8().unwrap());
-            self.trajectory_done = cursor.read_u8().unwrap() != 0;
-        }
-    }
-}
-
 // Command messages
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x18)]
 pub struct ClearErrorsCommand {
     base: OdriveCanMessage,
+    #[can(offset = 0)]
     pub identify: u8,
 }
 
@@ -483,27 +308,16 @@ impl ClearErrorsCommand {
     }
 }
 
-impl CanMessageTrait for ClearErrorsCommand {
-    fn cmd_id() -> u32 { 0x18 }
+/// A DBC can only give the value one type, but it depends on the endpoint.
+const PARAMETER_VALUE_COMMENT: &str = "Raw bytes of the value. Its type and length depend on the endpoint.";
 
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        Self::new(arb.node_id, 0)
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        vec![self.identify]
-    }
+fn parameter_opcodes() -> Vec<(i64, String)> {
+    vec![(0, "Read".to_string()), (1, "Write".to_string())]
+}
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+/// Read and write parameter commands share 0x04 and differ in the opcode byte.
+fn matches_parameter_opcode(msg: &RawCanMessage, opcode: u8) -> bool {
+    OdriveArbitrationId::for_cmd(msg, 0x04).is_ok() && msg.data.first() == Some(&opcode)
 }
 
 #[derive(Debug, Clone)]
@@ -523,17 +337,21 @@ impl CanMessageTrait for ReadParameterCommand {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool { matches_parameter_opcode(msg, 0) }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { OdriveArbitrationId::can_filters(Self::cmd_id(), node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        let arb = OdriveArbitrationId::for_cmd(&msg, Self::cmd_id())?;
         let mut s = Self::new(arb.node_id, 0);
-        s.parse_can_msg_data(&msg);
-        s
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> {
@@ -544,12 +362,25 @@ impl CanMessageTrait for ReadParameterCommand {
         data
     }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 {
-            let _opcode = msg.data[0];
-            self.endpoint_id = u16::from_le_bytes([msg.data[1], msg.data[2]]);
-            let _reserved = msg.data[3];
-        }
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(4)?;
+        DecodeError::check_command(0, msg.data[0])?;
+        self.endpoint_id = u16::from_le_bytes([msg.data[1], msg.data[2]]);
+        let _reserved = msg.data[3];
+        Ok(())
+    }
+}
+
+impl DbcLayout for ReadParameterCommand {
+    const DBC_NAME: &'static str = "ReadParameterCommand";
+
+    const DBC_LEN: u8 = 4;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal { values: parameter_opcodes(), ..Signal::new("opcode", 0, 8, ByteOrder::LittleEndian, ValueType::Unsigned) },
+            Signal::new("endpoint_id", 8, 16, ByteOrder::LittleEndian, ValueType::Unsigned),
+        ]
     }
 }
 
@@ -587,14 +418,20 @@ impl CanMessageTrait for WriteParameterCommand {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        Self::new(arb.node_id, 0, ValueTypes::Uint32, Value::Uint32(0))
-    }
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
+    fn matches(msg: &RawCanMessage) -> bool { matches_parameter_opcode(msg, 1) }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { OdriveArbitrationId::can_filters(Self::cmd_id(), node_id) }
+
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        let arb = OdriveArbitrationId::for_cmd(&msg, Self::cmd_id())?;
+        let mut s = Self::new(arb.node_id, 0, ValueTypes::Uint32, Value::Uint32(0));
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
+    }
 
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
@@ -606,7 +443,35 @@ impl CanMessageTrait for WriteParameterCommand {
         data
     }
 
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(4)?;
+        DecodeError::check_command(1, msg.data[0])?;
+        self.endpoint_id = u16::from_le_bytes(msg.bytes(1)?);
+        // The frame does not carry the value's type, so it is guessed from the length.
+        self.value_type = match msg.data.len() - 4 {
+            1 => ValueTypes::Uint8,
+            2 => ValueTypes::Uint16,
+            8 => ValueTypes::Uint64,
+            _ => ValueTypes::Uint32,
+        };
+        self.value = ParameterResponse::parse_value(&msg.data[4..], self.value_type)?;
+        Ok(())
+    }
+}
+
+impl DbcLayout for WriteParameterCommand {
+    const DBC_NAME: &'static str = "WriteParameterCommand";
+
+    const DBC_LEN: u8 = 8;
+
+    /// Like `ReadParameterCommand`'s, plus the value, so it describes both.
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal { values: parameter_opcodes(), ..Signal::new("opcode", 0, 8, ByteOrder::LittleEndian, ValueType::Unsigned) },
+            Signal::new("endpoint_id", 8, 16, ByteOrder::LittleEndian, ValueType::Unsigned),
+            Signal { comment: Some(PARAMETER_VALUE_COMMENT.to_string()), ..Signal::new("value", 32, 32, ByteOrder::LittleEndian, ValueType::Unsigned) },
+        ]
+    }
 }
 
 // ParameterResponse already partially implemented
@@ -624,19 +489,28 @@ impl ParameterResponse {
         Self { base: OdriveCanMessage::new(node_id, Self::cmd_id()), endpoint_id, value_type, value }
     }
 
-    pub fn parse_value(data: &[u8], value_type: ValueTypes) -> Value {
-        match value_type {
-            ValueTypes::Bool => Value::Bool(data[0] != 0),
-            ValueTypes::Uint8 => Value::Uint8(data[0]),
-            ValueTypes::Int8 => Value::Int8(data[0] as i8),
-            ValueTypes::Uint16 => Value::Uint16(u16::from_le_bytes(data[0..2].try_into().unwrap())),
-            ValueTypes::Int16 => Value::Int16(i16::from_le_bytes(data[0..2].try_into().unwrap())),
-            ValueTypes::Uint32 => Value::Uint32(u32::from_le_bytes(data[0..4].try_into().unwrap())),
-            ValueTypes::Int32 => Value::Int32(i32::from_le_bytes(data[0..4].try_into().unwrap())),
-            ValueTypes::Float => Value::Float(f32::from_le_bytes(data[0..4].try_into().unwrap())),
-            ValueTypes::Uint64 => Value::Uint64(u64::from_le_bytes(data[0..8].try_into().unwrap())),
-            ValueTypes::Int64 => Value::Int64(i64::from_le_bytes(data[0..8].try_into().unwrap())),
-        }
+    /// Decodes a reply whose value has the type of the endpoint that was read.
+    pub fn decode_as(msg: RawCanMessage, value_type: ValueTypes) -> Result<Self, DecodeError> {
+        let arb = OdriveArbitrationId::for_cmd(&msg, Self::cmd_id())?;
+        let mut s = Self::new(arb.node_id, 0, value_type, Value::Uint32(0));
+        s.parse_can_msg_data(&msg)?;
+        s.base.timestamp = msg.timestamp;
+        Ok(s)
+    }
+
+    pub fn parse_value(data: &[u8], value_type: ValueTypes) -> Result<Value, DecodeError> {
+        Ok(match value_type {
+            ValueTypes::Bool => Value::Bool(le_bytes::<1>(data)?[0] != 0),
+            ValueTypes::Uint8 => Value::Uint8(le_bytes::<1>(data)?[0]),
+            ValueTypes::Int8 => Value::Int8(le_bytes::<1>(data)?[0] as i8),
+            ValueTypes::Uint16 => Value::Uint16(u16::from_le_bytes(le_bytes(data)?)),
+            ValueTypes::Int16 => Value::Int16(i16::from_le_bytes(le_bytes(data)?)),
+            ValueTypes::Uint32 => Value::Uint32(u32::from_le_bytes(le_bytes(data)?)),
+            ValueTypes::Int32 => Value::Int32(i32::from_le_bytes(le_bytes(data)?)),
+            ValueTypes::Float => Value::Float(f32::from_le_bytes(le_bytes(data)?)),
+            ValueTypes::Uint64 => Value::Uint64(u64::from_le_bytes(le_bytes(data)?)),
+            ValueTypes::Int64 => Value::Int64(i64::from_le_bytes(le_bytes(data)?)),
+        })
     }
 }
 
@@ -645,36 +519,51 @@ impl CanMessageTrait for ParameterResponse {
 
     fn node_id(&self) -> u32 { self.base.node_id }
 
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
+    fn timestamp(&self) -> Option<RxTimestamp> { self.base.timestamp }
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0, ValueTypes::Uint32, Value::Uint32(0));
-        s.parse_can_msg_data(&msg);
-        s
-    }
+    fn set_timestamp(&mut self, timestamp: Option<RxTimestamp>) { self.base.timestamp = timestamp; }
+
+    fn matches(msg: &RawCanMessage) -> bool { OdriveArbitrationId::for_cmd(msg, Self::cmd_id()).is_ok() }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
+    fn can_filters(node_id: Option<u32>) -> Vec<CanIdFilter> { OdriveArbitrationId::can_filters(Self::cmd_id(), node_id) }
+
+    /// The frame does not say the value's type, so it is decoded as a `Uint32`; see `decode_as`.
+    fn try_from_can_message(msg: RawCanMessage) -> Result<Self, DecodeError> {
+        Self::decode_as(msg, ValueTypes::Uint32)
+    }
 
     fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
 
     fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
 
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 + self.value_type.byte_size() {
-            let mut cursor = Cursor::new(&msg.data);
-            let _reserved0 = cursor.read_u8().unwrap();
-            self.endpoint_id = cursor.read_u16::<LittleEndian>().unwrap();
-            let _reserved1 = cursor.read_u8().unwrap();
-            let value_data = &msg.data[4..4 + self.value_type.byte_size()];
-            self.value = Self::parse_value(value_data, self.value_type);
-        }
+    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) -> Result<(), DecodeError> {
+        msg.require_len(4 + self.value_type.byte_size())?;
+        let _reserved0 = msg.data[0];
+        self.endpoint_id = u16::from_le_bytes(msg.bytes(1)?);
+        let _reserved1 = msg.data[3];
+        self.value = Self::parse_value(&msg.data[4..], self.value_type)?;
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
+impl DbcLayout for ParameterResponse {
+    const DBC_NAME: &'static str = "ParameterResponse";
+
+    const DBC_LEN: u8 = 8;
+
+    fn dbc_signals() -> Vec<Signal> {
+        vec![
+            Signal::new("endpoint_id", 8, 16, ByteOrder::LittleEndian, ValueType::Unsigned),
+            Signal { comment: Some(PARAMETER_VALUE_COMMENT.to_string()), ..Signal::new("value", 32, 32, ByteOrder::LittleEndian, ValueType::Unsigned) },
+        ]
+    }
+}
+
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x07)]
 pub struct SetAxisStateMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, raw = "u32", enum)]
     pub axis_state: AxisState,
 }
 
@@ -684,35 +573,15 @@ impl SetAxisStateMessage {
     }
 }
 
-impl CanMessageTrait for SetAxisStateMessage {
-    fn cmd_id() -> u32 { 0x07 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        Self::new(arb.node_id, AxisState::Undefined)
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        (self.axis_state as u32).to_le_bytes().to_vec()
-    }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
-}
-
 // Implement SetControllerMode, SetPositionMessage, SetTorqueMessage, SetVelocityMessage, EStop, Reboot similarly
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x0B)]
 pub struct SetControllerMode {
     base: OdriveCanMessage,
+    #[can(offset = 0, raw = "u32", enum)]
     pub control_mode: ControlMode,
+    #[can(offset = 4, raw = "u32", enum)]
     pub input_mode: InputMode,
 }
 
@@ -722,43 +591,15 @@ impl SetControllerMode {
     }
 }
 
-impl CanMessageTrait for SetControllerMode {
-    fn cmd_id() -> u32 { 0x0B }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, ControlMode::VoltageControl, InputMode::Inactive);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let mut data = (self.control_mode as u32).to_le_bytes().to_vec();
-        data.extend_from_slice(&(self.input_mode as u32).to_le_bytes());
-        data
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.control_mode = ControlMode::from(u32::from_le_bytes(msg.data[0..4].try_into().unwrap()));
-            self.input_mode = InputMode::from(u32::from_le_bytes(msg.data[4..8].try_into().unwrap()));
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x0C)]
 pub struct SetPositionMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "rev")]
     pub input_position: f32,
+    #[can(offset = 4)]
     pub velocity_ff: i16,
+    #[can(offset = 6)]
     pub torque_ff: i16,
 }
 
@@ -768,43 +609,11 @@ impl SetPositionMessage {
     }
 }
 
-impl CanMessageTrait for SetPositionMessage {
-    fn cmd_id() -> u32 { 0x0C }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0, 0, 0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let mut data = self.input_position.to_le_bytes().to_vec();
-        data.extend_from_slice(&self.velocity_ff.to_le_bytes());
-        data.extend_from_slice(&self.torque_ff.to_le_bytes());
-        data
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.input_position = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.velocity_ff = i16::from_le_bytes(msg.data[4..6].try_into().unwrap());
-            self.torque_ff = i16::from_le_bytes(msg.data[6..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x0E)]
 pub struct SetTorqueMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "Nm")]
     pub input_torque: f32,
 }
 
@@ -814,39 +623,13 @@ impl SetTorqueMessage {
     }
 }
 
-impl CanMessageTrait for SetTorqueMessage {
-    fn cmd_id() -> u32 { 0x0E }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        self.input_torque.to_le_bytes().to_vec()
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 {
-            self.input_torque = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x0D)]
 pub struct SetVelocityMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "rev/s")]
     pub velocity: f32,
+    #[can(offset = 4, unit = "Nm")]
     pub torque: f32,
 }
 
@@ -856,39 +639,8 @@ impl SetVelocityMessage {
     }
 }
 
-impl CanMessageTrait for SetVelocityMessage {
-    fn cmd_id() -> u32 { 0x0D }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let mut data = self.velocity.to_le_bytes().to_vec();
-        data.extend_from_slice(&self.torque.to_le_bytes());
-        data
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.velocity = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.torque = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x02, priority = "Emergency")]
 pub struct EStop {
     base: OdriveCanMessage,
 }
@@ -899,30 +651,11 @@ impl EStop {
     }
 }
 
-impl CanMessageTrait for EStop {
-    fn cmd_id() -> u32 { 0x02 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        Self::new(arb.node_id)
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x16)]
 pub struct Reboot {
     base: OdriveCanMessage,
+    #[can(offset = 0)]
     pub action: u32,
 }
 
@@ -932,37 +665,13 @@ impl Reboot {
     }
 }
 
-impl CanMessageTrait for Reboot {
-    fn cmd_id() -> u32 { 0x16 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> { self.action.to_le_bytes().to_vec() }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 {
-            self.action = u32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x0F)]
 pub struct SetLimitsCommand {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "rev/s")]
     pub velocity_limit: f32,
+    #[can(offset = 4, unit = "A")]
     pub current_limit: f32,
 }
 
@@ -972,41 +681,11 @@ impl SetLimitsCommand {
     }
 }
 
-impl CanMessageTrait for SetLimitsCommand {
-    fn cmd_id() -> u32 { 0x0F }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let mut data = self.velocity_limit.to_le_bytes().to_vec();
-        data.extend_from_slice(&self.current_limit.to_le_bytes());
-        data
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.velocity_limit = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.current_limit = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x11)]
 pub struct SetTrajVelLimitMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "rev/s")]
     pub traj_vel_limit: f32,
 }
 
@@ -1016,39 +695,13 @@ impl SetTrajVelLimitMessage {
     }
 }
 
-impl CanMessageTrait for SetTrajVelLimitMessage {
-    fn cmd_id() -> u32 { 0x11 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        self.traj_vel_limit.to_le_bytes().to_vec()
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 {
-            self.traj_vel_limit = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x12)]
 pub struct SetTrajAccelLimitsMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "rev/s^2")]
     pub traj_accel_limit: f32,
+    #[can(offset = 4, unit = "rev/s^2")]
     pub traj_decel_limit: f32,
 }
 
@@ -1058,41 +711,11 @@ impl SetTrajAccelLimitsMessage {
     }
 }
 
-impl CanMessageTrait for SetTrajAccelLimitsMessage {
-    fn cmd_id() -> u32 { 0x12 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let mut data = self.traj_accel_limit.to_le_bytes().to_vec();
-        data.extend_from_slice(&self.traj_decel_limit.to_le_bytes());
-        data
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.traj_accel_limit = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.traj_decel_limit = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x13)]
 pub struct SetTrajInertiaMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "Nm/(rev/s^2)")]
     pub traj_inertia: f32,
 }
 
@@ -1102,38 +725,11 @@ impl SetTrajInertiaMessage {
     }
 }
 
-impl CanMessageTrait for SetTrajInertiaMessage {
-    fn cmd_id() -> u32 { 0x13 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        self.traj_inertia.to_le_bytes().to_vec()
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 {
-            self.traj_inertia = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x19)]
 pub struct SetAbsolutePositionMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "rev")]
     pub position: f32,
 }
 
@@ -1143,38 +739,11 @@ impl SetAbsolutePositionMessage {
     }
 }
 
-impl CanMessageTrait for SetAbsolutePositionMessage {
-    fn cmd_id() -> u32 { 0x019 }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        self.position.to_le_bytes().to_vec()
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 {
-            self.position = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x1A)]
 pub struct SetPosGainMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "(rev/s)/rev")]
     pub pos_gain: f32,
 }
 
@@ -1184,39 +753,13 @@ impl SetPosGainMessage {
     }
 }
 
-impl CanMessageTrait for SetPosGainMessage {
-    fn cmd_id() -> u32 { 0x01A }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        self.pos_gain.to_le_bytes().to_vec()
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 4 {
-            self.pos_gain = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x1B)]
 pub struct SetVelGainsMessage {
     base: OdriveCanMessage,
+    #[can(offset = 0, unit = "Nm/(rev/s)")]
     pub vel_gain: f32,
+    #[can(offset = 4, unit = "Nm/rev")]
     pub vel_integrator_gain: f32,
 }
 
@@ -1226,39 +769,8 @@ impl SetVelGainsMessage {
     }
 }
 
-impl CanMessageTrait for SetVelGainsMessage {
-    fn cmd_id() -> u32 { 0x01B }
-
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
-
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        let mut s = Self::new(arb.node_id, 0.0, 0.0);
-        s.parse_can_msg_data(&msg);
-        s
-    }
-
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
-
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
-
-    fn gen_can_msg_data(&self) -> Vec<u8> {
-        let mut data = self.vel_gain.to_le_bytes().to_vec();
-        data.extend_from_slice(&self.vel_integrator_gain.to_le_bytes());
-        data
-    }
-
-    fn parse_can_msg_data(&mut self, msg: &RawCanMessage) {
-        if msg.data.len() >= 8 {
-            self.vel_gain = f32::from_le_bytes(msg.data[0..4].try_into().unwrap());
-            self.vel_integrator_gain = f32::from_le_bytes(msg.data[4..8].try_into().unwrap());
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x1F)]
 pub struct EnterDfuModeCommand {
     base: OdriveCanMessage,
 }
@@ -1269,23 +781,28 @@ impl EnterDfuModeCommand {
     }
 }
 
-impl CanMessageTrait for EnterDfuModeCommand {
-    fn cmd_id() -> u32 { 0x01F }
 
-    fn node_id(&self) -> u32 { self.base.node_id }
-
-    fn matches(msg: &RawCanMessage) -> bool { OdriveCanMessage::matches(msg) }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn from_can_message(msg: RawCanMessage) -> Self {
-        let arb = OdriveArbitrationId::from_can_message(&msg);
-        Self::new(arb.node_id)
+    fn extended(msg: RawCanMessage) -> RawCanMessage {
+        RawCanMessage { is_extended_id: true, ..msg }
     }
 
-    fn as_can_message(&self) -> RawCanMessage { self.base.as_can_message() }
+    #[test]
+    fn extended_frames_do_not_match() {
+        let heartbeat = HeartbeatMessage::new(1).as_can_message();
+        assert!(HeartbeatMessage::matches(&heartbeat));
+        assert!(!HeartbeatMessage::matches(&extended(heartbeat.clone())));
+        assert!(HeartbeatMessage::try_from_can_message(extended(heartbeat)).is_err());
 
-    fn gen_arbitration_id(&self) -> ArbitrationId { self.base.gen_arbitration_id() }
+        let error = RawCanMessage::new(1 << 5 | 0x03, vec![0; 8]);
+        assert!(ErrorMessage::matches(&error));
+        assert!(!ErrorMessage::matches(&extended(error)));
 
-    fn gen_can_msg_data(&self) -> Vec<u8> { vec![] }
-
-    fn parse_can_msg_data(&mut self, _msg: &RawCanMessage) {}
+        let read = ReadParameterCommand::new(1, 0x10).as_can_message();
+        assert!(ReadParameterCommand::matches(&read));
+        assert!(!ReadParameterCommand::matches(&extended(read)));
+    }
 }
@@ -0,0 +1,426 @@
+//! pcapng capture files for Wireshark.
+//!
+//! Frames are written as `LINKTYPE_CAN_SOCKETCAN` packets with nanosecond
+//! timestamps and their direction. Every channel gets its own interface
+//! description block, named after the channel, the first time it shows up, so
+//! one file can hold several buses. Long captures can be rotated by size or age.
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use super::recorder::{Direction, FrameSink};
+use super::transport::TransportEvent;
+
+const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+
+const SHB_TYPE: u32 = 0x0A0D_0D0A;
+const IDB_TYPE: u32 = 0x0000_0001;
+const EPB_TYPE: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_EPB_FLAGS: u16 = 2;
+
+/// `if_tsresol` of 9: timestamps count nanoseconds.
+const TSRESOL_NANOS: u8 = 9;
+
+const EPB_INBOUND: u32 = 0b01;
+const EPB_OUTBOUND: u32 = 0b10;
+
+// The SocketCAN header of each packet.
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+const CANFD_FDF: u8 = 0x04;
+
+/// Snap length of every interface: a full CAN FD frame.
+const SNAPLEN: u32 = 72;
+
+/// When a `PcapngWriter` moves on to a new file. Unset limits never trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+    /// Delete the oldest files beyond this many.
+    pub max_files: Option<usize>,
+}
+
+struct Output {
+    out: Box<dyn Write + Send>,
+    interfaces: Vec<String>,
+    written: u64,
+    opened: Instant,
+}
+
+/// Files of a rotating capture: `<stem>_00000.pcapng`, `<stem>_00001.pcapng`, ...
+struct RotatingFiles {
+    base: PathBuf,
+    rotation: Rotation,
+    next_index: u64,
+    files: VecDeque<PathBuf>,
+}
+
+impl RotatingFiles {
+    fn open_next(&mut self) -> Result<Box<dyn Write + Send>> {
+        let stem = self.base.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self.base.extension().map_or("pcapng".into(), |e| e.to_string_lossy());
+        let path = self.base.with_file_name(format!("{}_{:05}.{}", stem, self.next_index, extension));
+        self.next_index += 1;
+        let file = File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        self.files.push_back(path);
+        if let Some(max_files) = self.rotation.max_files {
+            while self.files.len() > max_files.max(1) {
+                let old = self.files.pop_front().unwrap();
+                if let Err(e) = fs::remove_file(&old) {
+                    log::warn!("Failed to remove old capture {}: {}", old.display(), e);
+                }
+            }
+        }
+        Ok(Box::new(BufWriter::new(file)))
+    }
+
+    fn due(&self, output: &Output) -> bool {
+        self.rotation.max_bytes.is_some_and(|max| output.written >= max)
+            || self.rotation.max_age.is_some_and(|max| output.opened.elapsed() >= max)
+    }
+}
+
+struct WriterState {
+    output: Output,
+    rotating: Option<RotatingFiles>,
+}
+
+/// A `FrameSink` writing pcapng. Use it with a `RecordingTransport`, or open a
+/// bus with `CanSimple::new_recorded`.
+pub struct PcapngWriter {
+    state: Mutex<WriterState>,
+}
+
+impl PcapngWriter {
+    pub fn new(out: impl Write + Send + 'static) -> Result<Self> {
+        let output = Self::start(Box::new(out), Vec::new())?;
+        Ok(Self { state: Mutex::new(WriterState { output, rotating: None }) })
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Self::new(BufWriter::new(file))
+    }
+
+    /// Writes numbered files next to `path`, starting a new one as `rotation` says.
+    pub fn create_rotating(path: impl AsRef<Path>, rotation: Rotation) -> Result<Self> {
+        let mut rotating = RotatingFiles { base: path.as_ref().to_path_buf(), rotation, next_index: 0, files: VecDeque::new() };
+        let output = Self::start(rotating.open_next()?, Vec::new())?;
+        Ok(Self { state: Mutex::new(WriterState { output, rotating: Some(rotating) }) })
+    }
+
+    /// The files written so far that have not been rotated away, oldest first.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let state = self.state.lock().unwrap();
+        state.rotating.as_ref().map_or_else(Vec::new, |r| r.files.iter().cloned().collect())
+    }
+
+    /// Starts a section on `out`, describing `interfaces` again so their ids stay valid.
+    fn start(out: Box<dyn Write + Send>, interfaces: Vec<String>) -> Result<Output> {
+        let mut output = Output { out, interfaces: Vec::new(), written: 0, opened: Instant::now() };
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // Section length unknown.
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        push_option(&mut body, OPT_COMMENT, b"havendrive CAN capture");
+        push_option(&mut body, OPT_END, &[]);
+        write_block(&mut output, SHB_TYPE, &body)?;
+        for name in interfaces {
+            Self::describe(&mut output, name)?;
+        }
+        Ok(output)
+    }
+
+    fn describe(output: &mut Output, name: String) -> Result<u32> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&SNAPLEN.to_le_bytes());
+        push_option(&mut body, OPT_IF_NAME, name.as_bytes());
+        push_option(&mut body, OPT_IF_TSRESOL, &[TSRESOL_NANOS]);
+        push_option(&mut body, OPT_END, &[]);
+        write_block(output, IDB_TYPE, &body)?;
+        output.interfaces.push(name);
+        Ok(output.interfaces.len() as u32 - 1)
+    }
+}
+
+impl FrameSink for PcapngWriter {
+    fn record(&self, channel: &str, direction: Direction, time: Duration, event: &TransportEvent) -> Result<()> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        if let Some(rotating) = &mut state.rotating {
+            if rotating.due(&state.output) {
+                state.output.out.flush()?;
+                let interfaces = std::mem::take(&mut state.output.interfaces);
+                state.output = Self::start(rotating.open_next()?, interfaces)?;
+            }
+        }
+        let output = &mut state.output;
+        let interface = match output.interfaces.iter().position(|name| name == channel) {
+            Some(i) => i as u32,
+            None => Self::describe(output, channel.to_string())?,
+        };
+
+        let packet = socketcan_packet(event);
+        let nanos = time.as_nanos() as u64;
+        let mut body = Vec::new();
+        body.extend_from_slice(&interface.to_le_bytes());
+        body.extend_from_slice(&((nanos >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(nanos as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&packet);
+        pad(&mut body);
+        let flags = match direction {
+            Direction::Rx => EPB_INBOUND,
+            Direction::Tx => EPB_OUTBOUND,
+        };
+        push_option(&mut body, OPT_EPB_FLAGS, &flags.to_le_bytes());
+        push_option(&mut body, OPT_END, &[]);
+        write_block(output, EPB_TYPE, &body)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.state.lock().unwrap().output.out.flush()?;
+        Ok(())
+    }
+}
+
+/// A frame as `LINKTYPE_CAN_SOCKETCAN` lays it out: the id and flags in network
+/// byte order, the payload length, FD flags, two reserved bytes, then the payload.
+fn socketcan_packet(event: &TransportEvent) -> Vec<u8> {
+    let (can_id, fd_flags, data) = match event {
+        TransportEvent::Frame(raw) => {
+            let can_id = raw.arbitration_id | if raw.is_extended_id { CAN_EFF_FLAG } else { 0 };
+            let fd_flags = if raw.is_fd {
+                CANFD_FDF | if raw.bitrate_switch { CANFD_BRS } else { 0 } | if raw.error_state_indicator { CANFD_ESI } else { 0 }
+            } else {
+                0
+            };
+            (can_id, fd_flags, raw.data.clone())
+        }
+        TransportEvent::Error(frame) => {
+            let (bits, data) = frame.encode();
+            (bits | CAN_ERR_FLAG, 0, data.to_vec())
+        }
+    };
+    let mut packet = Vec::with_capacity(8 + data.len());
+    packet.extend_from_slice(&can_id.to_be_bytes());
+    packet.push(data.len() as u8);
+    packet.push(fd_flags);
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(&data);
+    packet
+}
+
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    pad(buf);
+}
+
+fn write_block(output: &mut Output, block_type: u32, body: &[u8]) -> Result<()> {
+    let total = (body.len() + 12) as u32;
+    output.out.write_all(&block_type.to_le_bytes())?;
+    output.out.write_all(&total.to_le_bytes())?;
+    output.out.write_all(body)?;
+    output.out.write_all(&total.to_le_bytes())?;
+    output.written += total as u64;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::drivers::can::bus_events::{BusErrorFrame, BusErrorKind};
+    use crate::drivers::can::messages::RawCanMessage;
+
+    /// A `Write` the test can read back while the writer holds it.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    /// Splits a file into (type, body) blocks, checking both length fields.
+    fn blocks(bytes: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let mut blocks = Vec::new();
+        let mut at = 0;
+        while at < bytes.len() {
+            let (block_type, total) = (u32_at(bytes, at), u32_at(bytes, at + 4) as usize);
+            assert_eq!(total % 4, 0, "block at {} is not padded", at);
+            assert_eq!(u32_at(bytes, at + total - 4) as usize, total, "trailing length of the block at {}", at);
+            blocks.push((block_type, bytes[at + 8..at + total - 4].to_vec()));
+            at += total;
+        }
+        assert_eq!(at, bytes.len());
+        blocks
+    }
+
+    /// The options starting at `at`, up to `opt_endofopt`.
+    fn options(body: &[u8], mut at: usize) -> Vec<(u16, Vec<u8>)> {
+        let mut options = Vec::new();
+        loop {
+            let (code, len) = (u16_at(body, at), u16_at(body, at + 2) as usize);
+            if code == OPT_END {
+                assert_eq!((len, at + 4), (0, body.len()));
+                return options;
+            }
+            options.push((code, body[at + 4..at + 4 + len].to_vec()));
+            at += 4 + len.next_multiple_of(4);
+        }
+    }
+
+    const T0: Duration = Duration::new(1_700_000_000, 123_456_789);
+
+    fn frame(id: u32) -> TransportEvent {
+        TransportEvent::Frame(RawCanMessage::new(id, vec![1, 2, 3]))
+    }
+
+    #[test]
+    fn blocks_interfaces_and_packets() {
+        let buffer = Buffer::default();
+        let writer = PcapngWriter::new(buffer.clone()).unwrap();
+        let fd = RawCanMessage { is_fd: true, bitrate_switch: true, is_extended_id: true, ..RawCanMessage::new(0x1ABC_DEF0, vec![0xAA; 12]) };
+        let error = BusErrorFrame { kinds: vec![BusErrorKind::AckError], controller: 0, counters: None };
+        writer.record("can0", Direction::Rx, T0, &frame(0x123)).unwrap();
+        writer.record("can1", Direction::Tx, T0 + Duration::from_secs(1), &TransportEvent::Frame(fd)).unwrap();
+        writer.record("can0", Direction::Rx, T0, &TransportEvent::Error(error.clone())).unwrap();
+        writer.flush().unwrap();
+
+        let bytes = buffer.0.lock().unwrap().clone();
+        let blocks = blocks(&bytes);
+        let types: Vec<u32> = blocks.iter().map(|(block_type, _)| *block_type).collect();
+        assert_eq!(types, [SHB_TYPE, IDB_TYPE, EPB_TYPE, IDB_TYPE, EPB_TYPE, EPB_TYPE]);
+
+        let shb = &blocks[0].1;
+        assert_eq!((u32_at(shb, 0), u16_at(shb, 4), u16_at(shb, 6)), (BYTE_ORDER_MAGIC, 1, 0));
+        assert_eq!(&shb[8..16], &[0xFF; 8]);
+        assert_eq!(options(shb, 16), [(OPT_COMMENT, b"havendrive CAN capture".to_vec())]);
+
+        for (idb, name) in [(&blocks[1].1, "can0"), (&blocks[3].1, "can1")] {
+            assert_eq!((u16_at(idb, 0), u16_at(idb, 2), u32_at(idb, 4)), (227, 0, SNAPLEN));
+            assert_eq!(options(idb, 8), [(OPT_IF_NAME, name.as_bytes().to_vec()), (OPT_IF_TSRESOL, vec![9])]);
+        }
+
+        // (interface, time, packet, flags) of each packet.
+        let packets: Vec<(u32, Duration, Vec<u8>, u32)> = [2, 4, 5]
+            .iter()
+            .map(|i| {
+                let epb = &blocks[*i].1;
+                let nanos = (u32_at(epb, 4) as u64) << 32 | u32_at(epb, 8) as u64;
+                let (captured, original) = (u32_at(epb, 12) as usize, u32_at(epb, 16));
+                assert_eq!(captured as u32, original);
+                let options = options(epb, 20 + captured.next_multiple_of(4));
+                assert_eq!(options.len(), 1);
+                assert_eq!(options[0].0, OPT_EPB_FLAGS);
+                (u32_at(epb, 0), Duration::from_nanos(nanos), epb[20..20 + captured].to_vec(), u32_at(&options[0].1, 0))
+            })
+            .collect();
+        // 1_700_000_000.123456789 s is 0x1797_9CFE_3D85_CD15 ns.
+        assert_eq!((u32_at(&blocks[2].1, 4), u32_at(&blocks[2].1, 8)), (0x1797_9CFE, 0x3D85_CD15));
+        assert_eq!(packets[0], (0, T0, vec![0, 0, 0x01, 0x23, 3, 0, 0, 0, 1, 2, 3], EPB_INBOUND));
+        let fd_header = [0x9A, 0xBC, 0xDE, 0xF0, 12, CANFD_FDF | CANFD_BRS, 0, 0];
+        assert_eq!(packets[1].2[..8], fd_header);
+        assert_eq!((packets[1].0, packets[1].1, packets[1].2.len(), packets[1].3), (1, T0 + Duration::from_secs(1), 20, EPB_OUTBOUND));
+        let (bits, data) = error.encode();
+        assert_eq!(packets[2].2[..4], (bits | CAN_ERR_FLAG).to_be_bytes());
+        assert_eq!((packets[2].0, &packets[2].2[8..]), (0, &data[..]));
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("havendrive-pcapng-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(writer: &PcapngWriter) -> Vec<String> {
+        writer.files().iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn rotates_by_size_and_keeps_the_newest_files() {
+        let dir = temp_dir("size");
+        // The header and one interface take 100 bytes and each packet 56 more,
+        // so a file fills up after its second packet.
+        let rotation = Rotation { max_bytes: Some(200), max_age: None, max_files: Some(2) };
+        let writer = PcapngWriter::create_rotating(dir.join("bus.pcapng"), rotation).unwrap();
+        for i in 0..3 {
+            writer.record("can0", Direction::Rx, T0, &frame(i)).unwrap();
+        }
+        assert_eq!(file_names(&writer), ["bus_00000.pcapng", "bus_00001.pcapng"]);
+        for i in 3..5 {
+            writer.record("can0", Direction::Rx, T0, &frame(i)).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(file_names(&writer), ["bus_00001.pcapng", "bus_00002.pcapng"]);
+        assert!(!dir.join("bus_00000.pcapng").exists());
+
+        // Every file is a capture of its own, describing its interfaces again.
+        let types: Vec<Vec<u32>> = writer
+            .files()
+            .iter()
+            .map(|path| blocks(&fs::read(path).unwrap()).iter().map(|(block_type, _)| *block_type).collect())
+            .collect();
+        assert_eq!(types, [vec![SHB_TYPE, IDB_TYPE, EPB_TYPE, EPB_TYPE], vec![SHB_TYPE, IDB_TYPE, EPB_TYPE]]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_by_age() {
+        let dir = temp_dir("age");
+        let rotation = Rotation { max_bytes: None, max_age: Some(Duration::from_millis(50)), max_files: None };
+        let writer = PcapngWriter::create_rotating(dir.join("bus"), rotation).unwrap();
+        writer.record("can0", Direction::Rx, T0, &frame(1)).unwrap();
+        writer.record("can0", Direction::Rx, T0, &frame(2)).unwrap();
+        assert_eq!(file_names(&writer), ["bus_00000.pcapng"]);
+        std::thread::sleep(Duration::from_millis(60));
+        writer.record("can0", Direction::Rx, T0, &frame(3)).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(file_names(&writer), ["bus_00000.pcapng", "bus_00001.pcapng"]);
+        assert!(writer.files().iter().all(|path| path.exists()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
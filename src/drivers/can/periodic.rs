@@ -0,0 +1,71 @@
+//! Frames sent at a fixed rate.
+//!
+//! `CanSimple::send_periodic` hands the frame to the transport when it can keep
+//! time itself (the kernel broadcast manager for SocketCAN) and otherwise runs a
+//! userspace interval. Either way the caller gets a `PeriodicHandle`.
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use super::messages::{CanMessageTrait, RawCanMessage};
+
+/// A running periodic transmission, as started by a transport or the userspace fallback.
+pub trait PeriodicTx: Send + Sync {
+    /// Replaces the frame sent from the next period on. The id and frame type stay the same.
+    fn update(&self, msg: &RawCanMessage) -> Result<()>;
+
+    /// Stops sending. Calling it again does nothing.
+    fn cancel(&self) -> Result<()>;
+}
+
+/// Controls a periodic transmission. Dropping the handle cancels it.
+pub struct PeriodicHandle {
+    task: Arc<dyn PeriodicTx>,
+    arbitration_id: u32,
+    is_fd: bool,
+    period: Duration,
+}
+
+impl PeriodicHandle {
+    pub(crate) fn new(task: Arc<dyn PeriodicTx>, msg: &RawCanMessage, period: Duration) -> Self {
+        Self { task, arbitration_id: msg.arbitration_id, is_fd: msg.is_fd, period }
+    }
+
+    pub fn arbitration_id(&self) -> u32 {
+        self.arbitration_id
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Sends `msg` instead from the next period on, without restarting the timer.
+    pub fn update(&self, msg: &impl CanMessageTrait) -> Result<()> {
+        self.update_raw(msg.as_can_message())
+    }
+
+    pub fn update_raw(&self, msg: RawCanMessage) -> Result<()> {
+        msg.validate()?;
+        if msg.arbitration_id != self.arbitration_id || msg.is_fd != self.is_fd {
+            return Err(anyhow!(
+                "Periodic frame 0x{:X} can only be updated with a frame of the same id and type, got 0x{:X}",
+                self.arbitration_id,
+                msg.arbitration_id
+            ));
+        }
+        self.task.update(&msg)
+    }
+
+    pub fn cancel(self) -> Result<()> {
+        self.task.cancel()
+    }
+}
+
+impl Drop for PeriodicHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.task.cancel() {
+            log::warn!("Failed to cancel periodic frame 0x{:X}: {}", self.arbitration_id, e);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Recording of bus traffic.
+//!
+//! `RecordingTransport` wraps another transport and hands every frame it sends or
+//! receives to a `FrameSink`, such as a `CandumpWriter`. Wrap the transport before
+//! passing it to `CanSimple::with_transport` to record a session.
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use super::messages::{CanIdFilter, RawCanMessage, RxTimestamp, TimestampSource};
+use super::periodic::PeriodicTx;
+use super::transport::{CanTransport, TransportEvent, TransportFuture};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+/// Where a `RecordingTransport` writes frames. `time` is since the Unix epoch.
+pub trait FrameSink: Send + Sync {
+    fn record(&self, channel: &str, direction: Direction, time: Duration, event: &TransportEvent) -> Result<()>;
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct RecordingTransport<T> {
+    inner: T,
+    sink: Arc<dyn FrameSink>,
+}
+
+impl<T: CanTransport> RecordingTransport<T> {
+    pub fn new(inner: T, sink: Arc<dyn FrameSink>) -> Self {
+        Self { inner, sink }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn record(&self, direction: Direction, time: Duration, event: &TransportEvent) {
+        if let Err(e) = self.sink.record(self.inner.channel(), direction, time, event) {
+            log::warn!("Failed to record a frame on {}: {}", self.inner.channel(), e);
+        }
+    }
+}
+
+impl<T: CanTransport> CanTransport for RecordingTransport<T> {
+    fn open(channel: &str) -> Result<Self> {
+        Err(anyhow!("A recording of {} needs a sink, use RecordingTransport::new", channel))
+    }
+
+    fn channel(&self) -> &str {
+        self.inner.channel()
+    }
+
+    fn send<'a>(&'a self, msg: &'a RawCanMessage) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            self.inner.send(msg).await?;
+            self.record(Direction::Tx, RxTimestamp::now().time, &TransportEvent::Frame(msg.clone()));
+            Ok(())
+        })
+    }
+
+    fn recv(&self, timeout: Duration) -> TransportFuture<'_, Option<TransportEvent>> {
+        Box::pin(async move {
+            let event = self.inner.recv(timeout).await?;
+            if let Some(event) = &event {
+                // Hardware stamps are not on the wall clock the sinks write.
+                let time = match event {
+                    TransportEvent::Frame(RawCanMessage { timestamp: Some(ts), .. }) if ts.source != TimestampSource::Hardware => ts.time,
+                    _ => RxTimestamp::now().time,
+                };
+                self.record(Direction::Rx, time, event);
+            }
+            Ok(event)
+        })
+    }
+
+    /// Keeps the inner transport unfiltered so the recording has the whole bus,
+    /// not just the frames someone subscribed to.
+    fn set_filters(&self, _filters: &[CanIdFilter]) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_periodic(&self, _msg: &RawCanMessage, _period: Duration) -> Result<Option<Box<dyn PeriodicTx>>> {
+        // Frames the transport sends on its own would bypass `send` and go unrecorded.
+        Ok(None)
+    }
+
+    fn can_reopen(&self) -> bool {
+        self.inner.can_reopen()
+    }
+
+    fn reopen(&self) -> TransportFuture<'_, ()> {
+        self.inner.reopen()
+    }
+}
+
+impl<T> Drop for RecordingTransport<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.sink.flush() {
+            log::warn!("Failed to flush a recording: {}", e);
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Playback of recorded bus traffic.
+//!
+//! `ReplayTransport` feeds the frames of a candump log to a `CanSimple` as if
+//! they were arriving on a bus, so decoders, subscriptions and callbacks run
+//! against a captured session. Sent frames are discarded.
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::Notify;
+use tokio::time::{self, Instant};
+
+use super::candump::{read_log, CandumpEntry};
+use super::messages::{CanIdFilter, RawCanMessage};
+use super::transport::{CanTransport, TransportEvent, TransportFuture};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReplayMode {
+    /// Frames come with the gaps they were recorded with.
+    #[default]
+    Realtime,
+    /// Gaps are divided by the factor. `f64::INFINITY` replays as fast as possible.
+    Accelerated(f64),
+    /// Frames come only as `ReplayControl::step` releases them.
+    Stepped,
+}
+
+struct ReplayState {
+    entries: Vec<CandumpEntry>,
+    position: usize,
+    /// When the first frame was released; the time base of `Realtime` and `Accelerated`.
+    started: Option<Instant>,
+    /// Frames `step` has released but `recv` has not handed out yet.
+    steps: usize,
+    filters: Option<Vec<CanIdFilter>>,
+}
+
+struct Shared {
+    mode: ReplayMode,
+    state: Mutex<ReplayState>,
+    /// Signalled on `step` and once the log is exhausted.
+    changed: Notify,
+}
+
+pub struct ReplayTransport {
+    channel: String,
+    shared: Arc<Shared>,
+}
+
+impl ReplayTransport {
+    /// Replays the frames of `entries` recorded on `interface`, or every frame if `None`.
+    pub fn new(entries: Vec<CandumpEntry>, interface: Option<&str>, mode: ReplayMode) -> Result<Self> {
+        if let ReplayMode::Accelerated(factor) = mode {
+            if factor.is_nan() || factor <= 0.0 {
+                return Err(anyhow!("Replay speed-up must be positive, got {}", factor));
+            }
+        }
+        let entries: Vec<CandumpEntry> = entries.into_iter().filter(|e| interface.is_none_or(|i| e.interface == i)).collect();
+        let state = ReplayState { entries, position: 0, started: None, steps: 0, filters: None };
+        Ok(Self {
+            channel: interface.unwrap_or("replay").to_string(),
+            shared: Arc::new(Shared { mode, state: Mutex::new(state), changed: Notify::new() }),
+        })
+    }
+
+    pub fn from_log(path: impl AsRef<Path>, interface: Option<&str>, mode: ReplayMode) -> Result<Self> {
+        Self::new(read_log(path)?, interface, mode)
+    }
+
+    /// A handle to step the replay and follow its progress.
+    pub fn control(&self) -> ReplayControl {
+        ReplayControl { shared: self.shared.clone() }
+    }
+
+    /// How long to wait before handing out the frame at the current position, or
+    /// `None` while `Stepped` replay has no step to spend.
+    fn due(&self, state: &mut ReplayState, now: Instant) -> Option<Instant> {
+        let offset = |state: &ReplayState| state.entries[state.position].time.saturating_sub(state.entries[0].time);
+        match self.shared.mode {
+            ReplayMode::Stepped => (state.steps > 0).then_some(now),
+            ReplayMode::Realtime => Some(*state.started.get_or_insert(now) + offset(state)),
+            ReplayMode::Accelerated(factor) => {
+                let started = *state.started.get_or_insert(now);
+                Some(started + offset(state).div_f64(factor).min(Duration::from_secs(u32::MAX as u64)))
+            }
+        }
+    }
+}
+
+impl CanTransport for ReplayTransport {
+    /// Replays every frame of the candump log at `channel` in real time.
+    fn open(channel: &str) -> Result<Self> {
+        Self::from_log(channel, None, ReplayMode::Realtime)
+    }
+
+    fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    fn send<'a>(&'a self, _msg: &'a RawCanMessage) -> TransportFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Hands out the next frame when it is due. Once the log is exhausted this only ever times out.
+    fn recv(&self, timeout: Duration) -> TransportFuture<'_, Option<TransportEvent>> {
+        Box::pin(async move {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let changed = self.shared.changed.notified();
+                tokio::pin!(changed);
+                changed.as_mut().enable();
+                let due = {
+                    let mut state = self.shared.state.lock().unwrap();
+                    if state.position >= state.entries.len() {
+                        None
+                    } else {
+                        self.due(&mut state, Instant::now())
+                    }
+                };
+                match due {
+                    Some(due) if due <= deadline => time::sleep_until(due).await,
+                    Some(_) => {
+                        time::sleep_until(deadline).await;
+                        return Ok(None);
+                    }
+                    None => {
+                        if time::timeout_at(deadline, changed).await.is_err() {
+                            return Ok(None);
+                        }
+                        continue;
+                    }
+                }
+                let mut state = self.shared.state.lock().unwrap();
+                let Some(entry) = state.entries.get(state.position).cloned() else { continue };
+                state.position += 1;
+                state.steps = state.steps.saturating_sub(1);
+                if state.position == state.entries.len() {
+                    self.shared.changed.notify_waiters();
+                }
+                if let TransportEvent::Frame(raw) = &entry.event {
+                    let accepted = state.filters.as_ref().is_none_or(|filters| filters.iter().any(|f| f.accepts_frame(raw)));
+                    if !accepted {
+                        continue;
+                    }
+                }
+                return Ok(Some(entry.event));
+            }
+        })
+    }
+
+    fn set_filters(&self, filters: &[CanIdFilter]) -> Result<()> {
+        self.shared.state.lock().unwrap().filters = Some(filters.to_vec());
+        Ok(())
+    }
+}
+
+/// Steps a `Stepped` replay and reports progress of any replay.
+#[derive(Clone)]
+pub struct ReplayControl {
+    shared: Arc<Shared>,
+}
+
+impl ReplayControl {
+    /// Releases the next `frames` frames of a `Stepped` replay.
+    pub fn step(&self, frames: usize) {
+        self.shared.state.lock().unwrap().steps += frames;
+        self.shared.changed.notify_waiters();
+    }
+
+    /// Frames handed out so far, including ones the filters dropped.
+    pub fn position(&self) -> usize {
+        self.shared.state.lock().unwrap().position
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_finished(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        state.position >= state.entries.len()
+    }
+
+    /// Waits until every frame has been handed out.
+    pub async fn finished(&self) {
+        loop {
+            let changed = self.shared.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            if self.is_finished() {
+                return;
+            }
+            changed.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::can::candump::CandumpWriter;
+    use crate::drivers::can::recorder::RecordingTransport;
+    use crate::drivers::can::virtual_bus::VirtualBus;
+
+    fn entry(ms: u64, interface: &str, raw: RawCanMessage) -> CandumpEntry {
+        CandumpEntry { time: Duration::from_secs(1_700_000_000) + Duration::from_millis(ms), interface: interface.to_string(), event: TransportEvent::Frame(raw) }
+    }
+
+    fn log() -> Vec<CandumpEntry> {
+        vec![
+            entry(0, "can0", RawCanMessage::new(0x100, vec![0])),
+            entry(50, "can0", RawCanMessage::new(0x101, vec![1])),
+            entry(60, "can1", RawCanMessage::new(0x200, vec![2])),
+            entry(200, "can0", RawCanMessage::new(0x102, vec![2])),
+        ]
+    }
+
+    /// Receives every frame until the replay runs dry, with how long after the first each came.
+    async fn drain(replay: &ReplayTransport) -> Vec<(u32, Duration)> {
+        let mut frames = Vec::new();
+        let mut first = None;
+        while let Some(TransportEvent::Frame(raw)) = replay.recv(Duration::from_secs(10)).await.unwrap() {
+            let first = *first.get_or_insert_with(Instant::now);
+            frames.push((raw.arbitration_id, first.elapsed()));
+        }
+        frames
+    }
+
+    async fn next_id(replay: &ReplayTransport) -> Option<u32> {
+        match replay.recv(Duration::from_secs(1)).await.unwrap() {
+            Some(TransportEvent::Frame(raw)) => Some(raw.arbitration_id),
+            _ => None,
+        }
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn realtime_keeps_the_recorded_gaps() {
+        let replay = ReplayTransport::new(log(), Some("can0"), ReplayMode::Realtime).unwrap();
+        assert_eq!(drain(&replay).await, [(0x100, ms(0)), (0x101, ms(50)), (0x102, ms(200))]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn accelerated_divides_the_gaps() {
+        let replay = ReplayTransport::new(log(), None, ReplayMode::Accelerated(10.0)).unwrap();
+        assert_eq!(drain(&replay).await, [(0x100, ms(0)), (0x101, ms(5)), (0x200, ms(6)), (0x102, ms(20))]);
+
+        let replay = ReplayTransport::new(log(), None, ReplayMode::Accelerated(f64::INFINITY)).unwrap();
+        assert_eq!(drain(&replay).await, [(0x100, ms(0)), (0x101, ms(0)), (0x200, ms(0)), (0x102, ms(0))]);
+
+        assert!(ReplayTransport::new(log(), None, ReplayMode::Accelerated(0.0)).is_err());
+        assert!(ReplayTransport::new(log(), None, ReplayMode::Accelerated(f64::NAN)).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stepped_waits_for_steps() {
+        let replay = ReplayTransport::new(log(), Some("can0"), ReplayMode::Stepped).unwrap();
+        let control = replay.control();
+        assert_eq!((control.len(), control.position()), (3, 0));
+        assert_eq!(replay.recv(Duration::from_secs(1)).await.unwrap(), None);
+
+        control.step(2);
+        assert_eq!(next_id(&replay).await, Some(0x100));
+        assert_eq!(next_id(&replay).await, Some(0x101));
+        assert_eq!(next_id(&replay).await, None);
+        assert_eq!(control.position(), 2);
+
+        // A step taken while `recv` waits releases it.
+        let (frame, ()) = tokio::join!(next_id(&replay), async {
+            time::sleep(ms(100)).await;
+            control.step(1);
+        });
+        assert_eq!(frame, Some(0x102));
+        assert!(control.is_finished());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn end_of_log() {
+        let replay = ReplayTransport::new(log(), Some("can1"), ReplayMode::Realtime).unwrap();
+        let control = replay.control();
+        assert!(!control.is_finished());
+        let (frames, ()) = tokio::join!(drain(&replay), control.finished());
+        assert_eq!(frames, [(0x200, ms(0))]);
+        assert!(control.is_finished());
+        // Exhausted, it only ever times out.
+        let started = Instant::now();
+        assert_eq!(replay.recv(ms(500)).await.unwrap(), None);
+        assert_eq!(started.elapsed(), ms(500));
+
+        let empty = ReplayTransport::new(log(), Some("can2"), ReplayMode::Realtime).unwrap();
+        assert!(empty.control().is_empty() && empty.control().is_finished());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn filters_behave_like_socketcan() {
+        let extended = RawCanMessage { is_extended_id: true, ..RawCanMessage::new(0x1000_0100, vec![]) };
+        let replay = ReplayTransport::new(vec![entry(0, "can0", extended), entry(1, "can0", RawCanMessage::new(0x100, vec![]))], None, ReplayMode::Realtime).unwrap();
+        replay.set_filters(&[CanIdFilter::exact(0x100)]).unwrap();
+        assert_eq!(drain(&replay).await, [(0x100, ms(0))]);
+        assert_eq!(replay.control().position(), 2);
+    }
+
+    #[tokio::test]
+    async fn recorded_sessions_replay() {
+        let path = std::env::temp_dir().join(format!("havendrive-replay-{}.log", std::process::id()));
+        let frames = [
+            RawCanMessage::new(0x123, vec![1, 2, 3]),
+            RawCanMessage { is_extended_id: true, ..RawCanMessage::new(0x1ABC_DEF0, vec![]) },
+            RawCanMessage { is_fd: true, bitrate_switch: true, ..RawCanMessage::new(0x7FF, vec![0xAA; 12]) },
+        ];
+        let bus = VirtualBus::new();
+        let peer = bus.connect();
+        {
+            let recording = RecordingTransport::new(bus.connect(), Arc::new(CandumpWriter::create(&path).unwrap()));
+            for raw in &frames[..2] {
+                peer.send(raw).await.unwrap();
+                assert!(recording.recv(ms(100)).await.unwrap().is_some());
+            }
+            recording.send(&frames[2]).await.unwrap();
+        }
+
+        let replay = ReplayTransport::from_log(&path, None, ReplayMode::Accelerated(f64::INFINITY)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.control().len(), frames.len());
+        for raw in &frames {
+            let Some(TransportEvent::Frame(replayed)) = replay.recv(ms(100)).await.unwrap() else {
+                panic!("no frame replayed");
+            };
+            assert!(replayed.same_frame(raw), "{:?} != {:?}", replayed, raw);
+            assert!(replayed.timestamp.is_some());
+        }
+        assert!(replay.control().is_finished());
+    }
+}
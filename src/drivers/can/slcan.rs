@@ -0,0 +1,746 @@
+//! Serial-line CAN: the LAWICEL ASCII protocol spoken by CANable, USBtin and
+//! similar USB adapters when no `slcand` has been attached to them.
+//!
+//! Commands and frames are lines ending in `\r`, and the adapter answers each
+//! command with `\r`, or `BEL` if it refused it. A classic frame is
+//! `t<id:3><len><data>`, or `T<id:8>...` with an extended id; FD frames use
+//! `d`/`D`, or `b`/`B` with bitrate switching, and a length code up to `F`.
+//! With timestamps on, received frames end in four hex digits of milliseconds
+//! that wrap every minute.
+use std::ffi::CString;
+use std::fmt::Write as _;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use super::bus_events::{
+    BusErrorFrame, BusErrorKind, CAN_ERR_CRTL_ACTIVE, CAN_ERR_CRTL_RX_PASSIVE, CAN_ERR_CRTL_RX_WARNING,
+    CAN_ERR_CRTL_TX_PASSIVE, CAN_ERR_CRTL_TX_WARNING,
+};
+use super::messages::{CanIdFilter, RawCanMessage, RxTimestamp, TimestampSource, CANFD_VALID_DLENS, CAN_MAX_DLEN};
+use super::transport::{CanTransport, TransportEvent, TransportFuture};
+
+/// How long the adapter gets to answer a command while opening.
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long to discard leftovers of an earlier session before configuring.
+const DRAIN_TIME: Duration = Duration::from_millis(50);
+
+const BEL: u8 = 0x07;
+const TIMESTAMP_WRAP_MS: u64 = 60_000;
+
+/// Bitrates of the `S0`..`S8` commands.
+const SLCAN_BITRATES: [u32; 9] = [10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000];
+
+// Status flags, the reply to `F`.
+const STATUS_RX_FIFO_FULL: u8 = 0x01;
+const STATUS_TX_FIFO_FULL: u8 = 0x02;
+const STATUS_ERROR_WARNING: u8 = 0x04;
+const STATUS_DATA_OVERRUN: u8 = 0x08;
+const STATUS_ERROR_PASSIVE: u8 = 0x20;
+const STATUS_ARBITRATION_LOST: u8 = 0x40;
+const STATUS_BUS_ERROR: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlcanConfig {
+    /// Nominal bitrate; one of the nine the protocol can select.
+    pub bitrate: u32,
+    /// Have the adapter stamp received frames. Adapters that refuse get userspace stamps.
+    pub timestamps: bool,
+    /// Open the channel without acknowledging or sending frames.
+    pub listen_only: bool,
+    /// How often to ask for the status flags, which become `TransportEvent::Error`s.
+    pub status_interval: Option<Duration>,
+}
+
+impl Default for SlcanConfig {
+    fn default() -> Self {
+        Self { bitrate: 1_000_000, timestamps: true, listen_only: false, status_interval: Some(Duration::from_secs(1)) }
+    }
+}
+
+/// Unwraps the adapter's minute-long millisecond counter into time since the first frame.
+#[derive(Default)]
+struct SlcanClock {
+    last: Option<u64>,
+    elapsed: u64,
+}
+
+impl SlcanClock {
+    fn advance(&mut self, stamp: u16) -> Duration {
+        let stamp = stamp as u64 % TIMESTAMP_WRAP_MS;
+        if let Some(last) = self.last {
+            self.elapsed += (stamp + TIMESTAMP_WRAP_MS - last) % TIMESTAMP_WRAP_MS;
+        }
+        self.last = Some(stamp);
+        Duration::from_millis(self.elapsed)
+    }
+}
+
+struct RxState {
+    /// Bytes read but not yet parsed, up to an incomplete last line.
+    buf: Vec<u8>,
+    clock: SlcanClock,
+    /// The flags of the last status reply.
+    status: u8,
+    /// An `F` is out and its reply not seen yet.
+    status_pending: bool,
+    next_status_poll: Option<Instant>,
+}
+
+/// An opened and configured adapter.
+struct Port {
+    fd: AsyncFd<OwnedFd>,
+    /// Keeps frames and commands from interleaving on the line.
+    write_lock: tokio::sync::Mutex<()>,
+    rx: Mutex<RxState>,
+    timestamps: bool,
+    status_interval: Option<Duration>,
+}
+
+impl Port {
+    /// Opens the tty and configures the adapter. The handshake blocks for as long
+    /// as an unresponsive adapter takes to time out, so it runs on a blocking thread.
+    async fn open(path: &str, config: &SlcanConfig) -> Result<Self> {
+        let fd = open_tty(path)?;
+        Self::configure(fd, path.to_string(), *config).await
+    }
+
+    async fn configure(fd: OwnedFd, path: String, config: SlcanConfig) -> Result<Self> {
+        let (fd, timestamps) = tokio::task::spawn_blocking(move || handshake(&fd, &path, &config).map(|timestamps| (fd, timestamps))).await??;
+        let rx = RxState {
+            buf: Vec::new(),
+            clock: SlcanClock::default(),
+            status: 0,
+            status_pending: false,
+            next_status_poll: config.status_interval.map(|interval| Instant::now() + interval),
+        };
+        Ok(Self {
+            fd: AsyncFd::try_new(fd)?,
+            write_lock: tokio::sync::Mutex::new(()),
+            rx: Mutex::new(rx),
+            timestamps,
+            status_interval: config.status_interval,
+        })
+    }
+
+    async fn write_all(&self, bytes: &[u8]) -> io::Result<()> {
+        let _guard = self.write_lock.lock().await;
+        let mut written = 0;
+        while written < bytes.len() {
+            written += self.fd.async_io(Interest::WRITABLE, |fd| write_some(fd, &bytes[written..])).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_more(&self) -> io::Result<()> {
+        let mut chunk = [0u8; 256];
+        let n = self.fd.async_io(Interest::READABLE, |fd| read_some(fd, &mut chunk)).await?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "adapter hung up"));
+        }
+        self.rx.lock().unwrap().buf.extend_from_slice(&chunk[..n]);
+        Ok(())
+    }
+
+    /// Sends `F` if a status poll is due, and says when the next one is.
+    async fn poll_status(&self) -> io::Result<Option<Instant>> {
+        let Some(interval) = self.status_interval else { return Ok(None) };
+        let send = {
+            let mut rx = self.rx.lock().unwrap();
+            let Some(next) = rx.next_status_poll else { return Ok(None) };
+            if Instant::now() < next {
+                return Ok(Some(next));
+            }
+            rx.next_status_poll = Some(Instant::now() + interval);
+            // An adapter that never answered the last poll gets no more.
+            !mem::replace(&mut rx.status_pending, true)
+        };
+        if send {
+            self.write_all(b"F\r").await?;
+        }
+        Ok(self.rx.lock().unwrap().next_status_poll)
+    }
+
+    /// Parses buffered lines up to the first event that passes `filters`.
+    fn take_event(&self, channel: &str, filters: &Option<Vec<CanIdFilter>>) -> Option<TransportEvent> {
+        let mut rx = self.rx.lock().unwrap();
+        while let Some(end) = rx.buf.iter().position(|b| *b == b'\r' || *b == BEL) {
+            let line: Vec<u8> = rx.buf.drain(..=end).collect();
+            if line[end] == BEL {
+                if mem::take(&mut rx.status_pending) {
+                    log::info!("{} does not report status flags", channel);
+                    rx.next_status_poll = None;
+                } else {
+                    log::debug!("{} refused a frame", channel);
+                }
+                continue;
+            }
+            let Ok(line) = std::str::from_utf8(&line[..end]) else {
+                log::debug!("Ignoring a garbled line from {}", channel);
+                continue;
+            };
+            match line.as_bytes().first() {
+                Some(b't' | b'T' | b'r' | b'R' | b'd' | b'D' | b'b' | b'B') => match SlcanTransport::decode_frame(line) {
+                    Ok((mut raw, stamp)) => {
+                        if !filters.as_ref().is_none_or(|filters| filters.iter().any(|f| f.accepts_frame(&raw))) {
+                            continue;
+                        }
+                        raw.timestamp = Some(match stamp {
+                            Some(stamp) if self.timestamps => RxTimestamp { time: rx.clock.advance(stamp), source: TimestampSource::Hardware },
+                            _ => RxTimestamp::now(),
+                        });
+                        return Some(TransportEvent::Frame(raw));
+                    }
+                    Err(e) => log::debug!("Ignoring a bad frame from {}: {}", channel, e),
+                },
+                Some(b'F') => {
+                    rx.status_pending = false;
+                    let Some(flags) = line.get(1..3).and_then(|f| u8::from_str_radix(f, 16).ok()) else {
+                        log::debug!("Ignoring a bad status reply from {}: {:?}", channel, line);
+                        continue;
+                    };
+                    let previous = mem::replace(&mut rx.status, flags);
+                    if let Some(frame) = status_to_error_frame(flags, previous) {
+                        return Some(TransportEvent::Error(frame));
+                    }
+                }
+                // `z`/`Z` acknowledge a transmitted frame, an empty line a command.
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Port {
+    fn drop(&mut self) {
+        // Best effort: leave the adapter closed for whoever opens it next.
+        let _ = write_some(self.fd.get_ref(), b"C\r");
+    }
+}
+
+/// The status flags as an error frame, or `None` if there is nothing to report.
+/// The flags are cleared by reading them, except for the warning and passive
+/// states, so their going away is reported as the controller turning active.
+fn status_to_error_frame(flags: u8, previous: u8) -> Option<BusErrorFrame> {
+    let mut kinds = Vec::new();
+    if flags & (STATUS_RX_FIFO_FULL | STATUS_DATA_OVERRUN) != 0 {
+        kinds.push(BusErrorKind::RxOverflow);
+    }
+    if flags & STATUS_TX_FIFO_FULL != 0 {
+        kinds.push(BusErrorKind::TxOverflow);
+    }
+    if flags & STATUS_ARBITRATION_LOST != 0 {
+        kinds.push(BusErrorKind::ArbitrationLost { bit: None });
+    }
+    if flags & STATUS_BUS_ERROR != 0 {
+        kinds.push(BusErrorKind::BusError);
+    }
+    let states = STATUS_ERROR_WARNING | STATUS_ERROR_PASSIVE;
+    let controller = if flags & STATUS_ERROR_PASSIVE != 0 {
+        CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE
+    } else if flags & STATUS_ERROR_WARNING != 0 {
+        CAN_ERR_CRTL_RX_WARNING | CAN_ERR_CRTL_TX_WARNING
+    } else if previous & states != 0 {
+        CAN_ERR_CRTL_ACTIVE
+    } else {
+        0
+    };
+    let state_changed = flags & states != previous & states;
+    (!kinds.is_empty() || state_changed).then_some(BusErrorFrame { kinds, controller, counters: None })
+}
+
+/// The code of `bitrate` in the `S` command.
+fn bitrate_code(bitrate: u32) -> Result<usize> {
+    SLCAN_BITRATES.iter().position(|b| *b == bitrate).ok_or_else(|| anyhow!("SLCAN cannot run at {} bit/s, expected one of {:?}", bitrate, SLCAN_BITRATES))
+}
+
+/// Sets up the adapter on `fd` and opens its channel. Returns whether the adapter
+/// stamps received frames. Blocks for up to a few seconds.
+fn handshake(fd: &OwnedFd, path: &str, config: &SlcanConfig) -> Result<bool> {
+    let code = bitrate_code(config.bitrate)?;
+    // Finish whatever half-written command is pending and close the channel in
+    // case an earlier session left it open; either may be refused.
+    write_blocking(fd, b"\r\r\rC\r")?;
+    drain(fd)?;
+    command(fd, &format!("S{}\r", code)).with_context(|| format!("Failed to set the bitrate of {}", path))?;
+    let timestamps = match command(fd, if config.timestamps { "Z1\r" } else { "Z0\r" }) {
+        Ok(()) => config.timestamps,
+        Err(e) => {
+            log::warn!("{} does not stamp frames, stamping in userspace: {}", path, e);
+            false
+        }
+    };
+    command(fd, if config.listen_only { "L\r" } else { "O\r" }).with_context(|| format!("Failed to open the channel of {}", path))?;
+    Ok(timestamps)
+}
+
+fn open_tty(path: &str) -> Result<OwnedFd> {
+    let c_path = CString::new(path)?;
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(anyhow!("Failed to open {}: {}", path, io::Error::last_os_error()));
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut tio: libc::termios = unsafe { mem::zeroed() };
+    unsafe {
+        if libc::tcgetattr(fd.as_raw_fd(), &mut tio) < 0 {
+            return Err(anyhow!("{} is not a tty: {}", path, io::Error::last_os_error()));
+        }
+        libc::cfmakeraw(&mut tio);
+        // USB adapters ignore the line speed, serial ones mostly run at this one.
+        libc::cfsetspeed(&mut tio, libc::B115200);
+        // With no minimum a read of an idle line returns 0, which looks like a hangup.
+        tio.c_cc[libc::VMIN] = 1;
+        tio.c_cc[libc::VTIME] = 0;
+        if libc::tcsetattr(fd.as_raw_fd(), libc::TCSANOW, &tio) < 0 {
+            return Err(anyhow!("Failed to configure {}: {}", path, io::Error::last_os_error()));
+        }
+        libc::tcflush(fd.as_raw_fd(), libc::TCIOFLUSH);
+    }
+    Ok(fd)
+}
+
+fn write_some(fd: &OwnedFd, bytes: &[u8]) -> io::Result<usize> {
+    let n = unsafe { libc::write(fd.as_raw_fd(), bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+fn read_some(fd: &OwnedFd, buf: &mut [u8]) -> io::Result<usize> {
+    let n = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// Waits up to `timeout` for `events` on a descriptor that is not yet on the reactor.
+fn wait_for(fd: &OwnedFd, events: libc::c_short, timeout: Duration) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd { fd: fd.as_raw_fd(), events, revents: 0 };
+    let n = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n > 0)
+}
+
+fn write_blocking(fd: &OwnedFd, mut bytes: &[u8]) -> Result<()> {
+    while !bytes.is_empty() {
+        if !wait_for(fd, libc::POLLOUT, COMMAND_TIMEOUT)? {
+            return Err(anyhow!("Timed out writing to the adapter"));
+        }
+        match write_some(fd, bytes) {
+            Ok(n) => bytes = &bytes[n..],
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+fn drain(fd: &OwnedFd) -> Result<()> {
+    let mut buf = [0u8; 256];
+    while wait_for(fd, libc::POLLIN, DRAIN_TIME)? {
+        match read_some(fd, &mut buf) {
+            Ok(0) => return Err(anyhow!("Adapter hung up")),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Sends `cmd` and waits for the adapter to accept it. Reads a byte at a time so
+/// that nothing after the reply is consumed.
+fn command(fd: &OwnedFd, cmd: &str) -> Result<()> {
+    write_blocking(fd, cmd.as_bytes())?;
+    let deadline = Instant::now() + COMMAND_TIMEOUT;
+    let mut byte = [0u8; 1];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || !wait_for(fd, libc::POLLIN, remaining)? {
+            return Err(anyhow!("No reply to {:?}", cmd.trim_end()));
+        }
+        match read_some(fd, &mut byte) {
+            Ok(0) => return Err(anyhow!("Adapter hung up")),
+            Ok(_) if byte[0] == b'\r' => return Ok(()),
+            Ok(_) if byte[0] == BEL => return Err(anyhow!("Adapter refused {:?}", cmd.trim_end())),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// `CanTransport` over an SLCAN adapter's tty, e.g. `/dev/ttyACM0`.
+///
+/// Filters are applied in software. Frames stamped by the adapter carry
+/// `Hardware` stamps counting from the first frame received.
+pub struct SlcanTransport {
+    path: String,
+    config: SlcanConfig,
+    /// Swapped out wholesale by `reopen`, as for `SocketCanTransport`.
+    port: tokio::sync::Mutex<PortSlot>,
+    filters: Mutex<Option<Vec<CanIdFilter>>>,
+}
+
+enum PortSlot {
+    /// The handshake started by `open_with`, not waited for yet.
+    Opening(JoinHandle<Result<Port>>),
+    Open(Arc<Port>),
+    /// The handshake failed; `reopen` tries again.
+    Failed,
+}
+
+impl SlcanTransport {
+    /// Opens the tty right away, so a missing adapter fails here, and configures
+    /// the adapter in the background. `send` and `recv` wait for that and fail if
+    /// the adapter did not take the configuration.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn open_with(path: &str, config: SlcanConfig) -> Result<Self> {
+        bitrate_code(config.bitrate)?;
+        let fd = open_tty(path)?;
+        let opening = tokio::spawn(Port::configure(fd, path.to_string(), config));
+        Ok(Self { path: path.to_string(), config, port: tokio::sync::Mutex::new(PortSlot::Opening(opening)), filters: Mutex::new(None) })
+    }
+
+    async fn port(&self) -> Result<Arc<Port>> {
+        let mut slot = self.port.lock().await;
+        if let PortSlot::Opening(opening) = &mut *slot {
+            match opening.await.map_err(anyhow::Error::from).and_then(|port| port) {
+                Ok(port) => *slot = PortSlot::Open(Arc::new(port)),
+                Err(e) => {
+                    *slot = PortSlot::Failed;
+                    return Err(e.context(format!("Failed to open {}", self.path)));
+                }
+            }
+        }
+        match &*slot {
+            PortSlot::Open(port) => Ok(port.clone()),
+            _ => Err(anyhow!("{} is not open", self.path)),
+        }
+    }
+
+    /// The line sending `raw`, including its `\r`.
+    pub fn encode_frame(raw: &RawCanMessage) -> Result<String> {
+        raw.validate()?;
+        let kind = match (raw.is_fd, raw.bitrate_switch, raw.is_extended_id) {
+            (false, _, false) => 't',
+            (false, _, true) => 'T',
+            (true, false, false) => 'd',
+            (true, false, true) => 'D',
+            (true, true, false) => 'b',
+            (true, true, true) => 'B',
+        };
+        let dlc = if raw.is_fd { CANFD_VALID_DLENS.iter().position(|len| *len == raw.data.len()).unwrap() } else { raw.data.len() };
+        let mut line = String::with_capacity(raw.data.len() * 2 + 12);
+        line.push(kind);
+        if raw.is_extended_id {
+            write!(line, "{:08X}", raw.arbitration_id).unwrap();
+        } else {
+            write!(line, "{:03X}", raw.arbitration_id).unwrap();
+        }
+        write!(line, "{:X}", dlc).unwrap();
+        raw.data.iter().for_each(|b| write!(line, "{:02X}", b).unwrap());
+        line.push('\r');
+        Ok(line)
+    }
+
+    /// Parses a received frame line, without its `\r`, into the frame and the
+    /// adapter's timestamp if it sent one. Remote frames come out with empty data.
+    pub fn decode_frame(line: &str) -> Result<(RawCanMessage, Option<u16>)> {
+        let bad = || anyhow!("Bad SLCAN frame {:?}", line);
+        // The fields are sliced by byte offset.
+        if !line.is_ascii() {
+            return Err(bad());
+        }
+        let (is_extended_id, is_fd, bitrate_switch, remote) = match line.as_bytes().first() {
+            Some(b't') => (false, false, false, false),
+            Some(b'T') => (true, false, false, false),
+            Some(b'r') => (false, false, false, true),
+            Some(b'R') => (true, false, false, true),
+            Some(b'd') => (false, true, false, false),
+            Some(b'D') => (true, true, false, false),
+            Some(b'b') => (false, true, true, false),
+            Some(b'B') => (true, true, true, false),
+            _ => return Err(bad()),
+        };
+        let id_len = if is_extended_id { 8 } else { 3 };
+        let arbitration_id = line.get(1..1 + id_len).and_then(|id| u32::from_str_radix(id, 16).ok()).ok_or_else(bad)?;
+        let dlc = line.get(1 + id_len..2 + id_len).and_then(|d| usize::from_str_radix(d, 16).ok()).ok_or_else(bad)?;
+        let len = match (remote, is_fd) {
+            (true, _) => 0,
+            (false, true) => CANFD_VALID_DLENS[dlc],
+            (false, false) => dlc.min(CAN_MAX_DLEN),
+        };
+        let rest = &line[2 + id_len..];
+        let data_hex = rest.get(..len * 2).ok_or_else(bad)?;
+        let data = (0..len).map(|i| u8::from_str_radix(&data_hex[i * 2..i * 2 + 2], 16)).collect::<Result<Vec<u8>, _>>().map_err(|_| bad())?;
+        let stamp = match &rest[len * 2..] {
+            "" => None,
+            stamp if stamp.len() == 4 => Some(u16::from_str_radix(stamp, 16).map_err(|_| bad())?),
+            _ => return Err(bad()),
+        };
+        let raw = RawCanMessage { arbitration_id, data, is_extended_id, is_fd, bitrate_switch, ..Default::default() };
+        Ok((raw, stamp))
+    }
+}
+
+impl CanTransport for SlcanTransport {
+    /// Opens the adapter at the tty `channel` with the default `SlcanConfig`.
+    fn open(channel: &str) -> Result<Self> {
+        Self::open_with(channel, SlcanConfig::default())
+    }
+
+    fn channel(&self) -> &str {
+        &self.path
+    }
+
+    fn send<'a>(&'a self, msg: &'a RawCanMessage) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            let line = Self::encode_frame(msg)?;
+            self.port().await?.write_all(line.as_bytes()).await.map_err(|e| anyhow!("SLCAN send on {} failed: {}", self.path, e))
+        })
+    }
+
+    fn recv(&self, timeout: Duration) -> TransportFuture<'_, Option<TransportEvent>> {
+        Box::pin(async move {
+            let port = self.port().await?;
+            let deadline = time::Instant::now() + timeout;
+            loop {
+                let filters = self.filters.lock().unwrap().clone();
+                if let Some(event) = port.take_event(&self.path, &filters) {
+                    return Ok(Some(event));
+                }
+                let next_poll = port.poll_status().await.map_err(|e| anyhow!("SLCAN status poll on {} failed: {}", self.path, e))?;
+                let wake = next_poll.map_or(deadline, |next| deadline.min(time::Instant::from_std(next)));
+                match time::timeout_at(wake, port.read_more()).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => return Err(anyhow!("SLCAN receive on {} failed: {}", self.path, e)),
+                    Err(_) if wake >= deadline => return Ok(None),
+                    Err(_) => {}
+                }
+            }
+        })
+    }
+
+    fn set_filters(&self, filters: &[CanIdFilter]) -> Result<()> {
+        *self.filters.lock().unwrap() = Some(filters.to_vec());
+        Ok(())
+    }
+
+    fn can_reopen(&self) -> bool {
+        true
+    }
+
+    /// Opens the tty afresh, e.g. after the adapter was unplugged and came back.
+    fn reopen(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async move {
+            let port = Port::open(&self.path, &self.config).await?;
+            *self.port.lock().await = PortSlot::Open(Arc::new(port));
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn decode(line: &str) -> (RawCanMessage, Option<u16>) {
+        SlcanTransport::decode_frame(line).unwrap()
+    }
+
+    #[test]
+    fn classic_frames() {
+        let raw = RawCanMessage::new(0x123, vec![0xDE, 0xAD]);
+        assert_eq!(SlcanTransport::encode_frame(&raw).unwrap(), "t1232DEAD\r");
+        assert_eq!(decode("t1232DEAD"), (raw, None));
+
+        let raw = RawCanMessage { is_extended_id: true, ..RawCanMessage::new(0x1234_5678, vec![]) };
+        assert_eq!(SlcanTransport::encode_frame(&raw).unwrap(), "T123456780\r");
+        assert_eq!(decode("T123456780"), (raw, None));
+    }
+
+    #[test]
+    fn fd_frames() {
+        let raw = RawCanMessage { is_fd: true, bitrate_switch: true, ..RawCanMessage::new(0x7FF, (0..12).collect()) };
+        let line = SlcanTransport::encode_frame(&raw).unwrap();
+        assert_eq!(line, "b7FF9000102030405060708090A0B\r");
+        assert_eq!(decode(line.trim_end()), (raw, None));
+
+        let raw = RawCanMessage { is_fd: true, is_extended_id: true, ..RawCanMessage::new(1, vec![0; 64]) };
+        let line = SlcanTransport::encode_frame(&raw).unwrap();
+        assert!(line.starts_with("D00000001F"));
+        assert_eq!(decode(line.trim_end()), (raw, None));
+
+        let odd_length = RawCanMessage { is_fd: true, ..RawCanMessage::new(1, vec![0; 9]) };
+        assert!(SlcanTransport::encode_frame(&odd_length).is_err());
+    }
+
+    #[test]
+    fn remote_frames_and_timestamps() {
+        assert_eq!(decode("r1238"), (RawCanMessage::new(0x123, vec![]), None));
+        assert_eq!(decode("t1232BEEFEA5F"), (RawCanMessage::new(0x123, vec![0xBE, 0xEF]), Some(0xEA5F)));
+    }
+
+    #[test]
+    fn bad_lines() {
+        for line in ["", "x1230", "t12", "t123", "t1232BE", "t1232BEEF1", "t1232BEXF", "T1230", "t12320é0", "t1231AAé0", "tá230"] {
+            assert!(SlcanTransport::decode_frame(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn clock_unwraps_the_minute() {
+        let mut clock = SlcanClock::default();
+        assert_eq!(clock.advance(59_990), Duration::ZERO);
+        assert_eq!(clock.advance(10), Duration::from_millis(20));
+        assert_eq!(clock.advance(1_010), Duration::from_millis(1_020));
+        assert_eq!(clock.advance(1_010), Duration::from_millis(1_020));
+        // Out of range stamps count modulo the minute.
+        assert_eq!(clock.advance(61_010), Duration::from_millis(1_020));
+        assert_eq!(clock.advance(59_999), Duration::from_millis(1_020 + 58_989));
+    }
+
+    #[test]
+    fn status_flags() {
+        assert_eq!(status_to_error_frame(0, 0), None);
+        let frame = status_to_error_frame(STATUS_DATA_OVERRUN | STATUS_ARBITRATION_LOST, 0).unwrap();
+        assert_eq!(frame.kinds, [BusErrorKind::RxOverflow, BusErrorKind::ArbitrationLost { bit: None }]);
+        assert_eq!(frame.controller, 0);
+
+        let frame = status_to_error_frame(STATUS_ERROR_WARNING, 0).unwrap();
+        assert!(frame.kinds.is_empty());
+        assert_eq!(frame.controller, CAN_ERR_CRTL_RX_WARNING | CAN_ERR_CRTL_TX_WARNING);
+        // A state that persists is only reported again along with other flags.
+        assert_eq!(status_to_error_frame(STATUS_ERROR_WARNING, STATUS_ERROR_WARNING), None);
+        let frame = status_to_error_frame(STATUS_ERROR_PASSIVE | STATUS_BUS_ERROR, STATUS_ERROR_PASSIVE).unwrap();
+        assert_eq!(frame.kinds, [BusErrorKind::BusError]);
+        assert_eq!(frame.controller, CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE);
+
+        let frame = status_to_error_frame(STATUS_TX_FIFO_FULL, STATUS_ERROR_PASSIVE).unwrap();
+        assert_eq!(frame.kinds, [BusErrorKind::TxOverflow]);
+        assert_eq!(frame.controller, CAN_ERR_CRTL_ACTIVE);
+    }
+
+    /// A pty whose master side is answered like an adapter by a thread, which
+    /// passes on the frames sent to it and stays silent while `responsive` is off.
+    struct FakeAdapter {
+        path: String,
+        /// Writes frames for the transport to receive.
+        incoming: File,
+        sent: mpsc::Receiver<String>,
+        responsive: Arc<AtomicBool>,
+        /// Held open so the master does not see a hangup while the transport reopens the tty.
+        _slave: OwnedFd,
+    }
+
+    impl FakeAdapter {
+        fn start() -> Self {
+            let (mut master, mut slave) = (0, 0);
+            let mut name = [0 as libc::c_char; 64];
+            assert_eq!(unsafe { libc::openpty(&mut master, &mut slave, name.as_mut_ptr(), std::ptr::null(), std::ptr::null()) }, 0);
+            let master = unsafe { File::from_raw_fd(master) };
+            let path = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) }.to_str().unwrap().to_string();
+            let (sent_tx, sent) = mpsc::channel();
+            let responsive = Arc::new(AtomicBool::new(true));
+            let mut reply = master.try_clone().unwrap();
+            let incoming = master.try_clone().unwrap();
+            let answering = responsive.clone();
+            std::thread::spawn(move || {
+                for line in BufReader::new(master).split(b'\r') {
+                    let Ok(line) = line else { return };
+                    let line = String::from_utf8(line).unwrap();
+                    if !answering.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    let answer: &[u8] = match line.as_bytes().first() {
+                        None => continue,
+                        Some(b'S' | b'Z' | b'O' | b'L' | b'C') => b"\r",
+                        Some(b't' | b'T' | b'd' | b'D' | b'b' | b'B') => {
+                            let _ = sent_tx.send(line);
+                            b"z\r"
+                        }
+                        _ => &[BEL],
+                    };
+                    if reply.write_all(answer).is_err() {
+                        return;
+                    }
+                }
+            });
+            Self { path, incoming, sent, responsive, _slave: unsafe { OwnedFd::from_raw_fd(slave) } }
+        }
+    }
+
+    const CONFIG: SlcanConfig = SlcanConfig { bitrate: 500_000, timestamps: true, listen_only: false, status_interval: None };
+
+    #[tokio::test]
+    async fn talks_to_an_adapter_on_a_pty() {
+        let mut adapter = FakeAdapter::start();
+        let transport = SlcanTransport::open_with(&adapter.path, CONFIG).unwrap();
+        transport.send(&RawCanMessage::new(0x012, vec![1, 2, 3])).await.unwrap();
+        assert_eq!(adapter.sent.recv_timeout(Duration::from_secs(1)).unwrap(), "t0123010203");
+
+        transport.set_filters(&[CanIdFilter::exact(0x321)]).unwrap();
+        adapter.incoming.write_all(b"t1001AA0000\rt3212BEEF0010\r").unwrap();
+        let Some(TransportEvent::Frame(raw)) = transport.recv(Duration::from_secs(1)).await.unwrap() else {
+            panic!("no frame received");
+        };
+        assert_eq!((raw.arbitration_id, raw.data.as_slice()), (0x321, &[0xBE, 0xEF][..]));
+        // The first stamped frame starts the adapter's clock.
+        assert_eq!(raw.timestamp, Some(RxTimestamp { time: Duration::ZERO, source: TimestampSource::Hardware }));
+        assert_eq!(transport.recv(Duration::from_millis(50)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn handshakes_do_not_block_the_runtime() {
+        let adapter = FakeAdapter::start();
+        adapter.responsive.store(false, Ordering::SeqCst);
+        let started = Instant::now();
+        let transport = SlcanTransport::open_with(&adapter.path, CONFIG).unwrap();
+        assert!(started.elapsed() < COMMAND_TIMEOUT);
+        assert!(SlcanTransport::open_with(&adapter.path, SlcanConfig { bitrate: 42, ..CONFIG }).is_err());
+
+        // This single-threaded runtime keeps running timers while the handshake times out.
+        let ticks = async {
+            let mut ticks = 0;
+            loop {
+                time::sleep(Duration::from_millis(10)).await;
+                ticks += 1;
+                if ticks == 10 {
+                    return ticks;
+                }
+            }
+        };
+        tokio::select! {
+            _ = transport.recv(Duration::from_secs(5)) => panic!("the handshake finished first"),
+            ticks = ticks => assert_eq!(ticks, 10),
+        }
+        assert!(transport.recv(Duration::from_secs(5)).await.is_err());
+        assert!(transport.send(&RawCanMessage::new(1, vec![])).await.is_err());
+
+        adapter.responsive.store(true, Ordering::SeqCst);
+        transport.reopen().await.unwrap();
+        transport.send(&RawCanMessage::new(1, vec![])).await.unwrap();
+        assert_eq!(adapter.sent.recv_timeout(Duration::from_secs(1)).unwrap(), "t0010");
+    }
+}
@@ -0,0 +1,275 @@
+use std::io;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use socketcan::id::FdFlags;
+use socketcan::{
+    CanAnyFrame, CanFdFrame, CanFdSocket, CanFilter, CanFrame, EmbeddedFrame, ExtendedId, Id, Socket, SocketOptions,
+    StandardId,
+};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::time;
+
+use super::bcm::BcmTask;
+use super::bus_events::{BusErrorFrame, CAN_ERR_MASK_ALL};
+use super::messages::{CanIdFilter, RawCanMessage, RxTimestamp, TimestampSource};
+use super::periodic::PeriodicTx;
+use super::transport::{CanTransport, TransportEvent, TransportFuture};
+
+/// How long to back off when the interface tx queue is full.
+const TX_QUEUE_FULL_BACKOFF: Duration = Duration::from_micros(100);
+
+/// Hardware receive stamps where the adapter has them, kernel stamps always.
+const TIMESTAMPING_FLAGS: libc::c_uint = libc::SOF_TIMESTAMPING_RX_HARDWARE
+    | libc::SOF_TIMESTAMPING_RAW_HARDWARE
+    | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+    | libc::SOF_TIMESTAMPING_SOFTWARE;
+
+/// `CanTransport` over a Linux SocketCAN raw socket.
+///
+/// The socket is non-blocking and registered with the tokio reactor, so reads
+/// and writes wake on readiness and never wait on each other. It is opened in FD
+/// mode, so it carries both classic and FD frames; sending an FD frame still
+/// requires an FD-capable interface (MTU 72). Error frames of every class are
+/// enabled and come back from `recv` as `TransportEvent::Error`.
+///
+/// Received frames carry a `SO_TIMESTAMPING` stamp: the adapter's if it stamps
+/// in hardware, otherwise the kernel's. If the socket refuses timestamping they
+/// are stamped in userspace instead.
+pub struct SocketCanTransport {
+    channel: String,
+    /// Swapped out wholesale by `reopen`; readers clone the `Arc` and never hold the lock across I/O.
+    socket: RwLock<Arc<AsyncFd<CanFdSocket>>>,
+    /// The filters last set, so a reopened socket gets them back.
+    filters: Mutex<Option<Vec<CanIdFilter>>>,
+}
+
+impl SocketCanTransport {
+    fn open_socket(channel: &str) -> Result<AsyncFd<CanFdSocket>> {
+        let socket = CanFdSocket::open(channel).map_err(|e| anyhow!("Failed to open CAN socket on {}: {}", channel, e))?;
+        socket.set_nonblocking(true)?;
+        socket.set_error_filter(CAN_ERR_MASK_ALL)?;
+        if let Err(e) = Self::enable_timestamping(&socket) {
+            log::warn!("No kernel receive timestamps on {}: {}", channel, e);
+        }
+        // Flush bus
+        while socket.read_frame().is_ok() {}
+        Ok(AsyncFd::try_new(socket)?)
+    }
+
+    fn enable_timestamping(socket: &CanFdSocket) -> io::Result<()> {
+        let flags = TIMESTAMPING_FLAGS;
+        let res = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_TIMESTAMPING,
+                &flags as *const _ as *const libc::c_void,
+                mem::size_of_val(&flags) as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Reads one frame with `recvmsg`, picking its receive stamp out of the control messages.
+    fn read_frame_timestamped(socket: &CanFdSocket) -> io::Result<(CanAnyFrame, Option<RxTimestamp>)> {
+        let mut frame: libc::canfd_frame = unsafe { mem::zeroed() };
+        // u64 keeps the control buffer aligned for cmsghdr.
+        let mut control = [0u64; 16];
+        let mut iov = libc::iovec { iov_base: &mut frame as *mut _ as *mut libc::c_void, iov_len: mem::size_of_val(&frame) };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let n = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let frame = match n as usize {
+            libc::CAN_MTU => {
+                // A classic frame is a prefix of the FD layout.
+                let classic: libc::can_frame = unsafe { std::ptr::read(&frame as *const _ as *const libc::can_frame) };
+                CanAnyFrame::from(classic)
+            }
+            libc::CANFD_MTU => CanAnyFrame::from(frame),
+            n => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected CAN frame size {}", n))),
+        };
+
+        let mut timestamp = None;
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPING {
+                    // [software, deprecated, raw hardware]
+                    let stamps: [libc::timespec; 3] = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const _);
+                    timestamp = Self::pick_timestamp(&stamps);
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        Ok((frame, timestamp))
+    }
+
+    fn pick_timestamp(stamps: &[libc::timespec; 3]) -> Option<RxTimestamp> {
+        let duration = |ts: &libc::timespec| {
+            (ts.tv_sec != 0 || ts.tv_nsec != 0).then(|| Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+        };
+        if let Some(time) = duration(&stamps[2]) {
+            Some(RxTimestamp { time, source: TimestampSource::Hardware })
+        } else {
+            duration(&stamps[0]).map(|time| RxTimestamp { time, source: TimestampSource::Kernel })
+        }
+    }
+
+    fn socket(&self) -> Arc<AsyncFd<CanFdSocket>> {
+        self.socket.read().unwrap().clone()
+    }
+
+    fn apply_filters(socket: &CanFdSocket, filters: &[CanIdFilter]) -> Result<()> {
+        if filters.is_empty() {
+            socket.set_filter_drop_all()?;
+            return Ok(());
+        }
+        let filters: Vec<CanFilter> = filters
+            .iter()
+            .map(|f| match *f {
+                CanIdFilter::ACCEPT_ALL => CanFilter::new(0, 0),
+                // Our filters describe 11-bit ids, so keep extended frames out.
+                CanIdFilter { id, mask } => CanFilter::new(id, mask | libc::CAN_EFF_FLAG),
+            })
+            .collect();
+        socket.set_filters(&filters)?;
+        Ok(())
+    }
+
+    pub fn raw_to_frame(raw: &RawCanMessage) -> Result<CanAnyFrame> {
+        raw.validate()?;
+        let id: Id = if raw.is_extended_id {
+            ExtendedId::new(raw.arbitration_id).ok_or(anyhow!("Invalid extended ID"))?.into()
+        } else {
+            StandardId::new(raw.arbitration_id as u16).ok_or(anyhow!("Invalid standard ID"))?.into()
+        };
+        if raw.is_fd {
+            let mut flags = FdFlags::empty();
+            flags.set(FdFlags::BRS, raw.bitrate_switch);
+            flags.set(FdFlags::ESI, raw.error_state_indicator);
+            let frame = CanFdFrame::with_flags(id, &raw.data, flags).ok_or(anyhow!("Invalid CAN FD frame data length: {}", raw.data.len()))?;
+            Ok(frame.into())
+        } else {
+            let frame = CanFrame::new(id, &raw.data).ok_or(anyhow!("Invalid CAN frame data length: {}", raw.data.len()))?;
+            Ok(frame.into())
+        }
+    }
+
+    pub fn frame_to_event(frame: &CanAnyFrame) -> TransportEvent {
+        match frame {
+            CanAnyFrame::Error(f) => TransportEvent::Error(BusErrorFrame::decode(f.error_bits(), f.data())),
+            _ => TransportEvent::Frame(Self::frame_to_raw(frame).expect("only error frames lack a raw form")),
+        }
+    }
+
+    /// Converts a received frame. Error frames have no `RawCanMessage` form and give `None`.
+    pub fn frame_to_raw(frame: &CanAnyFrame) -> Option<RawCanMessage> {
+        let (id, data) = match frame {
+            CanAnyFrame::Normal(f) => (f.id(), f.data()),
+            CanAnyFrame::Remote(f) => (f.id(), f.data()),
+            CanAnyFrame::Fd(f) => (f.id(), f.data()),
+            CanAnyFrame::Error(_) => return None,
+        };
+        let (arbitration_id, is_extended_id) = match id {
+            Id::Standard(id) => (id.as_raw() as u32, false),
+            Id::Extended(id) => (id.as_raw(), true),
+        };
+        let mut raw = RawCanMessage { arbitration_id, data: data.to_vec(), is_extended_id, ..Default::default() };
+        if let CanAnyFrame::Fd(f) = frame {
+            raw.is_fd = true;
+            raw.bitrate_switch = f.is_brs();
+            raw.error_state_indicator = f.is_esi();
+        }
+        Some(raw)
+    }
+}
+
+impl CanTransport for SocketCanTransport {
+    /// Must be called from within a tokio runtime.
+    fn open(channel: &str) -> Result<Self> {
+        let socket = Self::open_socket(channel)?;
+        Ok(Self { channel: channel.to_string(), socket: RwLock::new(Arc::new(socket)), filters: Mutex::new(None) })
+    }
+
+    fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    fn send<'a>(&'a self, msg: &'a RawCanMessage) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            let frame = Self::raw_to_frame(msg)?;
+            let socket = self.socket();
+            loop {
+                match socket.async_io(Interest::WRITABLE, |s| s.write_frame(&frame)).await {
+                    Ok(()) => return Ok(()),
+                    // SocketCAN reports a full qdisc with ENOBUFS rather than EAGAIN,
+                    // so readiness alone will not tell us when to retry.
+                    Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => time::sleep(TX_QUEUE_FULL_BACKOFF).await,
+                    Err(e) => return Err(anyhow!("CAN socket send failed: {}", e)),
+                }
+            }
+        })
+    }
+
+    fn recv(&self, timeout: Duration) -> TransportFuture<'_, Option<TransportEvent>> {
+        Box::pin(async move {
+            let socket = self.socket();
+            match time::timeout(timeout, socket.async_io(Interest::READABLE, Self::read_frame_timestamped)).await {
+                Err(_) => Ok(None),
+                Ok(Ok((frame, timestamp))) => {
+                    let mut event = Self::frame_to_event(&frame);
+                    if let TransportEvent::Frame(raw) = &mut event {
+                        raw.timestamp = Some(timestamp.unwrap_or_else(RxTimestamp::now));
+                    }
+                    Ok(Some(event))
+                }
+                Ok(Err(e)) => Err(anyhow!("CAN socket receive failed: {}", e)),
+            }
+        })
+    }
+
+    fn set_filters(&self, filters: &[CanIdFilter]) -> Result<()> {
+        *self.filters.lock().unwrap() = Some(filters.to_vec());
+        Self::apply_filters(self.socket().get_ref(), filters)
+    }
+
+    fn start_periodic(&self, msg: &RawCanMessage, period: Duration) -> Result<Option<Box<dyn PeriodicTx>>> {
+        match BcmTask::start(&self.channel, msg, period) {
+            Ok(task) => Ok(Some(Box::new(task))),
+            Err(e) => {
+                log::warn!("CAN_BCM unavailable on {}, sending 0x{:X} periodically from userspace: {}", self.channel, msg.arbitration_id, e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn can_reopen(&self) -> bool {
+        true
+    }
+
+    fn reopen(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async move {
+            let socket = Self::open_socket(&self.channel)?;
+            if let Some(filters) = &*self.filters.lock().unwrap() {
+                Self::apply_filters(socket.get_ref(), filters)?;
+            }
+            *self.socket.write().unwrap() = Arc::new(socket);
+            Ok(())
+        })
+    }
+}
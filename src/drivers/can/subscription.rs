@@ -0,0 +1,50 @@
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio_stream::Stream;
+
+use super::dispatcher::{RouteReceiver, RouteStats};
+use super::messages::CanMessageTrait;
+
+/// A stream of decoded `T` messages, optionally limited to a single node.
+///
+/// Dropping the subscription removes it from the bus.
+pub struct Subscription<T> {
+    route: RouteReceiver,
+    node_id: Option<u32>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Subscription<T> {
+    pub(crate) fn new(route: RouteReceiver, node_id: Option<u32>) -> Self {
+        Self { route, node_id, _phantom: PhantomData }
+    }
+
+    pub fn node_id(&self) -> Option<u32> {
+        self.node_id
+    }
+
+    /// Number of frames dropped because the stream was not polled fast enough.
+    pub fn lagged(&self) -> u64 {
+        self.route.lagged()
+    }
+
+    /// Number of frames that matched `T` but could not be decoded.
+    pub fn decode_errors(&self) -> u64 {
+        self.route.decode_errors()
+    }
+
+    pub fn stats(&self) -> RouteStats {
+        self.route.stats()
+    }
+}
+
+impl<T: CanMessageTrait> Stream for Subscription<T> {
+    type Item = T;
+
+    /// The route only queues frames of the subscribed node, so nothing is filtered here.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.route.poll_recv_message::<T>(cx)
+    }
+}
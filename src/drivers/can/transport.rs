@@ -0,0 +1,59 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use super::bus_events::BusErrorFrame;
+use super::messages::{CanIdFilter, RawCanMessage};
+use super::periodic::PeriodicTx;
+
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// What a transport can hand back from `recv`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportEvent {
+    Frame(RawCanMessage),
+    /// An error frame from the controller.
+    Error(BusErrorFrame),
+}
+
+/// A link that carries raw CAN frames for a `CanSimple`.
+///
+/// Implementations are shared between the receive and transmit tasks of the
+/// connection, so both directions must work through `&self`.
+pub trait CanTransport: Send + Sync {
+    /// Opens the transport on `channel`, e.g. an interface name or a bus name.
+    fn open(channel: &str) -> Result<Self> where Self: Sized;
+
+    fn channel(&self) -> &str;
+
+    fn send<'a>(&'a self, msg: &'a RawCanMessage) -> TransportFuture<'a, ()>;
+
+    /// Waits up to `timeout` for the next frame or error frame. `Ok(None)` means the
+    /// timeout expired; an `Err` means the transport is unusable until `reopen`ed.
+    fn recv(&self, timeout: Duration) -> TransportFuture<'_, Option<TransportEvent>>;
+
+    /// Restricts `recv` to frames passing at least one of `filters`; an empty slice
+    /// blocks everything. Transports without hardware or kernel filtering ignore this.
+    fn set_filters(&self, _filters: &[CanIdFilter]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Starts sending `msg` now and every `period` after, timed by the transport
+    /// itself. `Ok(None)` means it cannot, and the caller keeps time instead.
+    fn start_periodic(&self, _msg: &RawCanMessage, _period: Duration) -> Result<Option<Box<dyn PeriodicTx>>> {
+        Ok(None)
+    }
+
+    /// Whether `reopen` is worth trying after `recv` fails.
+    fn can_reopen(&self) -> bool {
+        false
+    }
+
+    /// Tries to bring a failed transport back, keeping its filters. Slow steps,
+    /// such as handshakes with an adapter, must not block the runtime.
+    fn reopen(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async move { Err(anyhow!("{} cannot be reopened", self.channel())) })
+    }
+}
@@ -0,0 +1,387 @@
+//! Scheduling of outgoing frames.
+//!
+//! Every frame a `CanSimple` sends waits in a `TxQueue` until its transmit loop
+//! takes it. The queue hands out the highest `TxPriority` first, FIFO within a
+//! priority, and holds back frames of nodes or of a bus that are over their rate
+//! limit. `Emergency` frames skip the limits and are never turned away.
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::Notify;
+use tokio::time::{self, Instant};
+
+use super::bus_stats::wire_bits;
+use super::messages::RawCanMessage;
+
+/// Frames that may wait in the queue, not counting `Emergency` ones.
+pub const TX_QUEUE_SIZE: usize = 32;
+
+/// How far the bus limiter lets traffic run ahead of its long-term budget.
+const BUS_BURST: Duration = Duration::from_millis(10);
+
+/// Send order of a frame. Higher goes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum TxPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// Stop and e-stop commands. Skips rate limits and never waits for room.
+    Emergency,
+}
+
+impl TxPriority {
+    const ALL: [TxPriority; 4] = [TxPriority::Emergency, TxPriority::High, TxPriority::Normal, TxPriority::Low];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// What `push` does when `TX_QUEUE_SIZE` frames are already waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TxQueueFullPolicy {
+    /// Wait for room, however long it takes.
+    #[default]
+    Wait,
+    /// Wait for room up to the given time, then fail.
+    Timeout(Duration),
+    /// Fail right away.
+    Reject,
+    /// Drop the newest frame of the lowest priority below the new one's. Fails if
+    /// every queued frame has at least the new frame's priority.
+    DropLowest,
+}
+
+/// A token bucket limit: `frames_per_sec` on average, up to `burst` back to back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub frames_per_sec: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(frames_per_sec: f64, burst: u32) -> Self {
+        Self { frames_per_sec, burst }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TxStats {
+    pub queued: usize,
+    pub sent: u64,
+    /// Frames dropped by `DropLowest` to make room.
+    pub dropped: u64,
+    /// Pushes that failed because the queue was full.
+    pub rejected: u64,
+    /// Times the head of the queue had to wait for a rate limit.
+    pub rate_limited: u64,
+}
+
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: f64, now: Instant) -> Self {
+        Self { rate, burst, tokens: burst, last: now }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+
+    /// When `cost` tokens will be there; `None` if they are there now.
+    fn ready_at(&mut self, now: Instant, cost: f64) -> Option<Instant> {
+        self.refill(now);
+        let missing = cost.min(self.burst) - self.tokens;
+        (missing > 0.0).then(|| now + Duration::from_secs_f64(missing / self.rate))
+    }
+
+    /// Takes `cost` tokens, going into debt if there are not enough.
+    fn take(&mut self, now: Instant, cost: f64) {
+        self.refill(now);
+        self.tokens = (self.tokens - cost).max(-self.burst);
+    }
+}
+
+struct QueuedFrame {
+    raw: RawCanMessage,
+    node_id: Option<u32>,
+}
+
+struct TxState {
+    queues: [VecDeque<QueuedFrame>; 4],
+    closed: bool,
+    full_policy: TxQueueFullPolicy,
+    default_node_limit: Option<RateLimit>,
+    node_limits: HashMap<u32, RateLimit>,
+    node_buckets: HashMap<u32, TokenBucket>,
+    max_bus_load: Option<f64>,
+    bus_bucket: Option<TokenBucket>,
+    stats: TxStats,
+}
+
+impl TxState {
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Frames competing for the `TX_QUEUE_SIZE` slots.
+    fn limited_len(&self) -> usize {
+        self.len() - self.queues[TxPriority::Emergency.index()].len()
+    }
+
+    fn node_bucket(&mut self, node_id: u32, now: Instant) -> Option<&mut TokenBucket> {
+        let limit = self.node_limits.get(&node_id).or(self.default_node_limit.as_ref())?;
+        let (rate, burst) = (limit.frames_per_sec, limit.burst.max(1) as f64);
+        Some(self.node_buckets.entry(node_id).or_insert_with(|| TokenBucket::new(rate, burst, now)))
+    }
+
+    fn drop_lowest(&mut self, below: TxPriority) -> bool {
+        for priority in TxPriority::ALL.iter().rev().filter(|p| **p < below) {
+            if self.queues[priority.index()].pop_back().is_some() {
+                self.stats.dropped += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The first frame, by priority then age, that its limits let through now.
+    /// Otherwise the earliest time one of them might.
+    fn take_ready(&mut self, now: Instant) -> std::result::Result<(RawCanMessage, Option<u32>), Option<Instant>> {
+        let mut wake: Option<Instant> = None;
+        for priority in TxPriority::ALL {
+            for i in 0..self.queues[priority.index()].len() {
+                let (node_id, bits) = {
+                    let frame = &self.queues[priority.index()][i];
+                    (frame.node_id, wire_bits(&frame.raw))
+                };
+                // Once closed the rest of the queue is flushed without limits.
+                if priority != TxPriority::Emergency && !self.closed {
+                    let node_ready = node_id.and_then(|node_id| self.node_bucket(node_id, now)?.ready_at(now, 1.0));
+                    let bus_ready = self.bus_bucket.as_mut().and_then(|b| b.ready_at(now, bits as f64));
+                    if let Some(at) = node_ready.max(bus_ready) {
+                        wake = Some(wake.map_or(at, |w| w.min(at)));
+                        // Over the bus limit nothing else may go either.
+                        if bus_ready.is_some() {
+                            self.stats.rate_limited += 1;
+                            return Err(wake);
+                        }
+                        continue;
+                    }
+                }
+                let frame = self.queues[priority.index()].remove(i).unwrap();
+                if let Some(bucket) = node_id.and_then(|node_id| self.node_bucket(node_id, now)) {
+                    bucket.take(now, 1.0);
+                }
+                if let Some(bucket) = &mut self.bus_bucket {
+                    bucket.take(now, bits as f64);
+                }
+                self.stats.sent += 1;
+                return Ok((frame.raw, frame.node_id));
+            }
+        }
+        if wake.is_some() {
+            self.stats.rate_limited += 1;
+        }
+        Err(wake)
+    }
+}
+
+/// The transmit queue of one bus. Cheap to share behind an `Arc`.
+pub struct TxQueue {
+    bitrate: u32,
+    state: Mutex<TxState>,
+    /// Signalled when a frame is queued, a limit changes or the queue closes.
+    frame_ready: Notify,
+    /// Signalled when room is made.
+    room: Notify,
+}
+
+impl TxQueue {
+    /// A queue for a bus running at `bitrate`, which the bus load limit is relative to.
+    pub fn new(bitrate: u32) -> Self {
+        Self {
+            bitrate,
+            state: Mutex::new(TxState {
+                queues: Default::default(),
+                closed: false,
+                full_policy: TxQueueFullPolicy::default(),
+                default_node_limit: None,
+                node_limits: HashMap::new(),
+                node_buckets: HashMap::new(),
+                max_bus_load: None,
+                bus_bucket: None,
+                stats: TxStats::default(),
+            }),
+            frame_ready: Notify::new(),
+            room: Notify::new(),
+        }
+    }
+
+    pub fn set_full_policy(&self, policy: TxQueueFullPolicy) {
+        self.state.lock().unwrap().full_policy = policy;
+    }
+
+    /// Limits every node without a limit of its own. `None` removes the default.
+    pub fn set_default_node_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut state = self.state.lock().unwrap();
+        state.default_node_limit = limit;
+        state.node_buckets.clear();
+        drop(state);
+        self.frame_ready.notify_one();
+    }
+
+    pub fn set_node_rate_limit(&self, node_id: u32, limit: Option<RateLimit>) {
+        let mut state = self.state.lock().unwrap();
+        match limit {
+            Some(limit) => state.node_limits.insert(node_id, limit),
+            None => state.node_limits.remove(&node_id),
+        };
+        state.node_buckets.remove(&node_id);
+        drop(state);
+        self.frame_ready.notify_one();
+    }
+
+    /// Holds frames back while sending them would take more than `max_load` (0..=1)
+    /// of the bus, counting what is received as well as what is sent. Set through
+    /// `CanSimple::set_max_bus_load`, which makes sure every received frame is seen.
+    pub(crate) fn set_max_bus_load(&self, max_load: Option<f64>) -> Result<()> {
+        if let Some(max_load) = max_load {
+            if !(max_load > 0.0 && max_load <= 1.0) {
+                return Err(anyhow!("Maximum bus load must be in (0, 1], got {}", max_load));
+            }
+        }
+        let mut state = self.state.lock().unwrap();
+        state.max_bus_load = max_load;
+        state.bus_bucket = max_load.map(|max_load| {
+            let rate = max_load * self.bitrate as f64;
+            TokenBucket::new(rate, rate * BUS_BURST.as_secs_f64(), Instant::now())
+        });
+        drop(state);
+        self.frame_ready.notify_one();
+        Ok(())
+    }
+
+    pub fn max_bus_load(&self) -> Option<f64> {
+        self.state.lock().unwrap().max_bus_load
+    }
+
+    pub fn stats(&self) -> TxStats {
+        let state = self.state.lock().unwrap();
+        TxStats { queued: state.len(), ..state.stats }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queues `raw`, applying the full-queue policy if there is no room.
+    /// `node_id` selects the rate limit it counts against.
+    pub async fn push(&self, raw: RawCanMessage, priority: TxPriority, node_id: Option<u32>) -> Result<()> {
+        let mut deadline = None;
+        loop {
+            let room = self.room.notified();
+            tokio::pin!(room);
+            room.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.closed {
+                    return Err(anyhow!("CAN bus closed"));
+                }
+                let full = priority != TxPriority::Emergency && state.limited_len() >= TX_QUEUE_SIZE;
+                if !full {
+                    state.queues[priority.index()].push_back(QueuedFrame { raw, node_id });
+                    drop(state);
+                    self.frame_ready.notify_one();
+                    return Ok(());
+                }
+                match state.full_policy {
+                    TxQueueFullPolicy::Wait => {}
+                    TxQueueFullPolicy::Timeout(timeout) => {
+                        let deadline = *deadline.get_or_insert_with(|| Instant::now() + timeout);
+                        if Instant::now() >= deadline {
+                            state.stats.rejected += 1;
+                            return Err(anyhow!("CAN transmit queue still full after {:?}", timeout));
+                        }
+                    }
+                    TxQueueFullPolicy::Reject => {
+                        state.stats.rejected += 1;
+                        return Err(anyhow!("CAN transmit queue full"));
+                    }
+                    TxQueueFullPolicy::DropLowest => {
+                        if !state.drop_lowest(priority) {
+                            state.stats.rejected += 1;
+                            return Err(anyhow!("CAN transmit queue full of frames at {:?} priority or above", priority));
+                        }
+                        continue;
+                    }
+                }
+            }
+            match deadline {
+                Some(deadline) => {
+                    let _ = time::timeout_at(deadline, room).await;
+                }
+                None => room.await,
+            }
+        }
+    }
+
+    /// Takes the next frame to send and the node it was queued for, waiting for one
+    /// to be queued and let through by the rate limits. `None` once the queue is
+    /// closed and drained.
+    pub(crate) async fn pop(&self) -> Option<(RawCanMessage, Option<u32>)> {
+        loop {
+            let wake = {
+                let mut state = self.state.lock().unwrap();
+                match state.take_ready(Instant::now()) {
+                    Ok(frame) => {
+                        drop(state);
+                        self.room.notify_waiters();
+                        return Some(frame);
+                    }
+                    Err(_) if state.closed && state.len() == 0 => return None,
+                    Err(wake) => wake,
+                }
+            };
+            match wake {
+                Some(at) => {
+                    let _ = time::timeout_at(at, self.frame_ready.notified()).await;
+                }
+                None => self.frame_ready.notified().await,
+            }
+        }
+    }
+
+    /// Counts a received frame against the bus limit.
+    pub(crate) fn record_rx(&self, raw: &RawCanMessage) {
+        let now = Instant::now();
+        if let Some(bucket) = &mut self.state.lock().unwrap().bus_bucket {
+            bucket.take(now, wire_bits(raw) as f64);
+        }
+    }
+
+    /// Refuses new frames. What is queued is still handed out by `pop`, ignoring the rate limits.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.frame_ready.notify_one();
+        self.room.notify_waiters();
+    }
+}
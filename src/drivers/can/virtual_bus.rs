@@ -0,0 +1,219 @@
+//! In-process virtual CAN bus.
+//!
+//! Every `VirtualBusPort` attached to a `VirtualBus` sees every frame sent by the
+//! other ports, in the order the frames were sent. A port never receives its own
+//! frames, mirroring the default SocketCAN loopback behaviour.
+//!
+//! Frames are stamped in userspace as they are sent, so every receiver sees the
+//! same `RxTimestamp`.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::{mpsc, Mutex as AsyncMutex};
+use tokio::time;
+
+use super::bus_events::BusErrorFrame;
+use super::messages::{CanIdFilter, RawCanMessage, RxTimestamp};
+use super::transport::{CanTransport, TransportEvent, TransportFuture};
+
+struct Port {
+    id: u64,
+    tx: mpsc::UnboundedSender<TransportEvent>,
+    /// `None` accepts everything, like a freshly opened SocketCAN socket.
+    filters: Option<Vec<CanIdFilter>>,
+}
+
+impl Port {
+    fn accepts(&self, msg: &RawCanMessage) -> bool {
+        self.filters.as_ref().is_none_or(|filters| filters.iter().any(|f| f.accepts_frame(msg)))
+    }
+}
+
+#[derive(Default)]
+struct BusState {
+    next_port_id: u64,
+    ports: Vec<Port>,
+}
+
+#[derive(Clone)]
+pub struct VirtualBus {
+    name: String,
+    state: Arc<Mutex<BusState>>,
+}
+
+impl Default for VirtualBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualBus {
+    pub fn new() -> Self {
+        Self::with_name("virtual")
+    }
+
+    fn with_name(name: &str) -> Self {
+        Self { name: name.to_string(), state: Default::default() }
+    }
+
+    /// Returns the process-wide bus registered under `name`, creating it on first use.
+    pub fn named(name: &str) -> Self {
+        static REGISTRY: OnceLock<Mutex<HashMap<String, VirtualBus>>> = OnceLock::new();
+        let mut registry = REGISTRY.get_or_init(Default::default).lock().unwrap();
+        registry.entry(name.to_string()).or_insert_with(|| Self::with_name(name)).clone()
+    }
+
+    pub fn connect(&self) -> VirtualBusPort {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_port_id;
+        state.next_port_id += 1;
+        state.ports.push(Port { id, tx, filters: None });
+        VirtualBusPort { id, bus: self.clone(), rx: AsyncMutex::new(rx) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.state.lock().unwrap().ports.len()
+    }
+
+    fn deliver(&self, from: u64, msg: &RawCanMessage) {
+        let msg = RawCanMessage { timestamp: Some(RxTimestamp::now()), ..msg.clone() };
+        // Holding the lock for the whole fan-out keeps a single global frame order.
+        let mut state = self.state.lock().unwrap();
+        state.ports.retain(|port| port.id == from || !port.accepts(&msg) || port.tx.send(TransportEvent::Frame(msg.clone())).is_ok());
+    }
+
+    /// Delivers `frame` to every port as if each controller had reported it.
+    pub fn inject_error(&self, frame: BusErrorFrame) {
+        let mut state = self.state.lock().unwrap();
+        state.ports.retain(|port| port.tx.send(TransportEvent::Error(frame.clone())).is_ok());
+    }
+
+    fn set_filters(&self, id: u64, filters: &[CanIdFilter]) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(port) = state.ports.iter_mut().find(|port| port.id == id) {
+            port.filters = Some(filters.to_vec());
+        }
+    }
+
+    fn disconnect(&self, id: u64) {
+        self.state.lock().unwrap().ports.retain(|port| port.id != id);
+    }
+}
+
+pub struct VirtualBusPort {
+    id: u64,
+    bus: VirtualBus,
+    rx: AsyncMutex<mpsc::UnboundedReceiver<TransportEvent>>,
+}
+
+impl VirtualBusPort {
+    pub fn bus(&self) -> &VirtualBus {
+        &self.bus
+    }
+}
+
+impl CanTransport for VirtualBusPort {
+    fn open(channel: &str) -> Result<Self> {
+        Ok(VirtualBus::named(channel).connect())
+    }
+
+    fn channel(&self) -> &str {
+        self.bus.name()
+    }
+
+    fn send<'a>(&'a self, msg: &'a RawCanMessage) -> TransportFuture<'a, ()> {
+        self.bus.deliver(self.id, msg);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn recv(&self, timeout: Duration) -> TransportFuture<'_, Option<TransportEvent>> {
+        Box::pin(async move {
+            let mut rx = self.rx.lock().await;
+            // The sender half lives in the bus, which this port keeps alive, so `None` never happens.
+            Ok(time::timeout(timeout, rx.recv()).await.ok().flatten())
+        })
+    }
+
+    fn set_filters(&self, filters: &[CanIdFilter]) -> Result<()> {
+        self.bus.set_filters(self.id, filters);
+        Ok(())
+    }
+}
+
+impl Drop for VirtualBusPort {
+    fn drop(&mut self) {
+        self.bus.disconnect(self.id);
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::drivers::can::connection::CanSimple;
+    use crate::drivers::can::messages::CanMessageTrait;
+    use crate::drivers::can::odrive_msgs::{EncoderEstimatesMessage, SetPositionMessage};
+    use crate::drivers::can::subscription::Subscription;
+
+    async fn next<T: CanMessageTrait>(sub: &mut Subscription<T>) -> Option<T> {
+        time::timeout(Duration::from_millis(200), sub.next()).await.ok().flatten()
+    }
+
+    #[tokio::test]
+    async fn two_connections_exchange_odrive_messages_in_order() {
+        let bus = VirtualBus::new();
+        let host = CanSimple::with_virtual_bus(&bus);
+        let axis = CanSimple::with_virtual_bus(&bus);
+        let mut commands = axis.subscribe_node::<SetPositionMessage>(3);
+        let mut own_commands = host.subscribe::<SetPositionMessage>();
+        let mut estimates = host.subscribe_node::<EncoderEstimatesMessage>(3);
+
+        for i in 0..10 {
+            host.send(SetPositionMessage::new(3, i as f32, 0, 0)).await.unwrap();
+            // Another node's commands do not reach the subscription.
+            host.send(SetPositionMessage::new(4, -1.0, 0, 0)).await.unwrap();
+        }
+        for i in 0..10 {
+            let command = next(&mut commands).await.unwrap();
+            assert_eq!((command.node_id(), command.input_position), (3, i as f32));
+            assert!(command.timestamp().is_some());
+
+            let mut reply = EncoderEstimatesMessage::new(3);
+            reply.pos_estimate = command.input_position;
+            axis.send(reply).await.unwrap();
+        }
+        for i in 0..10 {
+            assert_eq!(next(&mut estimates).await.unwrap().pos_estimate, i as f32);
+        }
+        assert!(next(&mut commands).await.is_none());
+        assert!(next(&mut own_commands).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn filters_behave_like_socketcan() {
+        let bus = VirtualBus::new();
+        let (sender, receiver) = (bus.connect(), bus.connect());
+        receiver.set_filters(&[CanIdFilter::exact(0x123)]).unwrap();
+        let extended = RawCanMessage { is_extended_id: true, ..RawCanMessage::new(0x1000_0123, vec![]) };
+        sender.send(&extended).await.unwrap();
+        sender.send(&RawCanMessage::new(0x124, vec![])).await.unwrap();
+        sender.send(&RawCanMessage::new(0x123, vec![1])).await.unwrap();
+        let Some(TransportEvent::Frame(raw)) = receiver.recv(Duration::from_millis(100)).await.unwrap() else {
+            panic!("no frame received");
+        };
+        assert!(raw.same_frame(&RawCanMessage::new(0x123, vec![1])));
+        assert_eq!(receiver.recv(Duration::from_millis(10)).await.unwrap(), None);
+
+        receiver.set_filters(&[CanIdFilter::ACCEPT_ALL]).unwrap();
+        sender.send(&extended).await.unwrap();
+        assert!(matches!(receiver.recv(Duration::from_millis(100)).await.unwrap(), Some(TransportEvent::Frame(raw)) if raw.same_frame(&extended)));
+    }
+}
@@ -1 +1,3 @@
+extern crate self as havendrive;
+
 pub mod drivers;
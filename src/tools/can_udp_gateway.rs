@@ -0,0 +1,131 @@
+extern crate havendrive;
+
+use anyhow::Result;
+use clap::Parser;
+
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+#[cfg(target_os = "linux")]
+use tokio::time::{self, Duration};
+
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::cannelloni::CannelloniTransport;
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::slcan::SlcanTransport;
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::socketcan_transport::SocketCanTransport;
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::transport::{CanTransport, TransportEvent};
+
+#[cfg(target_os = "linux")]
+const RECV_TIMEOUT: Duration = Duration::from_secs(1);
+#[cfg(target_os = "linux")]
+const REOPEN_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Parser, Debug)]
+#[command(about = "Bridge a local CAN bus to a cannelloni peer over UDP")]
+struct Args {
+    /// SocketCAN interface, or a tty with --slcan.
+    #[arg(short = 'i', long, default_value = "can0")]
+    interface: String,
+
+    /// The interface is the tty of an SLCAN adapter.
+    #[arg(long)]
+    slcan: bool,
+
+    #[arg(short = 'l', long, default_value = "0.0.0.0:20000")]
+    local: String,
+
+    /// The peer, e.g. `192.168.1.10:20000`.
+    #[arg(short = 'r', long)]
+    remote: String,
+
+    /// Seconds between traffic reports, 0 for none.
+    #[arg(short = 's', long, default_value_t = 10)]
+    stats_interval: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    #[cfg(target_os = "linux")]
+    {
+        let bus: Arc<dyn CanTransport> = if args.slcan {
+            Arc::new(SlcanTransport::open(&args.interface)?)
+        } else {
+            Arc::new(SocketCanTransport::open(&args.interface)?)
+        };
+        let udp = Arc::new(CannelloniTransport::open(&format!("{},{}", args.local, args.remote))?);
+        println!("Bridging {} to {} from {}", bus.channel(), udp.remote_addr(), udp.local_addr()?);
+
+        tokio::select! {
+            result = bus_to_udp(bus.clone(), udp.clone()) => result?,
+            result = udp_to_bus(udp.clone(), bus.clone()) => result?,
+            _ = report(udp.clone(), args.stats_interval) => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+        println!("{:?}", udp.stats());
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = args;
+        println!("This tool is only supported on Linux platforms with socketcan.");
+    }
+
+    Ok(())
+}
+
+/// Forwards frames and error frames from the bus, reopening it if it fails.
+#[cfg(target_os = "linux")]
+async fn bus_to_udp(bus: Arc<dyn CanTransport>, udp: Arc<CannelloniTransport>) -> Result<()> {
+    loop {
+        match bus.recv(RECV_TIMEOUT).await {
+            Ok(Some(event)) => {
+                if let Err(e) = udp.send_event(&event).await {
+                    eprintln!("{}", e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) if bus.can_reopen() => {
+                eprintln!("{}, reopening {}", e, bus.channel());
+                while let Err(e) = bus.reopen().await {
+                    log::debug!("Reopening {} failed: {}", bus.channel(), e);
+                    time::sleep(REOPEN_INTERVAL).await;
+                }
+                println!("Reopened {}", bus.channel());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Puts the peer's frames on the bus. Its error frames describe its bus, not ours, and are dropped.
+#[cfg(target_os = "linux")]
+async fn udp_to_bus(udp: Arc<CannelloniTransport>, bus: Arc<dyn CanTransport>) -> Result<()> {
+    loop {
+        if let Some(TransportEvent::Frame(raw)) = udp.recv(RECV_TIMEOUT).await? {
+            if let Err(e) = bus.send(&raw).await {
+                eprintln!("{}", e);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn report(udp: Arc<CannelloniTransport>, interval: u64) {
+    if interval == 0 {
+        return std::future::pending().await;
+    }
+    let mut ticker = time::interval(Duration::from_secs(interval));
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let stats = udp.stats();
+        println!(
+            "sent {} frames, received {} frames, lost {} packets, {} reordered, {} malformed",
+            stats.frames_sent, stats.frames_received, stats.packets_lost, stats.packets_reordered, stats.malformed
+        );
+    }
+}
@@ -0,0 +1,39 @@
+extern crate havendrive;
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use havendrive::drivers::can::dbc_export::{builtin_dbc, DbcNodes};
+
+#[derive(Parser, Debug)]
+#[command(about = "Write a DBC file describing the built-in messages for the given node ids")]
+struct Args {
+    /// ODrive node ids, e.g. `--odrive 0,1`
+    #[arg(long, value_delimiter = ',')]
+    odrive: Vec<u32>,
+
+    /// MyActuator V3 motor ids
+    #[arg(long, value_delimiter = ',')]
+    myactuator: Vec<u32>,
+
+    /// X4-24 motor ids
+    #[arg(long, value_delimiter = ',')]
+    x424: Vec<u32>,
+
+    /// Where to write the DBC. Prints it if not given.
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let dbc = builtin_dbc(&DbcNodes { odrive: args.odrive, myactuator: args.myactuator, x424: args.x424 })?;
+    match args.output {
+        Some(path) => fs::write(&path, dbc.to_string()).with_context(|| format!("Failed to write {}", path.display()))?,
+        None => print!("{}", dbc),
+    }
+    Ok(())
+}
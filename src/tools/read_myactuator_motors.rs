@@ -6,23 +6,28 @@ use anyhow::Result;
 use clap::Parser;
 use tokio::time::{sleep, Duration};
 
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::connection::{CanSimple, MessageCallback};
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::enums::{BusType, CanInterface};
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::myactuator_v3_msgs::{
+    MotorShutdownCommand, PositionControlCommand, MyactuatorReadMotorStatus1Message, ReadMultiTurnAngleMessage,
+    SpeedControlCommand, SystemBrakeReleaseCommand,
+};
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::myactuator_x424_msgs::{
+    QAReturnMessageType1, QAReturnMessageType2, QAReturnMessageType3, QAReturnMessageType4,
+    QueryCANCommunicationIDMessage, SetCommunicationModeMessage, X424ServoPositionControlMessage,
+    X424ServoSpeedControlMessage,
+};
+#[cfg(target_os = "linux")]
+use havendrive::drivers::can::messages::CanMessageTrait;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     #[cfg(target_os = "linux")]
     {
-        use havendrive::drivers::can::connection::{CanSimple, DynamicCanListener};
-        use havendrive::drivers::can::enums::{BusType, CanInterface};
-        use havendrive::drivers::can::myactuator_v3_msgs::{
-            MotorShutdownCommand, PositionControlCommand, MyactuatorReadMotorStatus1Message, ReadMultiTurnAngleMessage,
-            SpeedControlCommand, SystemBrakeReleaseCommand,
-        };
-        use havendrive::drivers::can::myactuator_x424_msgs::{
-            QAReturnMessageType1, QAReturnMessageType2, QAReturnMessageType3, QAReturnMessageType4,
-            QueryCANCommunicationIDMessage, SetCommunicationModeMessage, X424ServoPositionControlMessage,
-            X424ServoSpeedControlMessage,
-        };
-        use havendrive::drivers::can::messages::CanMessageTrait;
-
         #[derive(Parser, Debug)]
         #[command(about = "Test MyActuator motors via CAN")]
         struct Args {
@@ -31,15 +36,18 @@ async fn main() -> Result<()> {
 
             #[arg(short = 'a', long)]
             test: bool,
+
+            #[arg(short = 'i', long, default_value = "can0")]
+            interface: String,
         }
 
         let args = Args::parse();
         if args.discover || args.test {
-            let discovered = discover_motors().await?;
+            let discovered = discover_motors(&args.interface).await?;
 
             if args.test {
                 for (id, motor_type) in discovered {
-                    test_motor(&motor_type, id).await?;
+                    test_motor(&args.interface, &motor_type, id).await?;
                 }
             }
         }
@@ -54,13 +62,13 @@ async fn main() -> Result<()> {
 }
 
 #[cfg(target_os = "linux")]
-async fn discover_motors() -> Result<HashMap<u32, String>> {
+async fn discover_motors(interface: &str) -> Result<HashMap<u32, String>> {
     let discovered = Arc::new(Mutex::new(HashMap::new()));
 
-    let can_bus = CanSimple::new(CanInterface::Myactuator, BusType::SocketCan);
+    let can_bus = CanSimple::new(CanInterface::from(interface), BusType::SocketCan)?;
 
     let discovered_v3 = discovered.clone();
-    let callback_v3 = Box::new(move |m: MyactuatorReadMotorStatus1Message| {
+    let callback_v3: MessageCallback<MyactuatorReadMotorStatus1Message> = Box::new(move |m| {
         let discovered = discovered_v3.clone();
         Box::pin(async move {
             discovered.lock().unwrap().insert(m.node_id(), "Controller V3".to_string());
@@ -69,7 +77,7 @@ async fn discover_motors() -> Result<HashMap<u32, String>> {
     });
 
     let discovered_x4 = discovered.clone();
-    let callback_x4 = Box::new(move |m: QueryCANCommunicationIDMessage| {
+    let callback_x4: MessageCallback<QueryCANCommunicationIDMessage> = Box::new(move |m| {
         let discovered = discovered_x4.clone();
         Box::pin(async move {
             discovered.lock().unwrap().insert(m.node_id(), "X4-24".to_string());
@@ -84,7 +92,7 @@ async fn discover_motors() -> Result<HashMap<u32, String>> {
         (std::marker::PhantomData, callback_x4),
     ]);
 
-    println!("Scanning CAN interface can0 for motors...");
+    println!("Scanning CAN interface {} for motors...", interface);
 
     let listen_task = tokio::spawn(can_bus.listen());
 
@@ -108,25 +116,25 @@ async fn discover_motors() -> Result<HashMap<u32, String>> {
 }
 
 #[cfg(target_os = "linux")]
-async fn test_motor(motor_type: &str, node_id: u32) -> Result<()> {
+async fn test_motor(interface: &str, motor_type: &str, node_id: u32) -> Result<()> {
     if motor_type == "X4-24" {
-        test_x4_motor(node_id).await
+        test_x4_motor(interface, node_id).await
     } else {
-        test_controller_v3_motor(node_id).await
+        test_controller_v3_motor(interface, node_id).await
     }
 }
 
 #[cfg(target_os = "linux")]
-async fn test_x4_motor(node_id: u32) -> Result<()> {
-    let can_bus = CanSimple::new(CanInterface::Myactuator, BusType::SocketCan);
+async fn test_x4_motor(interface: &str, node_id: u32) -> Result<()> {
+    let can_bus = CanSimple::new(CanInterface::from(interface), BusType::SocketCan)?;
 
-    println!("Connected to CAN interface: can0");
+    println!("Connected to CAN interface: {}", interface);
     println!("Testing X4-24 motor with ID: {}", node_id);
 
-    let callback1 = Box::new(move |m: QAReturnMessageType1| Box::pin(async move { println!("{:?}", m); }));
-    let callback2 = Box::new(move |m: QAReturnMessageType2| Box::pin(async move { println!("{:?}", m); }));
-    let callback3 = Box::new(move |m: QAReturnMessageType3| Box::pin(async move { println!("{:?}", m); }));
-    let callback4 = Box::new(move |m: QAReturnMessageType4| Box::pin(async move { println!("{:?}", m); }));
+    let callback1: MessageCallback<QAReturnMessageType1> = Box::new(move |m| Box::pin(async move { println!("{:?}", m); }));
+    let callback2: MessageCallback<QAReturnMessageType2> = Box::new(move |m| Box::pin(async move { println!("{:?}", m); }));
+    let callback3: MessageCallback<QAReturnMessageType3> = Box::new(move |m| Box::pin(async move { println!("{:?}", m); }));
+    let callback4: MessageCallback<QAReturnMessageType4> = Box::new(move |m| Box::pin(async move { println!("{:?}", m); }));
 
     can_bus.register_callbacks::<QAReturnMessageType1>(vec![(std::marker::PhantomData, callback1)]);
     can_bus.register_callbacks::<QAReturnMessageType2>(vec![(std::marker::PhantomData, callback2)]);
@@ -171,22 +179,17 @@ async fn test_x4_motor(node_id: u32) -> Result<()> {
 }
 
 #[cfg(target_os = "linux")]
-async fn test_controller_v3_motor(node_id: u32) -> Result<()> {
-    let can_bus = CanSimple::new(CanInterface::Myactuator, BusType::SocketCan);
+async fn test_controller_v3_motor(interface: &str, node_id: u32) -> Result<()> {
+    let can_bus = CanSimple::new(CanInterface::from(interface), BusType::SocketCan)?;
 
-    println!("Connected to CAN interface: can0");
+    println!("Connected to CAN interface: {}", interface);
     println!("Testing Controller V3 motor with ID: {}", node_id);
 
-    let callback_status = Box::new(move |m: MyactuatorReadMotorStatus1Message| Box::pin(async move {
+    let callback_status: MessageCallback<MyactuatorReadMotorStatus1Message> = Box::new(move |m| Box::pin(async move {
         println!("Status: Temp={}°C, Voltage={:.1}V, Error=0x{:04x}", m.temperature, m.voltage, m.error_state);
     }));
 
-    let callback_angle = Box::new(move |m: ReadMultiTurnAngleMessage| Box::pin(async move {
-        println!("Angle: {:.2}°", m.angle);
-    }));
-
     can_bus.register_callbacks::<MyactuatorReadMotorStatus1Message>(vec![(std::marker::PhantomData, callback_status)]);
-    can_bus.register_callbacks::<ReadMultiTurnAngleMessage>(vec![(std::marker::PhantomData, callback_angle)]);
 
     let listen_task = tokio::spawn(can_bus.listen());
 
@@ -200,8 +203,10 @@ async fn test_controller_v3_motor(node_id: u32) -> Result<()> {
     can_bus.send(PositionControlCommand::new(node_id, 90.0, 500)).await?;
     sleep(Duration::from_secs_f32(3.5)).await;
 
-    can_bus.send(ReadMultiTurnAngleMessage::new(node_id)).await?;
-    sleep(Duration::from_secs_f32(0.1)).await;
+    let angle: ReadMultiTurnAngleMessage = can_bus
+        .request_with_retries(ReadMultiTurnAngleMessage::new(node_id), Duration::from_millis(100), 2)
+        .await?;
+    println!("Angle: {:.2}°", angle.angle);
 
     can_bus.send(PositionControlCommand::new(node_id, 0.0, 500)).await?;
     sleep(Duration::from_secs_f32(3.5)).await;
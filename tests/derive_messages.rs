@@ -0,0 +1,107 @@
+//! Messages built with `#[derive(CanMessage)]` put the same bytes on the wire as
+//! the hand-written implementations they replaced.
+use havendrive::drivers::can::enums::{AxisState, ControlMode, InputMode, ProcedureResult};
+use havendrive::drivers::can::messages::{CanMessage, CanMessageTrait, RawCanMessage};
+use havendrive::drivers::can::myactuator_v3_msgs::{MyactuatorReadMotorStatus1Message, PositionControlCommand, TorqueControlCommand};
+use havendrive::drivers::can::myactuator_x424_msgs::QAReturnMessageType1;
+use havendrive::drivers::can::odrive_msgs::{HeartbeatMessage, OdriveCanMessage, SetControllerMode, SetPositionMessage};
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-5, "{} is not {}", actual, expected);
+}
+
+#[test]
+fn little_endian_fields() {
+    let raw = SetPositionMessage::new(3, 1.5, -2, 300).as_can_message();
+    assert_eq!(raw.arbitration_id, 3 << 5 | 0x0C);
+    assert_eq!(raw.data, [0x00, 0x00, 0xC0, 0x3F, 0xFE, 0xFF, 0x2C, 0x01]);
+    let decoded = SetPositionMessage::try_from_can_message(raw).unwrap();
+    assert_eq!((decoded.node_id(), decoded.input_position, decoded.velocity_ff, decoded.torque_ff), (3, 1.5, -2, 300));
+}
+
+#[test]
+fn enums_and_bools() {
+    let raw = SetControllerMode::new(1, ControlMode::PositionControl, InputMode::PosFilter).as_can_message();
+    assert_eq!(raw.data, [3, 0, 0, 0, 3, 0, 0, 0]);
+
+    // A heartbeat as the ODrive sends it: error, state, procedure result, trajectory done.
+    let data = vec![0x02, 0x00, 0x00, 0x01, 8, 0, 1, 0];
+    let heartbeat = HeartbeatMessage::try_from_can_message(RawCanMessage::new(2 << 5 | 0x01, data.clone())).unwrap();
+    assert_eq!(heartbeat.axis_error, 0x0100_0002);
+    assert_eq!(heartbeat.axis_state, AxisState::ClosedLoopControl);
+    assert_eq!(heartbeat.procedure_result, ProcedureResult::Success);
+    assert!(heartbeat.trajectory_done);
+    assert_eq!(heartbeat.as_can_message().data, data);
+
+    let mut unknown = data;
+    unknown[4] = 0xEE;
+    assert!(HeartbeatMessage::try_from_can_message(RawCanMessage::new(2 << 5 | 0x01, unknown)).is_err());
+}
+
+#[test]
+fn scaled_fields() {
+    assert_eq!(TorqueControlCommand::new(1, -1.25).as_can_message().data, [0xA1, 0, 0, 0, 0x83, 0xFF, 0, 0]);
+    assert_eq!(PositionControlCommand::new(1, 90.5, 500).as_can_message().data, [0xA4, 0, 0xF4, 0x01, 0x5A, 0x23, 0, 0]);
+
+    // A status reply from node 1: -25 degC, brake released, 24.0 V, error 4.
+    let data = vec![0x9A, 0xE7, 0, 1, 0xF0, 0x00, 0x04, 0x00];
+    let status = MyactuatorReadMotorStatus1Message::try_from_can_message(RawCanMessage::new(0x241, data.clone())).unwrap();
+    assert_eq!((status.node_id(), status.temperature, status.brake_released, status.error_state), (1, -25, true, 4));
+    assert_close(status.voltage, 24.0);
+    assert_eq!(status.as_can_message().data, data);
+}
+
+#[test]
+fn scaled_fields_round_and_saturate() {
+    let torque = |current| TorqueControlCommand::new(1, current).as_can_message().data[4..6].to_vec();
+    assert_eq!(torque(0.126), 13i16.to_le_bytes());
+    assert_eq!(torque(-0.126), (-13i16).to_le_bytes());
+    assert_eq!(torque(1000.0), i16::MAX.to_le_bytes());
+    assert_eq!(torque(-1000.0), i16::MIN.to_le_bytes());
+}
+
+#[test]
+fn big_endian_bit_fields() {
+    // Type 1, position raw 0x8000, speed and current raw 0x800, 20 and 25 degC.
+    let data = vec![0x20, 0x80, 0x00, 0x80, 0x08, 0x00, 0x5A, 0x64];
+    let qa = QAReturnMessageType1::try_from_can_message(RawCanMessage::new(0x7, data.clone())).unwrap();
+    assert_close(qa.position, 0.0);
+    assert_close(qa.speed, 2048.0 / 4095.0 * 36.0 - 18.0);
+    assert_close(qa.current, 2048.0 / 4095.0 * 60.0 - 30.0);
+    assert_close(qa.motor_temp, 20.0);
+    assert_close(qa.mos_temp, 25.0);
+    assert_eq!(qa.as_can_message().data, data);
+
+    // Values out of range are held to the width of their bits.
+    let mut qa = QAReturnMessageType1::new(7);
+    qa.position = -100.0;
+    qa.speed = 100.0;
+    qa.motor_temp = 1000.0;
+    qa.mos_temp = -25.0;
+    assert_eq!(qa.as_can_message().data, [0x20, 0x00, 0x00, 0xFF, 0xF8, 0x00, 0xFF, 0x00]);
+}
+
+#[derive(Debug, Clone, CanMessage)]
+#[can(protocol = "odrive", cmd_id = 0x1E, len = 8)]
+struct Probe {
+    base: OdriveCanMessage,
+    #[can(offset = 0, endian = "big")]
+    counter: u16,
+    #[can(offset = 2)]
+    level: i16,
+    #[can(skip)]
+    label: String,
+}
+
+#[test]
+fn skipped_fields_and_length() {
+    let probe = Probe { base: OdriveCanMessage::new(4, Probe::cmd_id()), counter: 0x1234, level: -2, label: "not sent".to_string() };
+    let raw = probe.as_can_message();
+    assert_eq!(raw.data, [0x12, 0x34, 0xFE, 0xFF, 0, 0, 0, 0]);
+    let decoded = Probe::try_from_can_message(raw).unwrap();
+    assert_eq!((decoded.counter, decoded.level, decoded.label.as_str()), (0x1234, -2, ""));
+
+    // Only the fields have to be there.
+    assert!(Probe::try_from_can_message(RawCanMessage::new(4 << 5 | 0x1E, vec![0x12, 0x34, 0xFE, 0xFF])).is_ok());
+    assert!(Probe::try_from_can_message(RawCanMessage::new(4 << 5 | 0x1E, vec![0x12, 0x34, 0xFE])).is_err());
+}
@@ -0,0 +1,419 @@
+// Generated from a DBC file by havendrive-dbc. Do not edit.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+pub enum Axis0HeartbeatAxisState {
+    Undefined = 0,
+    Idle = 1,
+    ClosedLoopControl = 8,
+}
+
+impl TryFrom<i64> for Axis0HeartbeatAxisState {
+    type Error = ::havendrive::drivers::can::messages::DecodeError;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Axis0HeartbeatAxisState::Undefined),
+            1 => Ok(Axis0HeartbeatAxisState::Idle),
+            8 => Ok(Axis0HeartbeatAxisState::ClosedLoopControl),
+            _ => Err(::havendrive::drivers::can::messages::DecodeError::UnknownEnumValue { name: "Axis0HeartbeatAxisState", value: value as u32 }),
+        }
+    }
+}
+
+/// Sent by each axis at a fixed rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis0Heartbeat {
+    pub node_id: u32,
+    pub timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>,
+    pub axis_error: u32,
+    pub axis_state: Axis0HeartbeatAxisState,
+    pub procedure_result: u8,
+    pub trajectory_done_flag: bool,
+}
+
+impl Axis0Heartbeat {
+    pub const DLC: usize = 8;
+
+    pub fn new(node_id: u32) -> Self {
+        Self { node_id, timestamp: None, axis_error: 0, axis_state: Axis0HeartbeatAxisState::Undefined, procedure_result: 0, trajectory_done_flag: false }
+    }
+}
+
+impl ::havendrive::drivers::can::messages::CanMessageTrait for Axis0Heartbeat {
+    fn cmd_id() -> u32 { 0x1 }
+
+    fn node_id(&self) -> u32 { self.node_id }
+
+    fn timestamp(&self) -> Option<::havendrive::drivers::can::messages::RxTimestamp> { self.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>) { self.timestamp = timestamp; }
+
+    fn matches(msg: &::havendrive::drivers::can::messages::RawCanMessage) -> bool { msg.arbitration_id & 0x1F == 0x1 && !msg.is_extended_id }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<::havendrive::drivers::can::messages::CanIdFilter> {
+        match node_id {
+            Some(node_id) => vec![::havendrive::drivers::can::messages::CanIdFilter::exact((node_id << 5) | 0x1)],
+            None => vec![::havendrive::drivers::can::messages::CanIdFilter::new(0x1, 0x1F)],
+        }
+    }
+
+    fn try_from_can_message(msg: ::havendrive::drivers::can::messages::RawCanMessage) -> Result<Self, ::havendrive::drivers::can::messages::DecodeError> {
+        if !Self::matches(&msg) {
+            return Err(::havendrive::drivers::can::messages::DecodeError::WrongId { arbitration_id: msg.arbitration_id });
+        }
+        let mut s = Self::new(msg.arbitration_id >> 5);
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
+    }
+
+    fn as_can_message(&self) -> ::havendrive::drivers::can::messages::RawCanMessage {
+        ::havendrive::drivers::can::messages::RawCanMessage {
+            arbitration_id: ::havendrive::drivers::can::messages::CanMessageTrait::gen_arbitration_id(self).value(),
+            data: ::havendrive::drivers::can::messages::CanMessageTrait::gen_can_msg_data(self),
+            is_extended_id: false,
+            is_fd: false,
+            ..Default::default()
+        }
+    }
+
+    fn gen_arbitration_id(&self) -> ::havendrive::drivers::can::messages::ArbitrationId { ::havendrive::drivers::can::messages::ArbitrationId::Raw((self.node_id << 5) | 0x1) }
+
+    fn gen_can_msg_data(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::DLC];
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 0, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.axis_error as u64);
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 32, 8, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.axis_state as i64 as u64);
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 40, 8, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.procedure_result as u64);
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 48, 1, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.trajectory_done_flag as u64);
+        data
+    }
+
+    fn parse_can_msg_data(&mut self, msg: &::havendrive::drivers::can::messages::RawCanMessage) -> Result<(), ::havendrive::drivers::can::messages::DecodeError> {
+        msg.require_len(Self::DLC)?;
+        self.axis_error = ::havendrive::drivers::can::dbc::signal::extract(&msg.data, 0, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) as u32;
+        self.axis_state = Axis0HeartbeatAxisState::try_from(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 32, 8, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) as i64)?;
+        self.procedure_result = ::havendrive::drivers::can::dbc::signal::extract(&msg.data, 40, 8, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) as u8;
+        self.trajectory_done_flag = ::havendrive::drivers::can::dbc::signal::extract(&msg.data, 48, 1, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) != 0;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+pub enum Axis0SetAxisStateAxisRequestedState {
+    Undefined = 0,
+    Idle = 1,
+    ClosedLoopControl = 8,
+}
+
+impl TryFrom<i64> for Axis0SetAxisStateAxisRequestedState {
+    type Error = ::havendrive::drivers::can::messages::DecodeError;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Axis0SetAxisStateAxisRequestedState::Undefined),
+            1 => Ok(Axis0SetAxisStateAxisRequestedState::Idle),
+            8 => Ok(Axis0SetAxisStateAxisRequestedState::ClosedLoopControl),
+            _ => Err(::havendrive::drivers::can::messages::DecodeError::UnknownEnumValue { name: "Axis0SetAxisStateAxisRequestedState", value: value as u32 }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis0SetAxisState {
+    pub node_id: u32,
+    pub timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>,
+    pub axis_requested_state: Axis0SetAxisStateAxisRequestedState,
+}
+
+impl Axis0SetAxisState {
+    pub const DLC: usize = 4;
+
+    pub fn new(node_id: u32) -> Self {
+        Self { node_id, timestamp: None, axis_requested_state: Axis0SetAxisStateAxisRequestedState::Undefined }
+    }
+}
+
+impl ::havendrive::drivers::can::messages::CanMessageTrait for Axis0SetAxisState {
+    fn cmd_id() -> u32 { 0x7 }
+
+    fn node_id(&self) -> u32 { self.node_id }
+
+    fn timestamp(&self) -> Option<::havendrive::drivers::can::messages::RxTimestamp> { self.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>) { self.timestamp = timestamp; }
+
+    fn matches(msg: &::havendrive::drivers::can::messages::RawCanMessage) -> bool { msg.arbitration_id & 0x1F == 0x7 && !msg.is_extended_id }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<::havendrive::drivers::can::messages::CanIdFilter> {
+        match node_id {
+            Some(node_id) => vec![::havendrive::drivers::can::messages::CanIdFilter::exact((node_id << 5) | 0x7)],
+            None => vec![::havendrive::drivers::can::messages::CanIdFilter::new(0x7, 0x1F)],
+        }
+    }
+
+    fn try_from_can_message(msg: ::havendrive::drivers::can::messages::RawCanMessage) -> Result<Self, ::havendrive::drivers::can::messages::DecodeError> {
+        if !Self::matches(&msg) {
+            return Err(::havendrive::drivers::can::messages::DecodeError::WrongId { arbitration_id: msg.arbitration_id });
+        }
+        let mut s = Self::new(msg.arbitration_id >> 5);
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
+    }
+
+    fn as_can_message(&self) -> ::havendrive::drivers::can::messages::RawCanMessage {
+        ::havendrive::drivers::can::messages::RawCanMessage {
+            arbitration_id: ::havendrive::drivers::can::messages::CanMessageTrait::gen_arbitration_id(self).value(),
+            data: ::havendrive::drivers::can::messages::CanMessageTrait::gen_can_msg_data(self),
+            is_extended_id: false,
+            is_fd: false,
+            ..Default::default()
+        }
+    }
+
+    fn gen_arbitration_id(&self) -> ::havendrive::drivers::can::messages::ArbitrationId { ::havendrive::drivers::can::messages::ArbitrationId::Raw((self.node_id << 5) | 0x7) }
+
+    fn gen_can_msg_data(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::DLC];
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 0, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.axis_requested_state as i64 as u64);
+        data
+    }
+
+    fn parse_can_msg_data(&mut self, msg: &::havendrive::drivers::can::messages::RawCanMessage) -> Result<(), ::havendrive::drivers::can::messages::DecodeError> {
+        msg.require_len(Self::DLC)?;
+        self.axis_requested_state = Axis0SetAxisStateAxisRequestedState::try_from(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 0, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) as i64)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis0GetEncoderEstimates {
+    pub node_id: u32,
+    pub timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>,
+    /// Unit: rev.
+    pub pos_estimate: f32,
+    /// Unit: rev/s.
+    pub vel_estimate: f32,
+}
+
+impl Axis0GetEncoderEstimates {
+    pub const DLC: usize = 8;
+
+    pub fn new(node_id: u32) -> Self {
+        Self { node_id, timestamp: None, pos_estimate: 0.0, vel_estimate: 0.0 }
+    }
+}
+
+impl ::havendrive::drivers::can::messages::CanMessageTrait for Axis0GetEncoderEstimates {
+    fn cmd_id() -> u32 { 0x9 }
+
+    fn node_id(&self) -> u32 { self.node_id }
+
+    fn timestamp(&self) -> Option<::havendrive::drivers::can::messages::RxTimestamp> { self.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>) { self.timestamp = timestamp; }
+
+    fn matches(msg: &::havendrive::drivers::can::messages::RawCanMessage) -> bool { msg.arbitration_id & 0x1F == 0x9 && !msg.is_extended_id }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<::havendrive::drivers::can::messages::CanIdFilter> {
+        match node_id {
+            Some(node_id) => vec![::havendrive::drivers::can::messages::CanIdFilter::exact((node_id << 5) | 0x9)],
+            None => vec![::havendrive::drivers::can::messages::CanIdFilter::new(0x9, 0x1F)],
+        }
+    }
+
+    fn try_from_can_message(msg: ::havendrive::drivers::can::messages::RawCanMessage) -> Result<Self, ::havendrive::drivers::can::messages::DecodeError> {
+        if !Self::matches(&msg) {
+            return Err(::havendrive::drivers::can::messages::DecodeError::WrongId { arbitration_id: msg.arbitration_id });
+        }
+        let mut s = Self::new(msg.arbitration_id >> 5);
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
+    }
+
+    fn as_can_message(&self) -> ::havendrive::drivers::can::messages::RawCanMessage {
+        ::havendrive::drivers::can::messages::RawCanMessage {
+            arbitration_id: ::havendrive::drivers::can::messages::CanMessageTrait::gen_arbitration_id(self).value(),
+            data: ::havendrive::drivers::can::messages::CanMessageTrait::gen_can_msg_data(self),
+            is_extended_id: false,
+            is_fd: false,
+            ..Default::default()
+        }
+    }
+
+    fn gen_arbitration_id(&self) -> ::havendrive::drivers::can::messages::ArbitrationId { ::havendrive::drivers::can::messages::ArbitrationId::Raw((self.node_id << 5) | 0x9) }
+
+    fn gen_can_msg_data(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::DLC];
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 0, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.pos_estimate.to_bits() as u64);
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 32, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.vel_estimate.to_bits() as u64);
+        data
+    }
+
+    fn parse_can_msg_data(&mut self, msg: &::havendrive::drivers::can::messages::RawCanMessage) -> Result<(), ::havendrive::drivers::can::messages::DecodeError> {
+        msg.require_len(Self::DLC)?;
+        self.pos_estimate = f32::from_bits(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 0, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) as u32);
+        self.vel_estimate = f32::from_bits(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 32, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) as u32);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis0SetLimits {
+    pub node_id: u32,
+    pub timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>,
+    /// Unit: rev/s.
+    pub velocity_limit: f32,
+    /// Unit: A.
+    pub current_limit: f32,
+}
+
+impl Axis0SetLimits {
+    pub const DLC: usize = 8;
+
+    pub fn new(node_id: u32) -> Self {
+        Self { node_id, timestamp: None, velocity_limit: 0.0, current_limit: 0.0 }
+    }
+}
+
+impl ::havendrive::drivers::can::messages::CanMessageTrait for Axis0SetLimits {
+    fn cmd_id() -> u32 { 0xF }
+
+    fn node_id(&self) -> u32 { self.node_id }
+
+    fn timestamp(&self) -> Option<::havendrive::drivers::can::messages::RxTimestamp> { self.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>) { self.timestamp = timestamp; }
+
+    fn matches(msg: &::havendrive::drivers::can::messages::RawCanMessage) -> bool { msg.arbitration_id & 0x1F == 0xF && !msg.is_extended_id }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<::havendrive::drivers::can::messages::CanIdFilter> {
+        match node_id {
+            Some(node_id) => vec![::havendrive::drivers::can::messages::CanIdFilter::exact((node_id << 5) | 0xF)],
+            None => vec![::havendrive::drivers::can::messages::CanIdFilter::new(0xF, 0x1F)],
+        }
+    }
+
+    fn try_from_can_message(msg: ::havendrive::drivers::can::messages::RawCanMessage) -> Result<Self, ::havendrive::drivers::can::messages::DecodeError> {
+        if !Self::matches(&msg) {
+            return Err(::havendrive::drivers::can::messages::DecodeError::WrongId { arbitration_id: msg.arbitration_id });
+        }
+        let mut s = Self::new(msg.arbitration_id >> 5);
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
+    }
+
+    fn as_can_message(&self) -> ::havendrive::drivers::can::messages::RawCanMessage {
+        ::havendrive::drivers::can::messages::RawCanMessage {
+            arbitration_id: ::havendrive::drivers::can::messages::CanMessageTrait::gen_arbitration_id(self).value(),
+            data: ::havendrive::drivers::can::messages::CanMessageTrait::gen_can_msg_data(self),
+            is_extended_id: false,
+            is_fd: false,
+            ..Default::default()
+        }
+    }
+
+    fn gen_arbitration_id(&self) -> ::havendrive::drivers::can::messages::ArbitrationId { ::havendrive::drivers::can::messages::ArbitrationId::Raw((self.node_id << 5) | 0xF) }
+
+    fn gen_can_msg_data(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::DLC];
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 0, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.velocity_limit.to_bits() as u64);
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 32, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian, self.current_limit.to_bits() as u64);
+        data
+    }
+
+    fn parse_can_msg_data(&mut self, msg: &::havendrive::drivers::can::messages::RawCanMessage) -> Result<(), ::havendrive::drivers::can::messages::DecodeError> {
+        msg.require_len(Self::DLC)?;
+        self.velocity_limit = f32::from_bits(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 0, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) as u32);
+        self.current_limit = f32::from_bits(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 32, 32, ::havendrive::drivers::can::dbc::ByteOrder::LittleEndian) as u32);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis0Debug {
+    pub node_id: u32,
+    pub timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>,
+    /// Selects which debug values follow.
+    pub mode: u8,
+    /// Unit: degC. Range: -40 to 100. Present when the multiplexor is 0.
+    pub temperature: Option<f64>,
+    /// Unit: V. Present when the multiplexor is 0.
+    pub bus_voltage: Option<f64>,
+    /// Present when the multiplexor is 1.
+    pub counter: Option<u16>,
+}
+
+impl Axis0Debug {
+    pub const DLC: usize = 8;
+
+    pub fn new(node_id: u32) -> Self {
+        Self { node_id, timestamp: None, mode: 0, temperature: None, bus_voltage: None, counter: None }
+    }
+}
+
+impl ::havendrive::drivers::can::messages::CanMessageTrait for Axis0Debug {
+    fn cmd_id() -> u32 { 0x11 }
+
+    fn node_id(&self) -> u32 { self.node_id }
+
+    fn timestamp(&self) -> Option<::havendrive::drivers::can::messages::RxTimestamp> { self.timestamp }
+
+    fn set_timestamp(&mut self, timestamp: Option<::havendrive::drivers::can::messages::RxTimestamp>) { self.timestamp = timestamp; }
+
+    fn matches(msg: &::havendrive::drivers::can::messages::RawCanMessage) -> bool { msg.arbitration_id & 0x1F == 0x11 && !msg.is_extended_id }
+
+    fn can_filters(node_id: Option<u32>) -> Vec<::havendrive::drivers::can::messages::CanIdFilter> {
+        match node_id {
+            Some(node_id) => vec![::havendrive::drivers::can::messages::CanIdFilter::exact((node_id << 5) | 0x11)],
+            None => vec![::havendrive::drivers::can::messages::CanIdFilter::new(0x11, 0x1F)],
+        }
+    }
+
+    fn try_from_can_message(msg: ::havendrive::drivers::can::messages::RawCanMessage) -> Result<Self, ::havendrive::drivers::can::messages::DecodeError> {
+        if !Self::matches(&msg) {
+            return Err(::havendrive::drivers::can::messages::DecodeError::WrongId { arbitration_id: msg.arbitration_id });
+        }
+        let mut s = Self::new(msg.arbitration_id >> 5);
+        s.parse_can_msg_data(&msg)?;
+        Ok(s)
+    }
+
+    fn as_can_message(&self) -> ::havendrive::drivers::can::messages::RawCanMessage {
+        ::havendrive::drivers::can::messages::RawCanMessage {
+            arbitration_id: ::havendrive::drivers::can::messages::CanMessageTrait::gen_arbitration_id(self).value(),
+            data: ::havendrive::drivers::can::messages::CanMessageTrait::gen_can_msg_data(self),
+            is_extended_id: false,
+            is_fd: false,
+            ..Default::default()
+        }
+    }
+
+    fn gen_arbitration_id(&self) -> ::havendrive::drivers::can::messages::ArbitrationId { ::havendrive::drivers::can::messages::ArbitrationId::Raw((self.node_id << 5) | 0x11) }
+
+    fn gen_can_msg_data(&self) -> Vec<u8> {
+        let mut data = vec![0u8; Self::DLC];
+        ::havendrive::drivers::can::dbc::signal::insert(&mut data, 7, 4, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian, self.mode as u64);
+        let mux = (self.mode as u64) & 0xF;
+        if let (Some(value), 0) = (self.temperature, mux) {
+            ::havendrive::drivers::can::dbc::signal::insert(&mut data, 3, 12, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian, ((value.clamp(-40.0f64, 100.0f64) + 40.0f64) / 0.5f64).round() as i64 as u64);
+        }
+        if let (Some(value), 0) = (self.bus_voltage, mux) {
+            ::havendrive::drivers::can::dbc::signal::insert(&mut data, 23, 16, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian, (value / 0.25f64).round() as u64);
+        }
+        if let (Some(value), 1) = (self.counter, mux) {
+            ::havendrive::drivers::can::dbc::signal::insert(&mut data, 15, 16, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian, value as u64);
+        }
+        data
+    }
+
+    fn parse_can_msg_data(&mut self, msg: &::havendrive::drivers::can::messages::RawCanMessage) -> Result<(), ::havendrive::drivers::can::messages::DecodeError> {
+        msg.require_len(Self::DLC)?;
+        let mux = ::havendrive::drivers::can::dbc::signal::extract(&msg.data, 7, 4, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian);
+        self.mode = ::havendrive::drivers::can::dbc::signal::extract(&msg.data, 7, 4, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian) as u8;
+        self.temperature = if mux == 0 { Some(::havendrive::drivers::can::dbc::signal::sign_extend(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 3, 12, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian), 12) as f64 * 0.5f64 - 40.0f64) } else { None };
+        self.bus_voltage = if mux == 0 { Some(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 23, 16, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian) as f64 * 0.25f64) } else { None };
+        self.counter = if mux == 1 { Some(::havendrive::drivers::can::dbc::signal::extract(&msg.data, 15, 16, ::havendrive::drivers::can::dbc::ByteOrder::BigEndian) as u16) } else { None };
+        Ok(())
+    }
+}